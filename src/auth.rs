@@ -0,0 +1,186 @@
+//! Authentication of requests, using bearer tokens.
+//!
+//! A [`TokenManager`] caches a token, fetched from a [`TokenSource`], and adds it to all
+//! requests of a connection, in the `Authorization` header. The token is refreshed before it
+//! expires, tracked using a [`Clock`](crate::Clock), and when the server rejects it with
+//! `401 Unauthorized`.
+//!
+//! ~~~
+//! use core::cell::Cell;
+//! use core::fmt::Write;
+//! use heapless::{consts::*, Vec};
+//!
+//! use drogue_http_client::auth::TokenManager;
+//! use drogue_http_client::*;
+//!
+//! struct Ticks(Cell<u64>);
+//!
+//! impl Clock for Ticks {
+//!     fn now(&self) -> u64 {
+//!         self.0.get()
+//!     }
+//! }
+//!
+//! // e.g. requesting a token from an OAuth server, using another connection
+//! let fetch = |token: &mut dyn Write| -> Result<u64, ()> {
+//!     token.write_str("my-token").map_err(|_| ())?;
+//!     // valid for an hour
+//!     Ok(60 * 60 * 1000)
+//! };
+//!
+//! let mut sink = Vec::<u8, U1024>::new();
+//! let req = HttpConnection::<U1024>::new()
+//!     .interceptor(TokenManager::<_, _>::new(Ticks(Cell::new(0)), fetch))
+//!     .begin("GET", "/")
+//!     .execute(&mut sink);
+//!
+//! assert_eq!(
+//!     sink,
+//!     b"GET / HTTP/1.1\r\nAuthorization: Bearer my-token\r\n\r\n".as_ref()
+//! );
+//! ~~~
+
+use crate::headers::AUTHORIZATION;
+use crate::{Clock, Interceptor, RequestParts, Response};
+use core::fmt::Write;
+use heapless::{consts, ArrayLength, String};
+
+/// Fetches new tokens.
+pub trait TokenSource {
+    /// Fetch a new token, writing it to `token`, and returning its lifetime in milliseconds.
+    fn fetch(&mut self, token: &mut dyn Write) -> Result<u64, ()>;
+}
+
+impl<F> TokenSource for F
+where
+    F: FnMut(&mut dyn Write) -> Result<u64, ()>,
+{
+    fn fetch(&mut self, token: &mut dyn Write) -> Result<u64, ()> {
+        self(token)
+    }
+}
+
+/// An interceptor, adding a bearer token to requests, which gets refreshed automatically.
+///
+/// The token is stored in a string of capacity `N`. Requests which already have an
+/// `Authorization` header are sent as they are.
+///
+/// See the [module documentation](self).
+pub struct TokenManager<C, S, N = consts::U512>
+where
+    C: Clock,
+    S: TokenSource,
+    N: ArrayLength<u8>,
+{
+    clock: C,
+    source: S,
+    token: String<N>,
+    // the time the token expires, `None` if there is no token
+    expires: Option<u64>,
+    // refresh the token this long before it expires
+    margin: u64,
+}
+
+impl<C, S, N> TokenManager<C, S, N>
+where
+    C: Clock,
+    S: TokenSource,
+    N: ArrayLength<u8>,
+{
+    /// Create a new instance, fetching tokens from the source.
+    ///
+    /// The first token is fetched with the first request.
+    pub fn new(clock: C, source: S) -> Self {
+        TokenManager {
+            clock,
+            source,
+            token: String::new(),
+            expires: None,
+            margin: 30_000,
+        }
+    }
+
+    /// Set how long before it expires the token is refreshed, in milliseconds, defaults to
+    /// 30 seconds.
+    pub fn margin(mut self, margin: u64) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// The current token, if it didn't expire yet.
+    pub fn token(&self) -> Option<&str> {
+        match self.expires {
+            Some(expires) if self.clock.now() < expires => Some(&self.token),
+            _ => None,
+        }
+    }
+
+    /// Drop the current token, so that a new one is fetched with the next request.
+    pub fn invalidate(&mut self) {
+        self.token = String::new();
+        self.expires = None;
+    }
+
+    /// Access the clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Fetch a new token, keeping the current one if that fails.
+    fn refresh(&mut self) {
+        let mut token = String::<N>::new();
+        let now = self.clock.now();
+        match self.source.fetch(&mut token) {
+            Ok(lifetime) => {
+                debug!("Refreshed token, valid for {} ms", lifetime);
+                self.token = token;
+                self.expires = Some(now.saturating_add(lifetime));
+            }
+            Err(()) => log::warn!("Failed to refresh the token"),
+        }
+    }
+}
+
+impl<C, S, N> Interceptor for TokenManager<C, S, N>
+where
+    C: Clock,
+    S: TokenSource,
+    N: ArrayLength<u8>,
+{
+    fn before_send(&mut self, request: &mut RequestParts) {
+        if request.contains_header(AUTHORIZATION) {
+            return;
+        }
+
+        let refresh = match self.expires {
+            Some(expires) => self.clock.now().saturating_add(self.margin) >= expires,
+            None => true,
+        };
+        if refresh {
+            self.refresh();
+        }
+
+        let token = match self.token() {
+            Some(token) => token,
+            None => {
+                log::warn!("No valid token, sending the request without it");
+                return;
+            }
+        };
+
+        let mut value = String::<N>::new();
+        let result = write!(value, "Bearer {}", token)
+            .map_err(|_| ())
+            .and_then(|_| request.header(AUTHORIZATION, &value));
+        if result.is_err() {
+            log::warn!("Unable to add the token, exceeds the buffer of the request");
+        }
+    }
+
+    fn on_response(&mut self, response: &Response) {
+        if response.code == 401 {
+            log::info!("Token was rejected, fetching a new one with the next request");
+            self.invalidate();
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! [`Source`] implementation, consuming data from a [`bbqueue`] queue.
+//!
+//! This allows interrupt driven drivers, e.g. of a modem connected by UART, to put the
+//! received data into the queue, while the request takes it from there, without an additional
+//! copy.
+//!
+//! This module requires the `bbqueue` feature.
+//!
+//! ~~~
+//! use bbqueue::BBBuffer;
+//! use heapless::consts::*;
+//!
+//! use drogue_http_client::bbqueue_source::BbqSource;
+//! use drogue_http_client::*;
+//!
+//! static QUEUE: BBBuffer<256> = BBBuffer::new();
+//! let (mut producer, consumer) = QUEUE.try_split().unwrap();
+//!
+//! # let mut uart = heapless::Vec::<u8, U256>::new();
+//! let mut req = HttpConnection::<U1024>::new()
+//!     .begin("GET", "/")
+//!     .handler(BufferResponseHandler::<U64>::new())
+//!     .execute(&mut uart);
+//!
+//! // e.g. in the interrupt handler of the UART
+//! let data = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+//! let mut grant = producer.grant_exact(data.len()).unwrap();
+//! grant.buf().copy_from_slice(data);
+//! grant.commit(data.len());
+//!
+//! let mut source = BbqSource::new(consumer);
+//! source.pipe_data(&mut req).unwrap();
+//! ~~~
+
+use crate::{Budget, Inbound, PipeError, Progress, Source};
+use bbqueue::Consumer;
+
+/// A [`Source`], consuming data from the consumer of a queue.
+pub struct BbqSource<'a, const N: usize> {
+    consumer: Consumer<'a, N>,
+    on_would_block: Option<&'a mut dyn FnMut()>,
+}
+
+impl<'a, const N: usize> BbqSource<'a, N> {
+    pub fn new(consumer: Consumer<'a, N>) -> Self {
+        BbqSource {
+            consumer,
+            on_would_block: None,
+        }
+    }
+
+    /// Call the function each time the queue is empty, while waiting for more data.
+    ///
+    /// This allows e.g. entering a low power mode, until the next interrupt.
+    pub fn on_would_block(mut self, f: &'a mut dyn FnMut()) -> Self {
+        self.on_would_block = Some(f);
+        self
+    }
+
+    /// Give back the consumer.
+    pub fn into_inner(self) -> Consumer<'a, N> {
+        self.consumer
+    }
+}
+
+impl<'a, const N: usize> Source for BbqSource<'a, N> {
+    type Error = bbqueue::Error;
+
+    fn pipe_data_limited<Q>(
+        &mut self,
+        request: &mut Q,
+        budget: Budget,
+    ) -> Result<Progress, PipeError<Self::Error>>
+    where
+        Q: Inbound,
+    {
+        let mut progress = Progress::default();
+        while !request.is_complete() && request.error().is_none() && !budget.is_exhausted(&progress)
+        {
+            progress.reads += 1;
+            match self.consumer.split_read() {
+                Ok(grant) => {
+                    // the data may wrap around the end of the queue
+                    let (first, second) = grant.bufs();
+                    let mut len = 0;
+                    for buf in &[first, second] {
+                        let max = budget.remaining_bytes(&progress, buf.len());
+                        if max == 0 || request.is_complete() {
+                            break;
+                        }
+                        request.push_data(&buf[..max]);
+                        progress.bytes += max;
+                        len += max;
+                    }
+                    grant.release(len);
+                }
+                Err(bbqueue::Error::InsufficientSize) => {
+                    request.redeliver();
+                    progress.would_block += 1;
+                    if let Some(f) = &mut self.on_would_block {
+                        f();
+                    }
+                }
+                Err(err) => {
+                    request.transport_failed();
+                    return Err(PipeError::Transport(err));
+                }
+            }
+        }
+        if let Some(err) = request.error() {
+            return Err(err.into());
+        }
+
+        progress.complete = request.is_complete();
+        Ok(progress)
+    }
+}
@@ -0,0 +1,259 @@
+//! Decoding of text in legacy charsets.
+//!
+//! Many embedded servers don't send UTF-8, but ISO-8859-1 or Windows-1252, e.g. for their error
+//! pages. A [`CharsetResponseHandler`] converts such payloads to UTF-8 on the fly, based on the
+//! `charset` parameter of the `Content-Type`, so that the wrapped handler can work with
+//! `from_utf8`.
+//!
+//! ~~~
+//! use heapless::{consts::*, Vec};
+//!
+//! use drogue_http_client::charset::CharsetResponseHandler;
+//! use drogue_http_client::*;
+//!
+//! # let mut sink = Vec::<u8, U1024>::new();
+//! let mut req = HttpConnection::<U1024>::new()
+//!     .begin("GET", "/")
+//!     .handler(CharsetResponseHandler::new(BufferResponseHandler::<U64>::new()))
+//!     .execute(&mut sink);
+//! req.push_data(b"HTTP/1.1 404 Not Found\r\nContent-Type: text/plain; charset=iso-8859-1\r\nContent-Length: 6\r\n\r\nSch\xF6n!");
+//!
+//! let (_, handler) = req.try_complete().unwrap();
+//! assert_eq!(handler.into_inner().payload_str(), Ok("Schön!"));
+//! ~~~
+
+use crate::headers::{ResponseHeaders, CONTENT_TYPE};
+use crate::{Error, FinishResponse, Response, ResponseHandler};
+use core::fmt::Write;
+
+/// A charset of text.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Charset {
+    /// UTF-8, or its subset US-ASCII.
+    Utf8,
+    /// ISO-8859-1, also known as Latin-1.
+    Latin1,
+    /// Windows-1252, a superset of ISO-8859-1 using the range of the C1 control characters.
+    Windows1252,
+}
+
+impl Charset {
+    /// Get the charset from its name, ignoring the case.
+    ///
+    /// Returns `None` if the charset isn't supported.
+    pub fn from_label(label: &str) -> Option<Self> {
+        const LABELS: &[(&str, Charset)] = &[
+            ("utf-8", Charset::Utf8),
+            ("utf8", Charset::Utf8),
+            ("us-ascii", Charset::Utf8),
+            ("ascii", Charset::Utf8),
+            ("iso-8859-1", Charset::Latin1),
+            ("iso8859-1", Charset::Latin1),
+            ("iso_8859-1", Charset::Latin1),
+            ("latin1", Charset::Latin1),
+            ("windows-1252", Charset::Windows1252),
+            ("cp1252", Charset::Windows1252),
+        ];
+
+        LABELS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(label))
+            .map(|(_, charset)| *charset)
+    }
+
+    /// Get the charset from the `charset` parameter of a `Content-Type` header value.
+    ///
+    /// Returns `None` if there is no such parameter, or the charset isn't supported.
+    ///
+    /// ~~~
+    /// use drogue_http_client::charset::Charset;
+    ///
+    /// assert_eq!(
+    ///     Charset::from_content_type("text/html; charset=\"ISO-8859-1\""),
+    ///     Some(Charset::Latin1)
+    /// );
+    /// assert_eq!(Charset::from_content_type("text/html"), None);
+    /// ~~~
+    pub fn from_content_type(value: &str) -> Option<Self> {
+        value
+            .split(';')
+            .skip(1)
+            .filter_map(|parameter| {
+                let mut parts = parameter.splitn(2, '=');
+                let name = parts.next()?.trim();
+                let value = parts.next()?.trim().trim_matches('"');
+                Some((name, value))
+            })
+            .find(|(name, _)| name.eq_ignore_ascii_case("charset"))
+            .and_then(|(_, value)| Self::from_label(value))
+    }
+
+    /// Decode the text, writing it as UTF-8.
+    ///
+    /// Text in UTF-8 must be complete, a sequence split across calls fails to decode. The other
+    /// charsets use a single byte per character, so their text can be decoded in parts.
+    ///
+    /// ~~~
+    /// use drogue_http_client::charset::Charset;
+    /// use heapless::{consts::*, String};
+    ///
+    /// let mut text = String::<U16>::new();
+    /// Charset::Windows1252.decode(b"\x80 5", &mut text).unwrap();
+    /// assert_eq!(text, "€ 5");
+    /// ~~~
+    pub fn decode<W>(self, data: &[u8], w: &mut W) -> core::fmt::Result
+    where
+        W: Write + ?Sized,
+    {
+        match self {
+            Charset::Utf8 => {
+                let text = core::str::from_utf8(data).map_err(|_| core::fmt::Error)?;
+                w.write_str(text)
+            }
+            _ => data
+                .iter()
+                .try_for_each(|b| w.write_char(self.decode_byte(*b))),
+        }
+    }
+
+    /// Decode a single byte of the single byte charsets.
+    fn decode_byte(self, b: u8) -> char {
+        match (self, b) {
+            (Charset::Windows1252, 0x80..=0x9F) => WINDOWS_1252[(b - 0x80) as usize],
+            _ => b as char,
+        }
+    }
+}
+
+/// The characters of Windows-1252 in the range `0x80..=0x9F`.
+///
+/// Bytes without an assigned character map to the C1 control characters, like ISO-8859-1 does.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// A response handler, which converts the payload to UTF-8, before passing it on to the wrapped
+/// handler.
+///
+/// The charset is taken from the `Content-Type` of the response. Payloads without a charset, or
+/// with an unsupported one, are passed on unchanged. The headers are passed on unchanged as well,
+/// so the wrapped handler still sees the original charset.
+///
+/// Converted payload is passed on in small parts, using [`ResponseHandler::more_payload`]. So
+/// the wrapped handler can only hold back payload, using
+/// [`ResponseHandler::consume_payload`], if the payload isn't converted.
+pub struct CharsetResponseHandler<H>
+where
+    H: ResponseHandler,
+{
+    handler: H,
+    charset: Option<Charset>,
+}
+
+impl<H> CharsetResponseHandler<H>
+where
+    H: ResponseHandler,
+{
+    pub fn new(handler: H) -> Self {
+        CharsetResponseHandler {
+            handler,
+            charset: None,
+        }
+    }
+
+    /// The charset of the response, `None` if it didn't announce a supported one.
+    pub fn charset(&self) -> Option<Charset> {
+        self.charset
+    }
+
+    /// Get the wrapped handler.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Get the wrapped handler, mutably.
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Give back the wrapped handler.
+    pub fn into_inner(self) -> H {
+        self.handler
+    }
+
+    /// Convert the payload to UTF-8, passing it on in parts.
+    fn convert(&mut self, charset: Charset, data: &[u8]) {
+        let mut buffer = [0u8; 64];
+        let mut len = 0;
+        for b in data {
+            let c = charset.decode_byte(*b);
+            if len + c.len_utf8() > buffer.len() {
+                self.handler.more_payload(Ok(Some(&buffer[..len])));
+                len = 0;
+            }
+            len += c.encode_utf8(&mut buffer[len..]).len();
+        }
+        if len > 0 {
+            self.handler.more_payload(Ok(Some(&buffer[..len])));
+        }
+    }
+
+    /// The charset to convert the payload from, `None` if it is passed on unchanged.
+    fn converted(&self) -> Option<Charset> {
+        self.charset.filter(|charset| *charset != Charset::Utf8)
+    }
+}
+
+impl<H> ResponseHandler for CharsetResponseHandler<H>
+where
+    H: ResponseHandler,
+{
+    fn response(&mut self, response: Response<'_>) {
+        self.charset = response
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(Charset::from_content_type);
+        self.handler.response(response);
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        match (self.converted(), payload) {
+            (Some(charset), Ok(Some(data))) => self.convert(charset, data),
+            (_, payload) => self.handler.more_payload(payload),
+        }
+    }
+
+    fn consume_payload(&mut self, payload: &[u8]) -> usize {
+        match self.converted() {
+            Some(_) => {
+                self.more_payload(Ok(Some(payload)));
+                payload.len()
+            }
+            None => self.handler.consume_payload(payload),
+        }
+    }
+
+    fn trailers(&mut self, trailers: ResponseHeaders) {
+        self.handler.trailers(trailers);
+    }
+
+    fn failed(&self) -> Option<Error> {
+        self.handler.failed()
+    }
+}
+
+impl<H> FinishResponse for CharsetResponseHandler<H>
+where
+    H: FinishResponse,
+{
+    /// The output of the wrapped handler.
+    type Output = H::Output;
+
+    fn finish(self) -> Result<Self::Output, Error> {
+        self.handler.finish()
+    }
+}
@@ -0,0 +1,572 @@
+//! A managed client, taking care of the TCP connection.
+//!
+//! The [`HttpClient`] opens and connects the socket on demand. When the connection breaks, it
+//! gets re-established, and idempotent requests are replayed.
+//!
+//! A client can also be created from a URL, using [`HttpClient::from_url`], which takes care of
+//! resolving the host, and setting the `Host` header.
+//!
+//! ~~~no_run
+//! use heapless::consts::*;
+//!
+//! use drogue_network::addr::HostSocketAddr;
+//!
+//! use drogue_http_client::client::HttpClient;
+//! use drogue_http_client::*;
+//!
+//! # use drogue_http_client::mock;
+//! # let (network, _) = mock::mock_connection();
+//! let remote = HostSocketAddr::from("192.168.1.1", 8080).unwrap();
+//! let mut client = HttpClient::new(&network, remote, HttpConnection::<U1024>::new());
+//!
+//! let handler = client
+//!     .request("GET", "/state", |req, sink| {
+//!         req.handler(BufferResponseHandler::<U1024>::new())
+//!             .execute(sink)
+//!     })
+//!     .unwrap();
+//! ~~~
+//!
+//! Several requests can be executed back-to-back over the same connection, using
+//! [`HttpClient::batch`]. This keeps the time the radio is on short, compared to connecting
+//! for each request.
+
+use crate::headers::HOST;
+use crate::tcp::TcpSocketSinkSource;
+use crate::uri::Url;
+use crate::{
+    Clock, Error, HttpConnection, Interceptor, NoOpResponseHandler, PipeError, PreparedRequest,
+    Request, RequestBuilder, ResponseHandler, Source, Version,
+};
+use core::fmt::Write;
+use core::str::FromStr;
+use drogue_network::addr::{HostAddr, HostSocketAddr, IpAddr};
+use drogue_network::dns::{AddrType, Dns};
+use drogue_network::tcp::{Mode, TcpStack};
+#[cfg(feature = "delay")]
+use embedded_hal::blocking::delay::DelayUs;
+use heapless::{consts, ArrayLength, String, Vec};
+
+/// An HTTP client, managing the TCP connection.
+pub struct HttpClient<'s, T, IN, I = ()>
+where
+    T: TcpStack,
+    IN: ArrayLength<u8>,
+    I: Interceptor,
+{
+    stack: &'s T,
+    remote: HostSocketAddr,
+    socket: Option<T::TcpSocket>,
+    mode: Mode,
+    // taken while a request is in progress
+    connection: Option<HttpConnection<IN, I>>,
+    max_reconnects: usize,
+    max_would_block: Option<usize>,
+    read_timeout: Option<(&'s dyn Clock, u64)>,
+    on_would_block: Option<&'s mut dyn FnMut()>,
+    #[cfg(feature = "delay")]
+    delay: Option<(&'s mut dyn DelayUs<u32>, u32)>,
+    // value of the host header
+    host: Option<String<consts::U256>>,
+}
+
+impl<'s, T, IN, I> HttpClient<'s, T, IN, I>
+where
+    T: TcpStack,
+    IN: ArrayLength<u8>,
+    I: Interceptor,
+{
+    /// Create a new client, connecting to the remote address.
+    ///
+    /// The socket will be opened when the first request gets executed.
+    pub fn new(stack: &'s T, remote: HostSocketAddr, connection: HttpConnection<IN, I>) -> Self {
+        HttpClient {
+            stack,
+            remote,
+            socket: None,
+            mode: Mode::Blocking,
+            connection: Some(connection),
+            max_reconnects: 3,
+            max_would_block: None,
+            read_timeout: None,
+            on_would_block: None,
+            #[cfg(feature = "delay")]
+            delay: None,
+            host: None,
+        }
+    }
+
+    /// Set how often the connection may be re-established for a single request, defaults to 3.
+    pub fn max_reconnects(mut self, max_reconnects: usize) -> Self {
+        self.max_reconnects = max_reconnects;
+        self
+    }
+
+    /// Set the mode to open sockets with, defaults to [`Mode::Blocking`].
+    ///
+    /// See [`TcpSocketSinkSource::mode`].
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Fail reading a response, when reading would block more often than this in a row. Sending
+    /// the request is limited the same way.
+    ///
+    /// See [`TcpSocketSinkSource::max_would_block`].
+    pub fn max_would_block(mut self, max_would_block: usize) -> Self {
+        self.max_would_block = Some(max_would_block);
+        self
+    }
+
+    /// Fail reading a response, when no data was received for the time, in milliseconds of the
+    /// clock. Sending the request fails as well, when no data could be written for the time.
+    ///
+    /// See [`TcpSocketSinkSource::read_timeout`].
+    pub fn read_timeout(mut self, clock: &'s dyn Clock, timeout: u64) -> Self {
+        self.read_timeout = Some((clock, timeout));
+        self
+    }
+
+    /// Call the function each time reading or writing would block.
+    ///
+    /// See [`TcpSocketSinkSource::on_would_block`].
+    pub fn on_would_block(mut self, f: &'s mut dyn FnMut()) -> Self {
+        self.on_would_block = Some(f);
+        self
+    }
+
+    /// Sleep for the interval, in microseconds, each time reading or writing would block.
+    ///
+    /// See [`TcpSocketSinkSource::delay`].
+    #[cfg(feature = "delay")]
+    pub fn delay(mut self, delay: &'s mut dyn DelayUs<u32>, interval: u32) -> Self {
+        self.delay = Some((delay, interval));
+        self
+    }
+
+    /// Access the HTTP connection.
+    pub fn connection(&self) -> &HttpConnection<IN, I> {
+        // only taken during a request, which requires a mutable reference
+        self.connection.as_ref().unwrap()
+    }
+
+    /// Mutably access the HTTP connection.
+    pub fn connection_mut(&mut self) -> &mut HttpConnection<IN, I> {
+        self.connection.as_mut().unwrap()
+    }
+
+    /// Check if the client holds a socket, which is believed to be connected.
+    pub fn is_connected(&self) -> bool {
+        match &self.socket {
+            Some(socket) => self.stack.is_connected(socket).unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Close the socket, if there is one.
+    pub fn close(&mut self) {
+        if let Some(socket) = self.socket.take() {
+            if let Err(err) = self.stack.close(socket) {
+                log::info!("Failed to close socket: {:?}", err);
+            }
+        }
+    }
+
+    /// Open and connect a new socket, closing the current one.
+    fn reconnect(&mut self) -> Result<(), T::Error> {
+        self.close();
+
+        let socket = self.stack.open(copy_mode(&self.mode))?;
+        let socket = self.stack.connect(socket, copy_addr(&self.remote))?;
+        self.socket = Some(socket);
+        self.connection_mut().reset();
+
+        Ok(())
+    }
+
+    /// Execute a request.
+    ///
+    /// The function is called with a builder for the request, and the sink to execute it on. It
+    /// may be called multiple times, if an idempotent request gets replayed after re-establishing
+    /// the connection.
+    ///
+    /// The connection is re-established if it was lost, or a request failed. Non-idempotent
+    /// requests are not replayed, but fail instead.
+    pub fn request<'req, F, R>(
+        &mut self,
+        method: &'req str,
+        path: &'req str,
+        mut f: F,
+    ) -> Result<R, PipeError<T::Error>>
+    where
+        F: FnMut(
+            RequestBuilder<'req, IN, NoOpResponseHandler, consts::U256, I>,
+            &mut TcpSocketSinkSource<'_, T>,
+        ) -> Request<IN, R, I>,
+        R: ResponseHandler,
+    {
+        self.execute(is_idempotent(method), |connection, host, sink| {
+            let mut builder = connection.begin(method, path);
+            if let Some(host) = host {
+                builder = builder.header(HOST, host);
+            }
+            f(builder, sink)
+        })
+    }
+
+    /// Prepare a request, for executing it repeatedly using [`HttpClient::execute_prepared`].
+    ///
+    /// The function is called with a builder for the request, e.g. for adding headers. The
+    /// `Host` header gets added, the same way as for [`HttpClient::request`].
+    ///
+    /// See [`RequestBuilder::prepare`].
+    pub fn prepare<'req, NP, F>(
+        &mut self,
+        method: &'req str,
+        path: &'req str,
+        f: F,
+    ) -> Result<PreparedRequest<NP>, Error>
+    where
+        NP: ArrayLength<u8>,
+        F: FnOnce(
+            RequestBuilder<'req, IN, NoOpResponseHandler, consts::U256, I>,
+        ) -> RequestBuilder<'req, IN, NoOpResponseHandler, consts::U256, I>,
+    {
+        // only taken during a request, which requires a mutable reference
+        let connection = self.connection.take().unwrap();
+        let mut builder = connection.begin(method, path);
+        if let Some(host) = &self.host {
+            builder = builder.header(HOST, host);
+        }
+        let (connection, prepared) = f(builder).prepare();
+        self.connection = Some(connection);
+        prepared
+    }
+
+    /// Execute a prepared request, sending the payload.
+    ///
+    /// The function creates the handler for the response. It may be called multiple times, as
+    /// the request gets replayed the same way as for [`HttpClient::request`], if its method is
+    /// idempotent.
+    pub fn execute_prepared<NP, R, F>(
+        &mut self,
+        prepared: &PreparedRequest<NP>,
+        mut handler: F,
+        payload: Option<&[u8]>,
+    ) -> Result<R, PipeError<T::Error>>
+    where
+        NP: ArrayLength<u8>,
+        R: ResponseHandler,
+        F: FnMut() -> R,
+    {
+        self.execute(is_idempotent(prepared.method()), |connection, _, sink| {
+            connection.execute_prepared(prepared, handler(), sink, payload)
+        })
+    }
+
+    /// Execute a prepared request, formatting the payload.
+    ///
+    /// See [`HttpClient::execute_prepared`] and [`RequestBuilder::execute_with_fmt`].
+    pub fn execute_prepared_fmt<NP, R, F, P>(
+        &mut self,
+        prepared: &PreparedRequest<NP>,
+        mut handler: F,
+        payload: P,
+    ) -> Result<R, PipeError<T::Error>>
+    where
+        NP: ArrayLength<u8>,
+        R: ResponseHandler,
+        F: FnMut() -> R,
+        P: Fn(&mut dyn Write) -> core::fmt::Result,
+    {
+        self.execute(is_idempotent(prepared.method()), |connection, _, sink| {
+            connection.execute_prepared_fmt(prepared, handler(), sink, &payload)
+        })
+    }
+
+    /// Execute a request, created by the function from the connection, the value of the `Host`
+    /// header, and the sink.
+    ///
+    /// Re-establishes the connection as needed, and replays idempotent requests.
+    fn execute<F, R>(&mut self, idempotent: bool, mut f: F) -> Result<R, PipeError<T::Error>>
+    where
+        F: FnMut(
+            HttpConnection<IN, I>,
+            Option<&str>,
+            &mut TcpSocketSinkSource<'_, T>,
+        ) -> Request<IN, R, I>,
+        R: ResponseHandler,
+    {
+        let mut reconnects = 0usize;
+
+        loop {
+            if !self.is_connected() || self.connection().is_poisoned() {
+                if let Err(err) = self.reconnect() {
+                    log::info!("Failed to connect: {:?}", err);
+                    if reconnects >= self.max_reconnects {
+                        return Err(PipeError::Transport(err));
+                    }
+                    reconnects += 1;
+                    continue;
+                }
+            }
+
+            let (connection, socket) = match (self.connection.take(), self.socket.as_mut()) {
+                (Some(connection), Some(socket)) => (connection, socket),
+                _ => unreachable!("Connected, and no request in progress"),
+            };
+
+            let mut tcp = TcpSocketSinkSource::from(self.stack, socket).mode(copy_mode(&self.mode));
+            if let Some(max_would_block) = self.max_would_block {
+                tcp = tcp.max_would_block(max_would_block);
+            }
+            if let Some((clock, timeout)) = self.read_timeout {
+                tcp = tcp.read_timeout(clock, timeout);
+            }
+            if let Some(f) = &mut self.on_would_block {
+                tcp = tcp.on_would_block(*f);
+            }
+            #[cfg(feature = "delay")]
+            if let Some((delay, interval)) = &mut self.delay {
+                tcp = tcp.delay(*delay, *interval);
+            }
+            let mut request = f(connection, self.host.as_deref(), &mut tcp);
+            let result = tcp.pipe_data(&mut request);
+            let (connection, handler) = request.into_parts();
+            self.connection = Some(connection);
+
+            match result {
+                Ok(_) => {
+                    if self.connection().request_version() == Version::Http10
+                        || self.connection().is_poisoned()
+                    {
+                        // the server closes the connection after the response
+                        self.close();
+                    }
+                    return Ok(handler);
+                }
+                Err(err) => {
+                    log::info!("Request failed: {:?}", err);
+                    // the state of the connection is unknown
+                    self.close();
+                    if !idempotent || reconnects >= self.max_reconnects {
+                        return Err(err);
+                    }
+                    reconnects += 1;
+                }
+            }
+        }
+    }
+
+    /// Execute the requests one after the other, over the same connection, returning the result
+    /// of each of them.
+    ///
+    /// The handler of each request is created by calling the function with the index of the
+    /// request. A failing request doesn't stop the batch, the connection gets re-established for
+    /// the next one, the same way as for [`HttpClient::request`]. At most `N` requests are
+    /// executed.
+    ///
+    /// ~~~no_run
+    /// use heapless::consts::*;
+    ///
+    /// use drogue_network::addr::HostSocketAddr;
+    ///
+    /// use drogue_http_client::client::{BatchRequest, HttpClient};
+    /// use drogue_http_client::*;
+    ///
+    /// # use drogue_http_client::mock;
+    /// # let (network, _) = mock::mock_connection();
+    /// let remote = HostSocketAddr::from("192.168.1.1", 8080).unwrap();
+    /// let mut client = HttpClient::new(&network, remote, HttpConnection::<U1024>::new());
+    ///
+    /// let requests = [
+    ///     BatchRequest::new("PUT", "/telemetry/temperature").payload(b"21.5"),
+    ///     BatchRequest::new("PUT", "/telemetry/humidity").payload(b"48"),
+    ///     BatchRequest::new("GET", "/commands"),
+    /// ];
+    /// let results = client.batch::<_, _, U4>(&requests, |_| BufferResponseHandler::<U256>::new());
+    /// ~~~
+    #[allow(clippy::type_complexity)]
+    pub fn batch<'req, F, R, N>(
+        &mut self,
+        requests: &[BatchRequest<'req>],
+        mut handler: F,
+    ) -> Vec<Result<R, PipeError<T::Error>>, N>
+    where
+        F: FnMut(usize) -> R,
+        R: ResponseHandler,
+        N: ArrayLength<Result<R, PipeError<T::Error>>>,
+    {
+        let mut results = Vec::new();
+        for (i, request) in requests.iter().enumerate() {
+            if results.len() == results.capacity() {
+                log::warn!("Skipping {} requests of the batch", requests.len() - i);
+                break;
+            }
+
+            let result = self.request(request.method, request.path, |req, sink| {
+                req.headers(request.headers)
+                    .handler(handler(i))
+                    .execute_with(sink, request.payload)
+            });
+            // checked the capacity, can't fail
+            results.push(result).ok();
+        }
+        results
+    }
+
+    /// Execute a request for a URL.
+    ///
+    /// The URL must point to the host and port of this client. Otherwise the request fails with
+    /// [`Error::Url`], without calling the function.
+    pub fn request_url<'req, F, R>(
+        &mut self,
+        method: &'req str,
+        url: &'req str,
+        mut f: F,
+    ) -> Result<R, PipeError<T::Error>>
+    where
+        F: FnMut(
+            RequestBuilder<'req, IN, NoOpResponseHandler, consts::U256, I>,
+            &mut TcpSocketSinkSource<'_, T>,
+        ) -> Request<IN, R, I>,
+        R: ResponseHandler,
+    {
+        let url = Url::parse(url).map_err(|_| Error::Url)?;
+        if !self.is_same_origin(&url) {
+            log::warn!("URL doesn't match the client: {}", url.host());
+            return Err(Error::Url.into());
+        }
+
+        let target = url.path();
+        match target.as_str() {
+            Some(path) => self.request(method, path, f),
+            None => self.request(method, "", |req, sink| {
+                f(req.path_fmt(format_args!("{}", target)), sink)
+            }),
+        }
+    }
+
+    /// Check if the URL points to the host and port of this client.
+    pub(crate) fn is_same_origin(&self, url: &Url) -> bool {
+        let hostname = self.remote.addr().hostname();
+        !url.is_secure()
+            && url.port() == self.remote.port()
+            && matches!(hostname, Some(h) if h.eq_ignore_ascii_case(url.host()))
+    }
+}
+
+impl<'s, T, IN, I> HttpClient<'s, T, IN, I>
+where
+    T: TcpStack + Dns,
+    IN: ArrayLength<u8>,
+    I: Interceptor,
+{
+    /// Create a new client for the host of the URL.
+    ///
+    /// The host gets resolved, unless it is an IP address, and the port defaults to the one of
+    /// the scheme. All requests will carry the matching `Host` header. As there is no support
+    /// for TLS, `https` URLs are rejected with [`Error::Tls`].
+    pub fn from_url(
+        stack: &'s T,
+        url: &str,
+        connection: HttpConnection<IN, I>,
+    ) -> Result<Self, Error> {
+        let url = Url::parse(url).map_err(|_| Error::Url)?;
+        if url.is_secure() {
+            log::warn!("TLS is not supported: {}", url.host());
+            return Err(Error::Tls);
+        }
+
+        let mut hostname = String::new();
+        hostname.push_str(url.host()).map_err(|_| Error::Url)?;
+        let ip = match IpAddr::from_str(url.host()) {
+            Ok(ip) => ip,
+            Err(_) => stack
+                .gethostbyname(url.host(), AddrType::Either)
+                .map_err(|err| {
+                    log::info!("Failed to resolve {}: {:?}", url.host(), err);
+                    Error::Resolve
+                })?
+                .ip(),
+        };
+        let remote = HostSocketAddr::new(HostAddr::new(ip, Some(hostname)), url.port());
+
+        let mut host = String::new();
+        host.push_str(url.authority()).map_err(|_| Error::Url)?;
+
+        let mut client = Self::new(stack, remote, connection);
+        client.host = Some(host);
+        Ok(client)
+    }
+}
+
+impl<'s, T, IN, I> Drop for HttpClient<'s, T, IN, I>
+where
+    T: TcpStack,
+    IN: ArrayLength<u8>,
+    I: Interceptor,
+{
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// The description of a request, executed as part of a batch.
+///
+/// See [`HttpClient::batch`].
+#[derive(Copy, Clone, Debug)]
+pub struct BatchRequest<'req> {
+    method: &'req str,
+    path: &'req str,
+    headers: &'req [(&'req str, &'req str)],
+    payload: Option<&'req [u8]>,
+}
+
+impl<'req> BatchRequest<'req> {
+    /// Create a new request, without headers and payload.
+    pub fn new(method: &'req str, path: &'req str) -> Self {
+        BatchRequest {
+            method,
+            path,
+            headers: &[],
+            payload: None,
+        }
+    }
+
+    /// Set the HTTP headers to send.
+    pub fn headers(mut self, headers: &'req [(&'req str, &'req str)]) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Set the payload to send.
+    pub fn payload(mut self, payload: &'req [u8]) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+}
+
+/// Check if requests of the method are idempotent, and so can safely be replayed.
+pub fn is_idempotent(method: &str) -> bool {
+    matches!(
+        method,
+        "GET" | "HEAD" | "PUT" | "DELETE" | "OPTIONS" | "TRACE"
+    )
+}
+
+fn copy_mode(mode: &Mode) -> Mode {
+    match mode {
+        Mode::Blocking => Mode::Blocking,
+        Mode::NonBlocking => Mode::NonBlocking,
+        Mode::Timeout(timeout) => Mode::Timeout(*timeout),
+    }
+}
+
+fn copy_addr(addr: &HostSocketAddr) -> HostSocketAddr {
+    HostSocketAddr::new(
+        HostAddr::new(addr.addr().ip(), addr.addr().hostname().cloned()),
+        addr.port(),
+    )
+}
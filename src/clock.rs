@@ -0,0 +1,136 @@
+use crate::{ConnectionEvent, Interceptor};
+
+/// A source of time.
+pub trait Clock {
+    /// The current time, in milliseconds.
+    ///
+    /// The time must be monotonic, but doesn't need to relate to any wall-clock time.
+    fn now(&self) -> u64;
+}
+
+/// A source of the wall-clock time, e.g. a real-time clock synchronized using NTP.
+pub trait UnixClock {
+    /// The current time, in seconds since the Unix epoch.
+    fn unix_time(&self) -> u64;
+}
+
+/// The timestamps of a request, in milliseconds of a [`Clock`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Sending the request started.
+    pub send_start: u64,
+    /// The first byte of the response was received.
+    pub first_byte: Option<u64>,
+    /// The response was received completely.
+    pub complete: Option<u64>,
+}
+
+impl Timings {
+    /// The time from starting to send the request, until receiving the first byte.
+    pub fn time_to_first_byte(&self) -> Option<u64> {
+        self.first_byte
+            .map(|first_byte| first_byte.saturating_sub(self.send_start))
+    }
+
+    /// The time from starting to send the request, until the response was complete.
+    pub fn duration(&self) -> Option<u64> {
+        self.complete
+            .map(|complete| complete.saturating_sub(self.send_start))
+    }
+}
+
+/// An interceptor, recording the [`Timings`] of requests.
+///
+/// The timings of a completed request are available from
+/// [`CompletedRequest::timings`](crate::CompletedRequest::timings).
+///
+/// ~~~
+/// use drogue_http_client::*;
+/// use heapless::{consts::*, Vec};
+///
+/// struct Ticks(core::cell::Cell<u64>);
+///
+/// impl Clock for Ticks {
+///     fn now(&self) -> u64 {
+///         let now = self.0.get();
+///         self.0.set(now + 10);
+///         now
+///     }
+/// }
+///
+/// let mut sink = Vec::<u8, U1024>::new();
+/// let mut req = HttpConnection::<U1024>::new()
+///     .interceptor(Timing::new(Ticks(Default::default())))
+///     .begin("GET", "/")
+///     .execute(&mut sink);
+///
+/// req.push_data(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n");
+///
+/// let req = req.into_completed().unwrap();
+/// let timings = req.timings().unwrap();
+/// assert_eq!(timings.time_to_first_byte(), Some(10));
+/// assert_eq!(timings.duration(), Some(20));
+/// ~~~
+pub struct Timing<C>
+where
+    C: Clock,
+{
+    clock: C,
+    timings: Option<Timings>,
+}
+
+impl<C> Timing<C>
+where
+    C: Clock,
+{
+    pub fn new(clock: C) -> Self {
+        Timing {
+            clock,
+            timings: None,
+        }
+    }
+
+    /// The timings of the current, or last, request.
+    pub fn timings(&self) -> Option<&Timings> {
+        self.timings.as_ref()
+    }
+
+    /// Access the clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+}
+
+impl<C> Interceptor for Timing<C>
+where
+    C: Clock,
+{
+    fn observe_inbound(&mut self, data: &[u8]) {
+        if let Some(timings) = &mut self.timings {
+            if timings.first_byte.is_none() && !data.is_empty() {
+                timings.first_byte = Some(self.clock.now());
+            }
+        }
+    }
+
+    fn observe_event(&mut self, event: ConnectionEvent) {
+        match event {
+            ConnectionEvent::SendStarted => {
+                self.timings = Some(Timings {
+                    send_start: self.clock.now(),
+                    ..Default::default()
+                });
+            }
+            ConnectionEvent::ResponseComplete => {
+                if let Some(timings) = &mut self.timings {
+                    timings.complete = Some(self.clock.now());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn request_timings(&self) -> Option<Timings> {
+        self.timings
+    }
+}
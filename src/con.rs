@@ -1,15 +1,94 @@
-use crate::{NoOpResponseHandler, ResponseHandler, Sink};
-use core::str::from_utf8;
-use heapless::{ArrayLength, Vec};
-use httparse::Status;
+use crate::digest::{write_base64, BodyDigest};
+#[cfg(feature = "gzip")]
+use crate::headers::CONTENT_ENCODING;
+use crate::headers::{
+    is_valid_name, HeaderEntries, HeaderMap, HeaderName, ResponseHeaders, CONNECTION,
+    CONTENT_DIGEST, CONTENT_LENGTH, CONTENT_MD5, CONTENT_TYPE, TRANSFER_ENCODING, UPGRADE,
+};
+use crate::interceptor::ObservedSink;
+use crate::metrics::increment;
+use crate::parser::{ResponseParser, MAX_HEADERS};
+use crate::sink::SinkWrapper;
+use crate::{
+    Budget, ChunkedWriter, ConnectionEvent, Error, FinishResponse, Inbound, IncompleteReason,
+    Interceptor, Metrics, NoOpResponseHandler, PipeError, Progress, RequestParts, ResponseHandler,
+    RingBuffer, SendHalf, Sink, Source, Timings,
+};
+use core::fmt::Write;
+use core::marker::PhantomData;
+use heapless::{consts, ArrayLength, String, Vec};
 
 /// An HTTP connection.
-pub struct HttpConnection<IN>
+///
+/// Requests executed on the connection pass through the chain of interceptors `I`, see
+/// [`HttpConnection::interceptor`].
+pub struct HttpConnection<IN, I = ()>
 where
     IN: ArrayLength<u8>,
+    I: Interceptor,
 {
     // inbound transport buffer
-    inbound: Vec<u8, IN>,
+    inbound: RingBuffer<IN>,
+    // the stream is out of sync, and must be re-established
+    poisoned: bool,
+    config: Config,
+    // the chain of interceptors
+    interceptors: I,
+    metrics: Metrics,
+}
+
+/// The settings of a connection.
+#[derive(Clone, Debug)]
+struct Config {
+    // store the reason phrase of responses
+    capture_reason: bool,
+    // tolerate malformed responses
+    lenient: bool,
+    // the protocol version of requests
+    version: Version,
+    // the maximum size of response payloads
+    max_body_size: Option<usize>,
+    // the maximum size of response headers
+    max_header_size: Option<usize>,
+    // the maximum number of response headers
+    max_headers: usize,
+    // the prefix of request paths, `None` if it didn't fit
+    base_path: Option<String<consts::U64>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            capture_reason: !cfg!(feature = "minimal"),
+            lenient: false,
+            version: Version::default(),
+            max_body_size: None,
+            max_header_size: None,
+            max_headers: MAX_HEADERS,
+            base_path: Some(String::new()),
+        }
+    }
+}
+
+impl Config {
+    fn set_base_path(&mut self, base_path: &str) {
+        let mut owned = String::new();
+        // the path of a request starts with a slash already
+        let base_path = base_path.trim_end_matches('/');
+        self.base_path = owned.push_str(base_path).ok().map(|_| owned);
+        if self.base_path.is_none() {
+            log::error!("Base path exceeds buffer capacity, requests will not be sent");
+        }
+    }
+}
+
+impl<IN> Default for HttpConnection<IN>
+where
+    IN: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<IN> HttpConnection<IN>
@@ -21,162 +100,1766 @@ where
     /// **Note:** The connection does not establish a new connection on e.g. a TCP stack. It more
     /// manages the state of an HTTP connection.
     pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    /// Create a builder, for configuring a new instance.
+    ///
+    /// ~~~
+    /// # use drogue_http_client::*;
+    /// # use heapless::consts::*;
+    /// let con = HttpConnection::<U1024>::builder()
+    ///     .lenient(true)
+    ///     .max_headers(8)
+    ///     .max_body_size(4096)
+    ///     .build();
+    /// ~~~
+    pub fn builder() -> HttpConnectionBuilder<IN> {
+        HttpConnectionBuilder {
+            config: Config::default(),
+            interceptors: (),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<IN, I> HttpConnection<IN, I>
+where
+    IN: ArrayLength<u8>,
+    I: Interceptor,
+{
+    /// Add an interceptor to the end of the chain.
+    ///
+    /// The interceptor will be applied to all requests executed on this connection.
+    pub fn interceptor<IC>(self, interceptor: IC) -> HttpConnection<IN, (I, IC)>
+    where
+        IC: Interceptor,
+    {
         HttpConnection {
-            inbound: Vec::new(),
+            inbound: self.inbound,
+            poisoned: self.poisoned,
+            config: self.config,
+            interceptors: (self.interceptors, interceptor),
+            metrics: self.metrics,
         }
     }
 
+    /// The metrics of this connection.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Take the metrics of this connection, resetting them.
+    ///
+    /// This can be used to periodically report the metrics of the last interval.
+    pub fn take_metrics(&mut self) -> Metrics {
+        core::mem::take(&mut self.metrics)
+    }
+
+    /// Access the chain of interceptors.
+    pub fn interceptors(&self) -> &I {
+        &self.interceptors
+    }
+
+    /// Mutably access the chain of interceptors.
+    pub fn interceptors_mut(&mut self) -> &mut I {
+        &mut self.interceptors
+    }
+
+    /// Set if the reason phrase of responses should be passed on to the response handlers.
+    ///
+    /// This is the default for all requests of this connection, and can be overridden per
+    /// request using [`RequestBuilder::capture_reason`]. By default, the reason phrase is
+    /// captured, unless the `minimal` feature is enabled. With the `tiny` feature, it is never
+    /// captured.
+    pub fn capture_reason(mut self, capture_reason: bool) -> Self {
+        self.config.capture_reason = capture_reason;
+        self
+    }
+
+    /// Set if responses should be parsed in lenient mode, disabled by default.
+    ///
+    /// Some modems mangle the stream, e.g. by echoing the request, or prefixing the response
+    /// with additional data. In lenient mode, everything before the status line is skipped, and
+    /// some deviations from the HTTP grammar, like additional spaces, are tolerated.
+    ///
+    /// The status line is expected to start with `HTTP/`, not preceded by a space or tab, so that
+    /// an echoed request line is skipped as well.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.config.lenient = lenient;
+        self
+    }
+
+    /// Set the protocol version of requests, defaults to HTTP/1.1.
+    ///
+    /// Some legacy servers don't cope with HTTP/1.1. With HTTP/1.0, servers close the connection
+    /// after each response, so the [`HttpClient`](crate::client::HttpClient) re-establishes it
+    /// for the next request. Payloads of unknown length can't be sent, as they require the
+    /// "chunked" transfer encoding, such requests fail with [`Error::Send`].
+    pub fn version(mut self, version: Version) -> Self {
+        self.config.version = version;
+        self
+    }
+
+    /// Fail requests with [`Error::BodyTooLarge`], when the payload of the response exceeds
+    /// the size, by default the size is not limited.
+    ///
+    /// This protects e.g. the data budget of a device from a misconfigured server, streaming
+    /// more data than expected. A payload announced to be larger fails right after the
+    /// header, before receiving any of it.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.config.max_body_size = Some(max_body_size);
+        self
+    }
+
+    /// Fail requests with [`Error::HeaderTooLarge`], when the header of the response exceeds
+    /// the size, by default the size is not limited.
+    ///
+    /// The header must always fit into the inbound buffer, this allows to limit it further,
+    /// protecting the device from servers sending an endless stream of header data.
+    pub fn max_header_size(mut self, max_header_size: usize) -> Self {
+        self.config.max_header_size = Some(max_header_size);
+        self
+    }
+
+    /// Set a prefix of the path of all requests, like `/api/v1`.
+    ///
+    /// The prefix is applied to paths starting with a slash, other forms of request targets are
+    /// sent as they are. The prefix can have up to 64 bytes, otherwise requests will not be
+    /// sent. Interceptors see the path including the prefix.
+    ///
+    /// ~~~
+    /// # use drogue_http_client::*;
+    /// # use heapless::{consts::*, Vec};
+    /// # let mut sink = Vec::<u8, U1024>::new();
+    /// let req = HttpConnection::<U1024>::new()
+    ///     .base_path("/api/v1")
+    ///     .begin("GET", "/devices")
+    ///     .execute(&mut sink);
+    ///
+    /// assert!(sink.starts_with(b"GET /api/v1/devices HTTP/1.1\r\n"));
+    /// ~~~
+    pub fn base_path(mut self, base_path: &str) -> Self {
+        self.config.set_base_path(base_path);
+        self
+    }
+
+    /// The protocol version of requests.
+    pub(crate) fn request_version(&self) -> Version {
+        self.config.version
+    }
+
+    /// Check if the connection is poisoned.
+    ///
+    /// A connection gets poisoned when processing a response failed. As the position in the
+    /// inbound stream is unknown, no further request can be executed on it. The underlying
+    /// transport must be re-established, and the connection [`reset`](HttpConnection::reset).
+    /// This is also the case, when the server closes the connection after a response, see
+    /// [`Request::is_close`].
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Reset the state of the connection, after the underlying transport was re-established.
+    ///
+    /// This discards any buffered inbound data, and clears the poisoned flag.
+    pub fn reset(&mut self) {
+        self.inbound.clear();
+        self.poisoned = false;
+        self.interceptors.observe_event(ConnectionEvent::Reset);
+    }
+
     /// Begin a new HTTP request.
     ///
     /// The request will only be sent to the sink (server) when one of the "execute" functions
-    /// is being invoked.
+    /// is being invoked. If the connection is poisoned, the request will not be sent, and fails
+    /// with [`Error::Send`].
     pub fn begin<'req>(
         self,
-        method: &'static str,
-        path: &'static str,
-    ) -> RequestBuilder<'req, IN, NoOpResponseHandler> {
-        log::debug!("Begin new request - method: {}, path: {}", method, path);
+        method: &'req str,
+        path: &'req str,
+    ) -> RequestBuilder<'req, IN, NoOpResponseHandler, consts::U256, I> {
+        debug!("Begin new request - method: {}, path: {}", method, path);
+
+        let capture_reason = self.config.capture_reason;
+        let invalid = self.poisoned;
+        if invalid {
+            log::warn!("Connection is poisoned, reset it before executing requests");
+        }
 
         RequestBuilder {
             connection: self,
             method,
             path,
-            headers: None,
-            handler: NoOpResponseHandler,
+            headers: None,
+            owned_headers: HeaderMap::new(),
+            fmt_headers: Vec::new(),
+            owned_path: None,
+            content_type: None,
+            handler: NoOpResponseHandler,
+            capture_reason,
+            invalid,
+        }
+    }
+
+    /// Begin a new HTTP request, formatting the path.
+    ///
+    /// The path will be rendered into a buffer owned by the request builder. If it doesn't fit,
+    /// the request will not be sent.
+    ///
+    /// ~~~
+    /// # use drogue_http_client::*;
+    /// # use heapless::{consts::*, Vec};
+    /// # let mut sink = Vec::<u8, U1024>::new();
+    /// let device_id = "my-device";
+    /// let req = HttpConnection::<U1024>::new()
+    ///     .begin_fmt("POST", format_args!("/devices/{}/events", device_id))
+    ///     .execute(&mut sink);
+    /// ~~~
+    pub fn begin_fmt<'req>(
+        self,
+        method: &'req str,
+        path: core::fmt::Arguments,
+    ) -> RequestBuilder<'req, IN, NoOpResponseHandler, consts::U256, I> {
+        self.begin(method, "").path_fmt(path)
+    }
+
+    /// Begin a new `OPTIONS` request, probing the capabilities of the server as a whole.
+    ///
+    /// This uses `*` as the request target (asterisk-form), the base path doesn't apply.
+    pub fn options_server<'req>(
+        self,
+    ) -> RequestBuilder<'req, IN, NoOpResponseHandler, consts::U256, I> {
+        self.begin("OPTIONS", "*")
+    }
+
+    /// Begin a new `CONNECT` request, establishing a tunnel through a proxy.
+    ///
+    /// The authority, like `example.com:443`, is used as the request target (authority-form),
+    /// the base path doesn't apply. If the proxy agrees, it responds with a `2xx` status code,
+    /// and the request gets completed right after the response header. Use
+    /// [`Request::into_upgraded`] to take over the tunnel.
+    pub fn connect<'req>(
+        self,
+        authority: &'req str,
+    ) -> RequestBuilder<'req, IN, NoOpResponseHandler, consts::U256, I> {
+        self.begin("CONNECT", authority)
+    }
+
+    /// Begin a new POST HTTP request.
+    pub fn post<'req>(
+        self,
+        path: &'req str,
+    ) -> RequestBuilder<'req, IN, NoOpResponseHandler, consts::U256, I> {
+        self.begin("POST", path)
+    }
+
+    /// Execute a prepared request, sending the payload.
+    ///
+    /// If the connection is poisoned, the request is not sent, and fails with [`Error::Send`].
+    ///
+    /// See [`PreparedRequest`].
+    pub fn execute_prepared<NP, R, S>(
+        self,
+        prepared: &PreparedRequest<NP>,
+        handler: R,
+        sink: &mut S,
+        payload: Option<&[u8]>,
+    ) -> Request<IN, R, I>
+    where
+        NP: ArrayLength<u8>,
+        R: ResponseHandler,
+        S: Sink,
+    {
+        match payload {
+            Some(payload) => {
+                self.execute_prepared_payload(prepared, handler, sink, Payload::Parts(&[payload]))
+            }
+            None => self.execute_prepared_payload(prepared, handler, sink, Payload::None),
+        }
+    }
+
+    /// Execute a prepared request, formatting the payload.
+    ///
+    /// See [`RequestBuilder::execute_with_fmt`].
+    pub fn execute_prepared_fmt<NP, R, S, F>(
+        self,
+        prepared: &PreparedRequest<NP>,
+        handler: R,
+        sink: &mut S,
+        f: F,
+    ) -> Request<IN, R, I>
+    where
+        NP: ArrayLength<u8>,
+        R: ResponseHandler,
+        S: Sink,
+        F: Fn(&mut dyn Write) -> core::fmt::Result,
+    {
+        self.execute_prepared_payload(prepared, handler, sink, Payload::Fmt(&f))
+    }
+
+    fn execute_prepared_payload<NP, R, S>(
+        mut self,
+        prepared: &PreparedRequest<NP>,
+        handler: R,
+        sink: &mut S,
+        payload: Payload,
+    ) -> Request<IN, R, I>
+    where
+        NP: ArrayLength<u8>,
+        R: ResponseHandler,
+        S: Sink,
+    {
+        let mut sent = false;
+        if self.poisoned {
+            log::warn!("Connection is poisoned, reset it before executing requests");
+        } else {
+            increment(&mut self.metrics.requests, 1);
+            self.interceptors
+                .observe_event(ConnectionEvent::SendStarted);
+            sent = self.send_prepared(sink, &prepared.head, payload).is_ok();
+            if !sent {
+                increment(&mut self.metrics.transport_errors, 1);
+            }
+        }
+
+        let capture_reason = self.config.capture_reason;
+        let mut request = self.into_request(handler, prepared.method(), capture_reason);
+        if !sent {
+            request.fail(Error::Send);
+        } else {
+            request
+                .parser
+                .push_retained(&mut request.connection.observer());
+        }
+        request
+    }
+
+    /// Create the request, for processing the response to a request of the method.
+    fn into_request<R>(
+        mut self,
+        handler: R,
+        method: &str,
+        capture_reason: bool,
+    ) -> Request<IN, R, I>
+    where
+        R: ResponseHandler,
+    {
+        let inbound = core::mem::take(&mut self.inbound);
+        let parser = ResponseParser::with_buffer(handler, inbound)
+            .tunnel(method == "CONNECT")
+            .head(method == "HEAD")
+            .capture_reason(capture_reason)
+            .lenient(self.config.lenient)
+            .max_body_size(self.config.max_body_size)
+            .max_header_size(self.config.max_header_size)
+            .max_headers(self.config.max_headers);
+        Request {
+            connection: self,
+            parser,
+        }
+    }
+
+    pub(crate) fn send_request<S>(
+        &mut self,
+        sink: &mut S,
+        head: &RequestHead,
+        payload: Payload,
+    ) -> Result<(), ()>
+    where
+        S: Sink,
+    {
+        self.send(sink, payload, |w, framing| {
+            Self::create_request_headers(w, head, framing)
+        })
+    }
+
+    /// Send a request, using the head of a prepared request.
+    fn send_prepared<S>(&mut self, sink: &mut S, head: &[u8], payload: Payload) -> Result<(), ()>
+    where
+        S: Sink,
+    {
+        self.send(sink, payload, |w, framing| {
+            w.0.send_all(head).map_err(|_| core::fmt::Error)?;
+            write_framing(w, framing)?;
+            w.write_str("\r\n")
+        })
+    }
+
+    /// Send a request, writing its head using the function.
+    fn send<S, F>(&mut self, sink: &mut S, payload: Payload, head: F) -> Result<(), ()>
+    where
+        S: Sink,
+        F: FnOnce(&mut SinkWrapper, Framing) -> core::fmt::Result,
+    {
+        let sink = &mut ObservedSink {
+            sink,
+            interceptors: &mut self.interceptors,
+            sent: &mut self.metrics.bytes_sent,
+        };
+
+        // send headers
+        let framing = payload.framing();
+        head(&mut SinkWrapper(sink), framing).map_err(|_| ())?;
+
+        // send payload
+        match payload {
+            Payload::None => {}
+            Payload::Parts(parts) => {
+                for part in parts {
+                    sink.send_all(part)?;
+                }
+            }
+            Payload::Fmt(f) => f(&mut SinkWrapper(sink)).map_err(|_| ())?,
+            Payload::Stream(f) => {
+                let mut writer = ChunkedWriter::new(sink);
+                f(&mut writer).map_err(|_| ())?;
+                writer.finish()?;
+            }
+        }
+
+        sink.flush()?;
+
+        self.interceptors
+            .observe_event(ConnectionEvent::RequestSent);
+        Ok(())
+    }
+
+    fn create_request_headers(
+        w: &mut SinkWrapper,
+        head: &RequestHead,
+        framing: Framing,
+    ) -> Result<(), core::fmt::Error> {
+        write_request_line(w, head.version, head.method, head.path, framing)?;
+        Self::create_header_fields(w, head, framing)?;
+        w.write_str("\r\n")
+    }
+
+    /// Write the header fields of the request, skipping the ones conflicting with the framing.
+    fn create_header_fields(
+        w: &mut SinkWrapper,
+        head: &RequestHead,
+        framing: Framing,
+    ) -> Result<(), core::fmt::Error> {
+        let content_type = head
+            .content_type
+            .map(|content_type| (CONTENT_TYPE.as_str(), content_type));
+        let headers = head
+            .headers
+            .unwrap_or_default()
+            .iter()
+            .copied()
+            // owned headers override the ones from the slice
+            .filter(|(name, _)| !head.owned_headers.contains(*name))
+            .chain(head.owned_headers.iter())
+            // formatted headers override all others
+            .filter(|(name, _)| !head.fmt_headers.iter().any(|(n, _)| n.matches(name)))
+            .filter(|(name, _)| {
+                let overridden = content_type.is_some() && CONTENT_TYPE.matches(name);
+                if overridden {
+                    debug!("Skipping header overridden by content type: {}", name);
+                }
+                !overridden
+            });
+
+        write_headers(w, content_type.into_iter().chain(headers), framing)?;
+        for (name, value) in head.fmt_headers {
+            if conflicts_with_framing(framing, name.as_str()) {
+                continue;
+            }
+            // written directly, without buffering the value
+            write!(w, "{}: ", name)?;
+            HeaderValueWriter(w).write_fmt(*value)?;
+            w.write_str("\r\n")?;
+        }
+        Ok(())
+    }
+
+    /// Observe the processing of a response, for the metrics and interceptors.
+    fn observer(&mut self) -> Observer<'_, I> {
+        Observer {
+            interceptors: &mut self.interceptors,
+            metrics: &mut self.metrics,
+            poisoned: &mut self.poisoned,
+        }
+    }
+}
+
+/// Records the metrics of a connection, and passes everything on to its interceptors.
+struct Observer<'a, I>
+where
+    I: Interceptor,
+{
+    interceptors: &'a mut I,
+    metrics: &'a mut Metrics,
+    poisoned: &'a mut bool,
+}
+
+impl<'a, I> Interceptor for Observer<'a, I>
+where
+    I: Interceptor,
+{
+    fn on_response(&mut self, response: &Response) {
+        self.metrics.record_response(response.code);
+        self.interceptors.on_response(response);
+    }
+
+    fn observe_inbound(&mut self, data: &[u8]) {
+        increment(&mut self.metrics.bytes_received, data.len());
+        self.interceptors.observe_inbound(data);
+    }
+
+    fn observe_event(&mut self, event: ConnectionEvent) {
+        if let ConnectionEvent::Failed(_) = event {
+            // the position in the inbound stream is unknown
+            *self.poisoned = true;
+            increment(&mut self.metrics.protocol_errors, 1);
+        }
+        self.interceptors.observe_event(event);
+    }
+}
+
+/// A builder for an [`HttpConnection`].
+///
+/// This gathers the settings of the connection, which apply to all of its requests. It is
+/// created using [`HttpConnection::builder`].
+pub struct HttpConnectionBuilder<IN, I = ()>
+where
+    IN: ArrayLength<u8>,
+    I: Interceptor,
+{
+    config: Config,
+    interceptors: I,
+    _marker: PhantomData<IN>,
+}
+
+impl<IN, I> HttpConnectionBuilder<IN, I>
+where
+    IN: ArrayLength<u8>,
+    I: Interceptor,
+{
+    /// Set if the reason phrase of responses should be passed on to the response handlers.
+    ///
+    /// See [`HttpConnection::capture_reason`].
+    pub fn capture_reason(mut self, capture_reason: bool) -> Self {
+        self.config.capture_reason = capture_reason;
+        self
+    }
+
+    /// Set if responses should be parsed in lenient mode, disabled by default.
+    ///
+    /// See [`HttpConnection::lenient`].
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.config.lenient = lenient;
+        self
+    }
+
+    /// Set the protocol version of requests, defaults to HTTP/1.1.
+    ///
+    /// See [`HttpConnection::version`].
+    pub fn version(mut self, version: Version) -> Self {
+        self.config.version = version;
+        self
+    }
+
+    /// Limit the size of response payloads, by default the size is not limited.
+    ///
+    /// See [`HttpConnection::max_body_size`].
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.config.max_body_size = Some(max_body_size);
+        self
+    }
+
+    /// Limit the size of response headers, by default the size is not limited.
+    ///
+    /// See [`HttpConnection::max_header_size`].
+    pub fn max_header_size(mut self, max_header_size: usize) -> Self {
+        self.config.max_header_size = Some(max_header_size);
+        self
+    }
+
+    /// Limit the number of response headers, defaults to the maximum of
+    /// [`MAX_HEADERS`](crate::parser::MAX_HEADERS).
+    ///
+    /// Responses with more headers fail to parse, with
+    /// [`httparse::Error::TooManyHeaders`]. Values above the maximum are capped.
+    pub fn max_headers(mut self, max_headers: usize) -> Self {
+        self.config.max_headers = max_headers.min(MAX_HEADERS);
+        self
+    }
+
+    /// Set a prefix of the path of all requests.
+    ///
+    /// See [`HttpConnection::base_path`].
+    pub fn base_path(mut self, base_path: &str) -> Self {
+        self.config.set_base_path(base_path);
+        self
+    }
+
+    /// Add an interceptor to the end of the chain.
+    ///
+    /// See [`HttpConnection::interceptor`].
+    pub fn interceptor<IC>(self, interceptor: IC) -> HttpConnectionBuilder<IN, (I, IC)>
+    where
+        IC: Interceptor,
+    {
+        HttpConnectionBuilder {
+            config: self.config,
+            interceptors: (self.interceptors, interceptor),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Create the connection.
+    pub fn build(self) -> HttpConnection<IN, I> {
+        HttpConnection {
+            inbound: RingBuffer::new(),
+            poisoned: false,
+            config: self.config,
+            interceptors: self.interceptors,
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+/// A request builder, which helps to gather all required information before executing the request.
+///
+/// Owned request data, like headers added using [`RequestBuilder::header`] or a formatted path,
+/// is copied into buffers of capacity `N`.
+pub struct RequestBuilder<'req, IN, R, N = consts::U256, I = ()>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+    N: ArrayLength<u8>,
+    I: Interceptor,
+{
+    connection: HttpConnection<IN, I>,
+    method: &'req str,
+    path: &'req str,
+    headers: Option<&'req [(&'req str, &'req str)]>,
+    owned_headers: HeaderMap<N>,
+    fmt_headers: Vec<(HeaderName<'req>, core::fmt::Arguments<'req>), consts::U4>,
+    owned_path: Option<String<N>>,
+    content_type: Option<&'req str>,
+    handler: R,
+    capture_reason: bool,
+    // the request is invalid, and must not be sent
+    invalid: bool,
+}
+
+/// The request line and headers of a request.
+pub(crate) struct RequestHead<'a> {
+    pub version: Version,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub headers: Option<&'a [(&'a str, &'a str)]>,
+    pub owned_headers: HeaderEntries<'a>,
+    pub fmt_headers: &'a [(HeaderName<'a>, core::fmt::Arguments<'a>)],
+    pub content_type: Option<&'a str>,
+}
+
+impl<'req, IN, R, N, I> RequestBuilder<'req, IN, R, N, I>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+    N: ArrayLength<u8>,
+    I: Interceptor,
+{
+    /// Set HTTP headers to send.
+    ///
+    /// The headers of the first call are borrowed. Calling this again merges the headers with
+    /// the ones set before, copying them like [`RequestBuilder::header`] does, replacing headers
+    /// of the same name.
+    ///
+    /// ~~~
+    /// use heapless::{consts::*, Vec};
+    /// use drogue_http_client::*;
+    ///
+    /// # let mut sink = Vec::<u8, U1024>::new();
+    /// let req = HttpConnection::<U1024>::new()
+    ///     .begin("GET", "/")
+    ///     .headers(&[("Accept", "application/json"), ("X-Device", "sensor-1")])
+    ///     .header("X-Api-Key", "secret")
+    ///     .headers(&[("X-Device", "sensor-2")])
+    ///     .execute(&mut sink);
+    ///
+    /// assert_eq!(
+    ///     sink,
+    ///     b"GET / HTTP/1.1\r\nAccept: application/json\r\nX-Api-Key: secret\r\nX-Device: sensor-2\r\n\r\n"
+    ///         .as_ref()
+    /// );
+    /// ~~~
+    pub fn headers(mut self, headers: &'req [(&'req str, &'req str)]) -> Self {
+        if self.headers.is_none() {
+            self.headers = Some(headers);
+            return self;
+        }
+
+        for (name, _) in headers {
+            self.owned_headers.remove(*name);
+        }
+        for (name, value) in headers {
+            if self.owned_headers.append(*name, value).is_err() {
+                log::warn!("Unable to add header: {}", name);
+            }
+        }
+        self
+    }
+
+    /// Set an HTTP header, copying name and value.
+    ///
+    /// This can be called repeatedly, to set several headers. It replaces a previously set
+    /// header with the same name, and overrides headers of the same name set using
+    /// [`RequestBuilder::headers`]. If the header is invalid or doesn't fit into the buffer, it
+    /// is dropped and a warning gets logged.
+    pub fn header<'n, H>(mut self, name: H, value: &str) -> Self
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        if self.owned_headers.insert(name, value).is_err() {
+            log::warn!("Unable to add header: {}", name);
+        }
+        self
+    }
+
+    /// Set an HTTP header, with a formatted value.
+    ///
+    /// The value is written directly to the sink when sending the request, so that no buffer is
+    /// required for it, e.g. for numeric values. Up to four headers can be set this way. They
+    /// replace previously set formatted headers of the same name, and override headers of the
+    /// same name set using [`RequestBuilder::header`] or [`RequestBuilder::headers`]. If the
+    /// header is invalid, or there are too many of them, it is dropped and a warning gets logged.
+    ///
+    /// Sending the request fails if the formatted value contains a line break.
+    ///
+    /// ~~~
+    /// use heapless::{consts::*, Vec};
+    /// use drogue_http_client::*;
+    ///
+    /// # let mut sink = Vec::<u8, U1024>::new();
+    /// let seq = 42;
+    /// let req = HttpConnection::<U1024>::new()
+    ///     .begin("GET", "/")
+    ///     .header_fmt("X-Seq", format_args!("{}", seq))
+    ///     .execute(&mut sink);
+    ///
+    /// assert_eq!(sink, b"GET / HTTP/1.1\r\nX-Seq: 42\r\n\r\n".as_ref());
+    /// ~~~
+    pub fn header_fmt<H>(mut self, name: H, value: core::fmt::Arguments<'req>) -> Self
+    where
+        H: Into<HeaderName<'req>>,
+    {
+        let name = name.into();
+        if !is_valid_name(name.as_str()) {
+            log::warn!("Unable to add header: {}", name);
+            return self;
+        }
+
+        match self.fmt_headers.iter_mut().find(|(n, _)| *n == name) {
+            Some(header) => header.1 = value,
+            None => {
+                if self.fmt_headers.push((name, value)).is_err() {
+                    log::warn!("Unable to add header: {}", name);
+                }
+            }
+        }
+        self
+    }
+
+    /// Change the capacity of the buffers used for owned request data.
+    ///
+    /// Headers which don't fit into the new buffer are dropped.
+    pub fn buffer_capacity<NN>(self) -> RequestBuilder<'req, IN, R, NN, I>
+    where
+        NN: ArrayLength<u8>,
+    {
+        let mut owned_headers = HeaderMap::new();
+        for (name, value) in self.owned_headers.entries().iter() {
+            if owned_headers.append(name, value).is_err() {
+                log::warn!("Unable to keep header: {}", name);
+            }
+        }
+
+        let mut invalid = self.invalid;
+        let owned_path = self.owned_path.map(|path| {
+            let mut owned_path = String::new();
+            if owned_path.push_str(&path).is_err() {
+                log::warn!("Unable to keep path");
+                invalid = true;
+            }
+            owned_path
+        });
+
+        RequestBuilder {
+            connection: self.connection,
+            headers: self.headers,
+            owned_headers,
+            fmt_headers: self.fmt_headers,
+            owned_path,
+            content_type: self.content_type,
+            method: self.method,
+            path: self.path,
+            handler: self.handler,
+            capture_reason: self.capture_reason,
+            invalid,
+        }
+    }
+
+    /// Request to switch to a different protocol, like `websocket`.
+    ///
+    /// This sets the `Upgrade` and `Connection` headers. If the server agrees, it responds with
+    /// `101 Switching Protocols`, and the request gets completed right after the response
+    /// header. Use [`Request::into_upgraded`] to take over the connection.
+    ///
+    /// Additional headers the protocol requires can be set using [`RequestBuilder::header`].
+    pub fn upgrade(self, protocol: &str) -> Self {
+        self.header(CONNECTION, "Upgrade").header(UPGRADE, protocol)
+    }
+
+    /// Set the content type of the request payload.
+    ///
+    /// This will be sent in addition to the headers set using [`RequestBuilder::headers`],
+    /// overriding a `Content-Type` header provided there.
+    pub fn content_type(mut self, content_type: &'req str) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    /// Set the content type to `application/json`.
+    pub fn json(self) -> Self {
+        self.content_type("application/json")
+    }
+
+    /// Set the content type to `text/plain`.
+    pub fn text(self) -> Self {
+        self.content_type("text/plain")
+    }
+
+    /// Set the content type to `application/octet-stream`.
+    pub fn octet_stream(self) -> Self {
+        self.content_type("application/octet-stream")
+    }
+
+    /// Set the handler that will process the response.
+    pub fn handler<RN: ResponseHandler>(self, handler: RN) -> RequestBuilder<'req, IN, RN, N, I> {
+        RequestBuilder {
+            connection: self.connection,
+            headers: self.headers,
+            owned_headers: self.owned_headers,
+            fmt_headers: self.fmt_headers,
+            owned_path: self.owned_path,
+            content_type: self.content_type,
+            method: self.method,
+            path: self.path,
+            handler,
+            capture_reason: self.capture_reason,
+            invalid: self.invalid,
+        }
+    }
+
+    /// Append a query parameter to the request target.
+    ///
+    /// Name and value will be percent-encoded, and appended using the correct separator. If the
+    /// path is not yet owned by the builder, it gets copied into the buffer first. If the target
+    /// doesn't fit into the buffer, or has no path, like `*` or the authority of a `CONNECT`
+    /// request, the request will not be sent.
+    ///
+    /// ~~~
+    /// # use drogue_http_client::*;
+    /// # use heapless::{consts::*, Vec};
+    /// # let mut sink = Vec::<u8, U1024>::new();
+    /// let req = HttpConnection::<U1024>::new()
+    ///     .begin("GET", "/events")
+    ///     .query_param("since", 1234)
+    ///     .query_param("filter", "a&b")
+    ///     .execute(&mut sink);
+    ///
+    /// assert!(sink.starts_with(b"GET /events?since=1234&filter=a%26b HTTP/1.1\r\n"));
+    /// ~~~
+    pub fn query_param<V>(mut self, name: &str, value: V) -> Self
+    where
+        V: core::fmt::Display,
+    {
+        if self.path == "*" || self.method == "CONNECT" {
+            log::warn!("Query on a request target without a path");
+            self.invalid = true;
+            return self;
+        }
+
+        let path = self.path;
+        let target = self.owned_path.get_or_insert_with(|| {
+            let mut target = String::new();
+            target.push_str(path).ok();
+            target
+        });
+        if target.len() < path.len() {
+            // the path itself didn't fit
+            self.invalid = true;
+        }
+
+        let separator = if target.contains('?') { '&' } else { '?' };
+        let result = target
+            .push(separator)
+            .map_err(|_| core::fmt::Error)
+            .and_then(|_| write!(PercentEncode(target), "{}", name))
+            .and_then(|_| target.push('=').map_err(|_| core::fmt::Error))
+            .and_then(|_| write!(PercentEncode(target), "{}", value));
+        if result.is_err() {
+            log::warn!("Query exceeds buffer capacity");
+            self.invalid = true;
+        }
+
+        self
+    }
+
+    /// Set the path, by formatting it into a buffer owned by the builder.
+    ///
+    /// If the path doesn't fit into the buffer, the request will not be sent.
+    pub fn path_fmt(mut self, path: core::fmt::Arguments) -> Self {
+        let mut owned_path = String::new();
+        if owned_path.write_fmt(path).is_err() {
+            log::warn!("Path exceeds buffer capacity");
+            self.invalid = true;
+        }
+        self.owned_path = Some(owned_path);
+        self
+    }
+
+    /// Set if the reason phrase of the response should be passed on to the handler.
+    ///
+    /// This overrides the default of the connection. If disabled, the handler will receive an
+    /// empty reason phrase.
+    pub fn capture_reason(mut self, capture_reason: bool) -> Self {
+        self.capture_reason = capture_reason;
+        self
+    }
+
+    /// Execute the request, without any request payload.
+    pub fn execute<S>(self, sink: &mut S) -> Request<IN, R, I>
+    where
+        S: Sink,
+    {
+        self.execute_with(sink, None)
+    }
+
+    /// Execute the request, optionally providing some payload.
+    ///
+    /// The request line and headers are directly written to the sink, in multiple calls, and the
+    /// sink gets flushed afterwards. If you need them to be sent together, wrap the sink in a
+    /// [`BufferedSink`](crate::BufferedSink).
+    pub fn execute_with<S>(self, sink: &mut S, payload: Option<&[u8]>) -> Request<IN, R, I>
+    where
+        S: Sink,
+    {
+        let payload = match payload {
+            Some(ref payload) => Payload::Parts(core::slice::from_ref(payload)),
+            None => Payload::None,
+        };
+        self.execute_payload(sink, payload)
+    }
+
+    /// Defer sending the request, until its response is read from the transport.
+    ///
+    /// See [`DeferredRequest`].
+    pub fn defer(self) -> DeferredRequest<'req, IN, R, N, I> {
+        self.defer_with(None)
+    }
+
+    /// Defer sending the request, optionally providing some payload, until its response is
+    /// read from the transport.
+    ///
+    /// See [`DeferredRequest`].
+    pub fn defer_with(self, payload: Option<&'req [u8]>) -> DeferredRequest<'req, IN, R, N, I> {
+        DeferredRequest {
+            builder: self,
+            payload,
+        }
+    }
+
+    /// Execute the request, sending the payload from multiple parts.
+    ///
+    /// The parts will be sent in sequence, as one payload. This allows to send e.g. a prefix,
+    /// a data buffer, and a suffix without the need to concatenate them first.
+    pub fn execute_with_parts<S>(self, sink: &mut S, parts: &[&[u8]]) -> Request<IN, R, I>
+    where
+        S: Sink,
+    {
+        self.execute_payload(sink, Payload::Parts(parts))
+    }
+
+    /// Execute the request, formatting the payload directly to the sink.
+    ///
+    /// The function will be called twice, once for evaluating the length of the payload, and
+    /// a second time for sending it. So it must produce the same output both times.
+    ///
+    /// ~~~
+    /// # use drogue_http_client::*;
+    /// # use heapless::{consts::*, Vec};
+    /// # let mut sink = Vec::<u8, U1024>::new();
+    /// let temp = 1.23;
+    /// let req = HttpConnection::<U1024>::new()
+    ///     .post("/telemetry")
+    ///     .execute_with_fmt(&mut sink, |w| write!(w, "{{\"temp\":{}}}", temp));
+    /// ~~~
+    pub fn execute_with_fmt<S, F>(self, sink: &mut S, f: F) -> Request<IN, R, I>
+    where
+        S: Sink,
+        F: Fn(&mut dyn Write) -> core::fmt::Result,
+    {
+        self.execute_payload(sink, Payload::Fmt(&f))
+    }
+
+    /// Execute the request, streaming a payload of unknown length.
+    ///
+    /// The payload will be sent using the "chunked" transfer encoding. Every write to the
+    /// provided writer will be sent as one chunk.
+    ///
+    /// ~~~
+    /// # use drogue_http_client::*;
+    /// # use heapless::{consts::*, Vec};
+    /// # let mut sink = Vec::<u8, U1024>::new();
+    /// use core::fmt::Write;
+    ///
+    /// let req = HttpConnection::<U1024>::new()
+    ///     .post("/telemetry")
+    ///     .execute_with_stream(&mut sink, |w| {
+    ///         for i in 0..10 {
+    ///             write!(w, "{},", i)?;
+    ///         }
+    ///         Ok(())
+    ///     });
+    /// ~~~
+    pub fn execute_with_stream<S, F>(self, sink: &mut S, f: F) -> Request<IN, R, I>
+    where
+        S: Sink,
+        F: FnOnce(&mut ChunkedWriter) -> core::fmt::Result,
+    {
+        let mut f = Some(f);
+        let mut f = |w: &mut ChunkedWriter| f.take().map_or(Ok(()), |f| f(w));
+        self.execute_payload(sink, Payload::Stream(&mut f))
+    }
+
+    /// Execute the request, sending the digest of the payload in the `Content-Digest` header.
+    ///
+    /// See [`digest`](crate::digest).
+    pub fn execute_with_digest<S, D>(
+        self,
+        sink: &mut S,
+        payload: &[u8],
+        digest: D,
+    ) -> Request<IN, R, I>
+    where
+        S: Sink,
+        D: BodyDigest,
+    {
+        self.digest_header(CONTENT_DIGEST, payload, digest)
+            .execute_with(sink, Some(payload))
+    }
+
+    /// Execute the request, sending the digest of the payload in the legacy `Content-MD5`
+    /// header.
+    ///
+    /// The header is intended for MD5 digests only, which must be provided by `md5`.
+    pub fn execute_with_md5<S, D>(self, sink: &mut S, payload: &[u8], md5: D) -> Request<IN, R, I>
+    where
+        S: Sink,
+        D: BodyDigest,
+    {
+        self.digest_header(CONTENT_MD5, payload, md5)
+            .execute_with(sink, Some(payload))
+    }
+
+    fn digest_header<D>(mut self, name: HeaderName, payload: &[u8], mut digest: D) -> Self
+    where
+        D: BodyDigest,
+    {
+        digest.update(payload);
+        let digest = digest.finish();
+
+        let mut value = String::<consts::U128>::new();
+        let result = if CONTENT_DIGEST == name {
+            write!(value, "{}=:", D::KEY)
+                .and_then(|_| write_base64(&mut value, digest.as_ref()))
+                .and_then(|_| value.write_char(':'))
+        } else {
+            write_base64(&mut value, digest.as_ref())
+        };
+
+        if result.is_err() {
+            log::error!("Digest exceeds buffer capacity, not sending");
+            self.invalid = true;
+            return self;
+        }
+        self.header(name, &value)
+    }
+
+    /// Execute the request, compressing the payload using gzip.
+    ///
+    /// Payloads of at least `threshold` bytes are compressed into a buffer of capacity `NC`,
+    /// and sent with the `Content-Encoding: gzip` header. Smaller payloads, and payloads which
+    /// don't shrink or fit into the buffer, are sent uncompressed.
+    ///
+    /// This function requires the `gzip` feature, see [`gzip`](crate::gzip).
+    #[cfg(feature = "gzip")]
+    pub fn execute_gzip<S, NC>(
+        self,
+        sink: &mut S,
+        payload: &[u8],
+        threshold: usize,
+    ) -> Request<IN, R, I>
+    where
+        S: Sink,
+        NC: ArrayLength<u8>,
+    {
+        if payload.len() >= threshold {
+            let mut compressed = Vec::<u8, NC>::new();
+            match crate::gzip::compress(payload, &mut compressed) {
+                Ok(()) if compressed.len() < payload.len() => {
+                    return self
+                        .header(CONTENT_ENCODING, "gzip")
+                        .execute_with(sink, Some(&compressed));
+                }
+                Ok(()) => debug!("Compression doesn't reduce the size, sending uncompressed"),
+                Err(()) => {
+                    log::warn!("Compressed payload exceeds the buffer, sending uncompressed")
+                }
+            }
+        }
+        self.execute_with(sink, Some(payload))
+    }
+
+    /// Split the request into a sending half, and the request, which receives the response.
+    ///
+    /// Instead of sending the request right away, it is serialized into a buffer of capacity
+    /// `NS`, which is sent using the [`SendHalf`]. This allows one task to transmit the request,
+    /// while another one pushes the received data to the request, e.g. from an interrupt. If
+    /// the request doesn't fit into the buffer, the request fails with [`Error::Send`].
+    ///
+    /// ~~~
+    /// # use drogue_http_client::*;
+    /// # use heapless::{consts::*, Vec};
+    /// let (mut tx, mut req) = HttpConnection::<U1024>::new()
+    ///     .post("/telemetry")
+    ///     .handler(BufferResponseHandler::<U64>::new())
+    ///     .split::<U256>(Some(b"{}"));
+    ///
+    /// // e.g. in the transmitting task
+    /// let mut uart = Vec::<u8, U256>::new();
+    /// while !tx.is_done() {
+    ///     tx.send_to(&mut uart).unwrap();
+    /// }
+    ///
+    /// // e.g. in the receiving interrupt
+    /// req.push_data(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n");
+    /// assert!(req.is_complete());
+    /// ~~~
+    pub fn split<NS>(self, payload: Option<&[u8]>) -> (SendHalf<NS>, Request<IN, R, I>)
+    where
+        NS: ArrayLength<u8>,
+    {
+        let mut data = Vec::<u8, NS>::new();
+        let request = self.execute_with(&mut data, payload);
+        if request.error().is_some() {
+            // don't send a partial request
+            data = Vec::new();
+        }
+        (SendHalf::new(data), request)
+    }
+
+    /// Continue a request, which was sent before, from the state saved by [`Request::suspend`],
+    /// without sending it again.
+    ///
+    /// The request must be built the same way as the original one, and the handler should
+    /// continue from where it was suspended. The request fails with the error, if the state
+    /// can't be restored, see [`ResponseParser::resume`].
+    ///
+    /// ~~~
+    /// use heapless::{consts::*, Vec};
+    /// use drogue_http_client::*;
+    ///
+    /// # let mut sink = Vec::<u8, U256>::new();
+    /// let mut req = HttpConnection::<U1024>::new()
+    ///     .begin("GET", "/firmware")
+    ///     .handler(BufferResponseHandler::<U64>::new())
+    ///     .execute(&mut sink);
+    /// req.push_data(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHel");
+    ///
+    /// let mut state = [0u8; 256];
+    /// let len = req.suspend(&mut state).unwrap();
+    /// let (_, handler) = req.into_parts();
+    ///
+    /// // after waking up again
+    /// let mut req = HttpConnection::<U1024>::new()
+    ///     .begin("GET", "/firmware")
+    ///     .handler(handler)
+    ///     .resume(&state[..len]);
+    /// req.push_data(b"lo");
+    ///
+    /// assert!(req.is_complete());
+    /// ~~~
+    pub fn resume(self, state: &[u8]) -> Request<IN, R, I> {
+        let mut request = self.into_request();
+        if let Err(err) = request.parser.resume(state) {
+            request.fail(err);
+        }
+        request
+    }
+
+    fn execute_payload<S>(mut self, sink: &mut S, payload: Payload) -> Request<IN, R, I>
+    where
+        S: Sink,
+    {
+        let mut target = String::<N>::new();
+        let path = match Self::target(
+            self.connection.config.base_path.as_deref(),
+            self.owned_path.as_deref().unwrap_or(self.path),
+            &mut target,
+        ) {
+            Some(path) if !self.invalid => path,
+            _ => {
+                log::error!("Invalid request, not sending");
+                let mut request = self.into_request();
+                request.parser.reject(Error::Send);
+                return request;
+            }
+        };
+
+        self.connection.interceptors.before_send(&mut RequestParts {
+            method: self.method,
+            path,
+            headers: self.headers,
+            owned_headers: &mut self.owned_headers,
+            payload: payload.parts(),
+        });
+
+        let head = RequestHead {
+            version: self.connection.config.version,
+            method: self.method,
+            path,
+            headers: self.headers,
+            owned_headers: self.owned_headers.entries(),
+            fmt_headers: &self.fmt_headers,
+            content_type: self.content_type,
+        };
+        let mut sent = true;
+        if self.connection.config.version == Version::Http10
+            && matches!(payload, Payload::Stream(_))
+        {
+            log::error!("Chunked payloads require HTTP/1.1, not sending");
+            sent = false;
+        } else {
+            increment(&mut self.connection.metrics.requests, 1);
+            self.connection
+                .interceptors
+                .observe_event(ConnectionEvent::SendStarted);
+            if self.connection.send_request(sink, &head, payload).is_err() {
+                increment(&mut self.connection.metrics.transport_errors, 1);
+                sent = false;
+            }
         }
+        let mut request = self.into_request();
+        if !sent {
+            // the request might have been sent partially
+            request.fail(Error::Send);
+        } else {
+            request
+                .parser
+                .push_retained(&mut request.connection.observer());
+        }
+        request
     }
 
-    /// Begin a new POST HTTP request.
-    pub fn post<'req>(self, path: &'static str) -> RequestBuilder<'req, IN, NoOpResponseHandler> {
-        self.begin("POST", path)
-    }
-
-    pub(crate) fn send_request<S, OUT>(
-        &mut self,
-        sink: &mut S,
-        method: &str,
-        path: &str,
-        headers: Option<&[(&str, &str)]>,
-        payload: Option<&[u8]>,
-    ) -> Result<(), ()>
+    /// Prepare the request, rendering its request line and headers into a buffer of capacity
+    /// `NP`, for executing it repeatedly.
+    ///
+    /// The interceptors are called once, when preparing the request. As the payload isn't known
+    /// yet, they can't take it into account. The response handler is not used.
+    ///
+    /// Fails with [`Error::Send`], if the request is invalid, or the buffer is too small. The
+    /// connection is returned in any case.
+    ///
+    /// See [`PreparedRequest`].
+    pub fn prepare<NP>(mut self) -> (HttpConnection<IN, I>, Result<PreparedRequest<NP>, Error>)
     where
-        S: Sink,
-        OUT: ArrayLength<u8>,
+        NP: ArrayLength<u8>,
     {
-        let mut out = Vec::<u8, OUT>::new();
+        let mut target = String::<N>::new();
+        let path = match Self::target(
+            self.connection.config.base_path.as_deref(),
+            self.owned_path.as_deref().unwrap_or(self.path),
+            &mut target,
+        ) {
+            Some(path) if !self.invalid => path,
+            _ => {
+                log::error!("Invalid request, not preparing");
+                return (self.connection, Err(Error::Send));
+            }
+        };
 
-        // create headers
-        self.create_request_headers(&mut out, method, path, headers, payload.map(|b| b.len()))
-            .map_err(|_| ())?;
+        self.connection.interceptors.before_send(&mut RequestParts {
+            method: self.method,
+            path,
+            headers: self.headers,
+            owned_headers: &mut self.owned_headers,
+            payload: None,
+        });
 
-        // send headers
-        sink.send(&out)?;
+        let version = self.connection.config.version;
+        let head = RequestHead {
+            version,
+            method: self.method,
+            path,
+            headers: self.headers,
+            owned_headers: self.owned_headers.entries(),
+            fmt_headers: &self.fmt_headers,
+            content_type: self.content_type,
+        };
+        let mut buffer = Vec::<u8, NP>::new();
+        let w = &mut SinkWrapper(&mut buffer);
+        // any framing, to skip the conflicting headers
+        let result =
+            write_request_line(w, version, self.method, path, Framing::None).and_then(|_| {
+                HttpConnection::<IN, I>::create_header_fields(w, &head, Framing::Chunked)
+            });
 
-        // send payload
-        if let Some(payload) = payload {
-            sink.send(payload)?;
+        match result {
+            Ok(_) => (self.connection, Ok(PreparedRequest { head: buffer })),
+            Err(_) => {
+                log::warn!("Request head exceeds buffer capacity");
+                (self.connection, Err(Error::Send))
+            }
         }
-
-        Ok(())
     }
 
-    fn create_request_headers(
-        &self,
-        w: &mut dyn core::fmt::Write,
-        method: &str,
-        path: &str,
-        headers: Option<&[(&str, &str)]>,
-        content_length: Option<usize>,
-    ) -> Result<(), core::fmt::Error> {
-        write!(w, "{} {} HTTP/1.1\r\n", method, path)?;
-        if let Some(headers) = headers {
-            if let Some(content_length) = content_length {
-                write!(w, "{}: {}\r\n", "Content-Length", content_length)?;
+    /// The request target, prefixed with the base path. `None` if the base path is invalid, or
+    /// the target doesn't fit into the buffer.
+    fn target<'p>(
+        base_path: Option<&str>,
+        path: &'p str,
+        target: &'p mut String<N>,
+    ) -> Option<&'p str> {
+        match base_path {
+            Some(base_path) if !base_path.is_empty() && path.starts_with('/') => {
+                if target
+                    .push_str(base_path)
+                    .and_then(|_| target.push_str(path))
+                    .is_err()
+                {
+                    log::warn!("Path exceeds buffer capacity");
+                    return None;
+                }
+                Some(target.as_str())
             }
-            for header in headers {
-                write!(w, "{}: {}\r\n", header.0, header.1)?;
+            Some(_) => Some(path),
+            None => {
+                log::error!("Invalid base path, not sending");
+                None
             }
         }
-        write!(w, "\r\n")?;
+    }
 
-        Ok(())
+    /// Create the request, for processing its response.
+    fn into_request(self) -> Request<IN, R, I> {
+        self.connection
+            .into_request(self.handler, self.method, self.capture_reason)
+    }
+}
+
+/// A request, which was prepared for executing it repeatedly, with a different payload each time.
+///
+/// The request line and headers are rendered into a buffer of capacity `N` once, using
+/// [`RequestBuilder::prepare`]. Executing the request, using
+/// [`HttpConnection::execute_prepared`], only adds the `Content-Length` of the payload. This
+/// saves building, and formatting, the request every time, e.g. when sending telemetry data
+/// every few seconds.
+///
+/// ~~~
+/// use heapless::{consts::*, Vec};
+/// use drogue_http_client::*;
+///
+/// let (mut con, prepared) = HttpConnection::<U1024>::new()
+///     .post("/telemetry")
+///     .header("X-Device", "sensor-1")
+///     .json()
+///     .prepare::<U128>();
+/// let prepared = prepared.unwrap();
+///
+/// for temp in &["21.5", "21.7"] {
+///     let mut sink = Vec::<u8, U1024>::new();
+///     let mut req = con.execute_prepared(
+///         &prepared,
+///         NoOpResponseHandler,
+///         &mut sink,
+///         Some(temp.as_bytes()),
+///     );
+///     req.push_data(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n");
+///     con = req.try_complete().unwrap().0;
+///
+///     assert!(sink.starts_with(
+///         b"POST /telemetry HTTP/1.1\r\nContent-Type: application/json\r\nX-Device: sensor-1\r\nContent-Length: 4\r\n\r\n"
+///     ));
+///     assert!(sink.ends_with(temp.as_bytes()));
+/// }
+/// ~~~
+pub struct PreparedRequest<N = consts::U256>
+where
+    N: ArrayLength<u8>,
+{
+    // the request line and headers, without the framing and the empty line
+    head: Vec<u8, N>,
+}
+
+impl<N> PreparedRequest<N>
+where
+    N: ArrayLength<u8>,
+{
+    /// The rendered request line and headers, without the header announcing the framing of the
+    /// payload, and without the empty line ending the head.
+    pub fn head(&self) -> &[u8] {
+        &self.head
     }
 
-    pub(crate) fn closed(&mut self) {
-        // FIXME: mark as closed
+    /// The method of the request.
+    pub fn method(&self) -> &str {
+        let end = self.head.iter().position(|b| *b == b' ').unwrap_or(0);
+        // copied from a string, when preparing the request
+        core::str::from_utf8(&self.head[..end]).unwrap_or_default()
     }
 }
 
-/// A request builder, which helps to gather all required information before executing the request.
-pub struct RequestBuilder<'req, IN, R>
+/// The request of a [`DeferredRequest`], together with the result of piping its response.
+pub type Piped<IN, R, I, E> = (Request<IN, R, I>, Result<Progress, PipeError<E>>);
+
+/// A request, which is sent once its response is read from the transport.
+///
+/// This allows using a single transport, which is both [`Sink`] and [`Source`], for sending the
+/// request and receiving the response in one go.
+///
+/// ~~~
+/// use heapless::consts::*;
+/// use drogue_http_client::*;
+/// use drogue_http_client::loopback::*;
+///
+/// let mut lo = Loopback::<U1024, _>::new(CannedResponse::new(200, "OK").body(b"ok"));
+///
+/// let (req, result) = HttpConnection::<U1024>::new()
+///     .post("/")
+///     .handler(BufferResponseHandler::<U64>::new())
+///     .defer_with(Some(b"{}"))
+///     .pipe_data(&mut lo);
+///
+/// assert!(result.unwrap().complete);
+/// assert_eq!(req.try_complete().unwrap().1.payload(), b"ok");
+/// ~~~
+pub struct DeferredRequest<'req, IN, R, N, I>
 where
     IN: ArrayLength<u8>,
     R: ResponseHandler,
+    N: ArrayLength<u8>,
+    I: Interceptor,
 {
-    connection: HttpConnection<IN>,
-    method: &'static str,
-    path: &'static str,
-    headers: Option<&'req [(&'req str, &'req str)]>,
-    handler: R,
+    builder: RequestBuilder<'req, IN, R, N, I>,
+    payload: Option<&'req [u8]>,
 }
 
-impl<'req, IN, R> RequestBuilder<'req, IN, R>
+impl<'req, IN, R, N, I> DeferredRequest<'req, IN, R, N, I>
 where
     IN: ArrayLength<u8>,
     R: ResponseHandler,
+    N: ArrayLength<u8>,
+    I: Interceptor,
 {
-    /// Set the HTTP headers to send.
-    pub fn headers(mut self, headers: &'req [(&'req str, &'req str)]) -> Self {
-        self.headers = Some(headers);
-        self
+    /// Send the request to the transport, and forward data from it to the request, until the
+    /// request is completed, a read error occurred, or processing the response failed.
+    ///
+    /// The request is returned in any case, together with the result of piping the data. See
+    /// [`Source::pipe_data`].
+    pub fn pipe_data<T>(self, transport: &mut T) -> Piped<IN, R, I, T::Error>
+    where
+        T: Sink + Source,
+    {
+        self.pipe_data_limited(transport, Budget::unlimited())
     }
 
-    /// Set the handler that will process the response.
-    pub fn handler<RN: ResponseHandler>(self, handler: RN) -> RequestBuilder<'req, IN, RN> {
-        RequestBuilder {
-            connection: self.connection,
-            headers: self.headers,
-            method: self.method,
-            path: self.path,
-            handler,
+    /// Send the request to the transport, and forward data from it to the request, until the
+    /// budget is exhausted.
+    ///
+    /// Use [`Source::pipe_data_limited`] to continue piping the data, in case the request is
+    /// not complete yet.
+    pub fn pipe_data_limited<T>(
+        self,
+        transport: &mut T,
+        budget: Budget,
+    ) -> Piped<IN, R, I, T::Error>
+    where
+        T: Sink + Source,
+    {
+        let mut request = self.builder.execute_with(transport, self.payload);
+        let result = match request.error() {
+            Some(err) => Err(err.into()),
+            None => transport.pipe_data_limited(&mut request, budget),
+        };
+        (request, result)
+    }
+}
+
+/// The payload of a request.
+pub(crate) enum Payload<'p> {
+    None,
+    Parts(&'p [&'p [u8]]),
+    Fmt(&'p dyn Fn(&mut dyn Write) -> core::fmt::Result),
+    Stream(&'p mut dyn FnMut(&mut ChunkedWriter) -> core::fmt::Result),
+}
+
+/// The version of the HTTP protocol.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Version {
+    /// HTTP/1.0
+    Http10,
+    /// HTTP/1.1
+    #[default]
+    Http11,
+}
+
+impl Version {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
         }
     }
+}
 
-    /// Execute the request, without any request payload.
-    pub fn execute<S, OUT>(self, sink: &mut S) -> Request<IN, R>
-    where
-        S: Sink,
-        OUT: ArrayLength<u8>,
-    {
-        self.execute_with::<S, OUT>(sink, None)
+impl core::fmt::Display for Version {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
     }
+}
 
-    /// Execute the request, optionally providing some payload.
-    pub fn execute_with<S, OUT>(mut self, sink: &mut S, payload: Option<&[u8]>) -> Request<IN, R>
+#[cfg(feature = "ufmt")]
+impl ufmt::uDisplay for Version {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
     where
-        S: Sink,
-        OUT: ArrayLength<u8>,
+        W: ufmt::uWrite + ?Sized,
     {
-        // FIXME: handle error
-        self.connection
-            .send_request::<S, OUT>(sink, self.method, self.path, self.headers, payload);
-        let connection = self.connection;
-        let handler = self.handler;
-        Request {
-            connection,
-            handler,
-            state: State::Header,
-            processed_bytes: 0,
+        f.write_str(self.as_str())
+    }
+}
+
+/// The framing of the request payload.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Framing {
+    /// There is no payload.
+    None,
+    /// The payload has the length, announced using `Content-Length`.
+    Length(usize),
+    /// The payload uses the "chunked" transfer encoding.
+    Chunked,
+}
+
+/// A writer of the request head, using `ufmt` if enabled.
+#[cfg(not(feature = "ufmt"))]
+pub(crate) use core::fmt::Write as HeadWrite;
+
+/// A writer of the request head, using `ufmt` if enabled.
+#[cfg(feature = "ufmt")]
+pub(crate) trait HeadWrite: ufmt::uWrite<Error = core::fmt::Error> {}
+
+#[cfg(feature = "ufmt")]
+impl<W> HeadWrite for W where W: ufmt::uWrite<Error = core::fmt::Error> + ?Sized {}
+
+/// Write part of the request head, using `ufmt` if enabled.
+#[cfg(not(feature = "ufmt"))]
+macro_rules! write_head {
+    ($($arg:tt)*) => {
+        write!($($arg)*)
+    };
+}
+
+/// Write part of the request head, using `ufmt` if enabled.
+#[cfg(feature = "ufmt")]
+macro_rules! write_head {
+    ($($arg:tt)*) => {
+        ufmt::uwrite!($($arg)*)
+    };
+}
+
+/// Use a `core::fmt` writer with `ufmt`.
+#[cfg(feature = "ufmt")]
+struct FmtWriter<'w, W: Write + ?Sized>(&'w mut W);
+
+#[cfg(feature = "ufmt")]
+impl<'w, W: Write + ?Sized> ufmt::uWrite for FmtWriter<'w, W> {
+    type Error = core::fmt::Error;
+
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.write_str(s)
+    }
+}
+
+/// Write the head of a request, up to the empty line which ends it.
+///
+/// This writes the request line, the header announcing the framing of the payload, and the
+/// headers. Headers which conflict with the framing are skipped. The head can be rendered into
+/// a buffer, to send it over some other transport, or written to a counter to get its size.
+///
+/// ~~~
+/// use heapless::{consts::*, String};
+/// use drogue_http_client::*;
+///
+/// let mut head = String::<U128>::new();
+/// write_request(
+///     &mut head,
+///     "POST",
+///     "/telemetry",
+///     &[("Content-Type", "application/json")],
+///     Framing::Length(2),
+/// )
+/// .unwrap();
+///
+/// assert_eq!(
+///     head,
+///     "POST /telemetry HTTP/1.1\r\nContent-Length: 2\r\nContent-Type: application/json\r\n\r\n"
+/// );
+/// ~~~
+pub fn write_request<W>(
+    w: &mut W,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    framing: Framing,
+) -> core::fmt::Result
+where
+    W: Write + ?Sized,
+{
+    #[cfg(feature = "ufmt")]
+    let w = &mut FmtWriter(w);
+    write_request_line(w, Version::Http11, method, path, framing)?;
+    write_headers(w, headers.iter().copied(), framing)?;
+    write_head!(w, "\r\n")
+}
+
+/// Write the request line, and the header announcing the framing of the payload.
+fn write_request_line<W>(
+    w: &mut W,
+    version: Version,
+    method: &str,
+    path: &str,
+    framing: Framing,
+) -> core::fmt::Result
+where
+    W: HeadWrite + ?Sized,
+{
+    write_head!(w, "{} {} {}\r\n", method, path, version)?;
+    write_framing(w, framing)
+}
+
+/// Write the header announcing the framing of the payload.
+fn write_framing<W>(w: &mut W, framing: Framing) -> core::fmt::Result
+where
+    W: HeadWrite + ?Sized,
+{
+    match framing {
+        Framing::None => {}
+        Framing::Length(content_length) => {
+            write_head!(w, "{}: {}\r\n", CONTENT_LENGTH, content_length)?
+        }
+        Framing::Chunked => write_head!(w, "{}: chunked\r\n", TRANSFER_ENCODING)?,
+    }
+    Ok(())
+}
+
+/// Write the headers, skipping the ones conflicting with the framing.
+fn write_headers<'h, W, H>(w: &mut W, headers: H, framing: Framing) -> core::fmt::Result
+where
+    W: HeadWrite + ?Sized,
+    H: Iterator<Item = (&'h str, &'h str)>,
+{
+    for (name, value) in headers {
+        if conflicts_with_framing(framing, name) {
+            continue;
+        }
+        write_head!(w, "{}: {}\r\n", name, value)?;
+    }
+    Ok(())
+}
+
+/// Check if the header conflicts with the framing of the payload, and must be skipped.
+fn conflicts_with_framing(framing: Framing, name: &str) -> bool {
+    let conflicts = framing != Framing::None
+        && (CONTENT_LENGTH.matches(name) || TRANSFER_ENCODING.matches(name));
+    if conflicts {
+        log::warn!("Skipping header conflicting with payload framing: {}", name);
+    }
+    conflicts
+}
+
+/// A writer of header values, failing on line breaks.
+struct HeaderValueWriter<'w, W: Write + ?Sized>(&'w mut W);
+
+impl<'w, W: Write + ?Sized> Write for HeaderValueWriter<'w, W> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if s.bytes().any(|b| b == b'\r' || b == b'\n') {
+            log::warn!("Header value contains a line break");
+            return Err(core::fmt::Error);
+        }
+        self.0.write_str(s)
+    }
+}
+
+impl<'p> Payload<'p> {
+    /// The parts of the payload, if it is known before sending.
+    fn parts(&self) -> Option<&'p [&'p [u8]]> {
+        match self {
+            Payload::None => Some(&[]),
+            Payload::Parts(parts) => Some(parts),
+            Payload::Fmt(_) | Payload::Stream(_) => None,
+        }
+    }
+
+    fn framing(&self) -> Framing {
+        match self {
+            Payload::None => Framing::None,
+            Payload::Parts(parts) => Framing::Length(parts.iter().map(|part| part.len()).sum()),
+            Payload::Fmt(f) => {
+                let mut counter = Counter(0);
+                // an error will be reported again when sending
+                f(&mut counter).ok();
+                Framing::Length(counter.0)
+            }
+            Payload::Stream(_) => Framing::Chunked,
+        }
+    }
+}
+
+/// A writer, percent-encoding all but the unreserved characters.
+struct PercentEncode<'a, W: Write>(&'a mut W);
+
+impl<'a, W: Write> Write for PercentEncode<'a, W> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for b in s.bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    self.0.write_char(b as char)?
+                }
+                _ => write!(self.0, "%{:02X}", b)?,
+            }
         }
+        Ok(())
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-enum State {
-    Header,
-    Payload(usize),
-    Complete,
-    UnlimitedPayload,
+/// A writer, only counting the bytes written.
+pub(crate) struct Counter(pub(crate) usize);
+
+impl Write for Counter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
 }
 
 /// The HTTP response header.
@@ -185,178 +1868,365 @@ pub struct Response<'a> {
     pub version: u8,
     pub code: u16,
     pub reason: &'a str,
+    pub headers: ResponseHeaders<'a>,
+}
+
+impl core::fmt::Display for Response<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "HTTP/1.{} {}", self.version, self.code)?;
+        if !self.reason.is_empty() {
+            write!(f, " {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Response<'_> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "HTTP/1.{=u8} {=u16}", self.version, self.code);
+        if !self.reason.is_empty() {
+            defmt::write!(f, " {=str}", self.reason);
+        }
+    }
 }
 
 /// The ongoing HTTP request.
-pub struct Request<IN, R>
+pub struct Request<IN, R, I = ()>
 where
     IN: ArrayLength<u8>,
     R: ResponseHandler,
+    I: Interceptor,
 {
-    // connection
-    pub(crate) connection: HttpConnection<IN>,
-    // current handler
-    handler: R,
-    // current state
-    state: State,
-    // processed bytes
-    processed_bytes: usize,
+    // connection, its inbound buffer is used by the parser
+    pub(crate) connection: HttpConnection<IN, I>,
+    parser: ResponseParser<IN, R>,
 }
 
-impl<IN, R> Request<IN, R>
+impl<IN, R, I> Request<IN, R, I>
 where
     IN: ArrayLength<u8>,
     R: ResponseHandler,
+    I: Interceptor,
 {
     /// Check if the request is completely processed.
     pub fn is_complete(&self) -> bool {
-        match self.state {
-            State::Complete => true,
-            _ => false,
-        }
+        self.parser.is_complete()
     }
 
-    fn push(&mut self, data: Result<Option<&[u8]>, ()>) {
-        log::debug!("Pushing data: {:?}", data.map(|o| o.map(|b| from_utf8(b))),);
-        match self.state {
-            State::Header => self.push_header(data),
-            State::Payload(size) => self.push_sized_payload(size, data),
-            State::UnlimitedPayload => self.push_payload(data),
-            State::Complete => self.push_complete_payload(data),
-        }
+    /// Check if the server switched to a different protocol.
+    ///
+    /// See [`RequestBuilder::upgrade`].
+    pub fn is_upgraded(&self) -> bool {
+        self.parser.is_upgraded()
     }
 
-    fn push_header(&mut self, data: Result<Option<&[u8]>, ()>) {
-        log::debug!("Current data: {:?}", from_utf8(&self.connection.inbound));
-
-        match data {
-            Ok(Some(data)) => {
-                self.connection.inbound.extend_from_slice(data).ok();
+    /// Get the error, if processing the response failed.
+    ///
+    /// Once failed, no more data will be processed.
+    pub fn error(&self) -> Option<Error> {
+        self.parser.error()
+    }
 
-                let mut headers = [httparse::EMPTY_HEADER; 16];
-                let mut response = httparse::Response::new(&mut headers);
+    /// Check if the server closes the connection after the response.
+    ///
+    /// The connection can't be reused then, and is poisoned once the request is completed. See
+    /// [`ResponseParser::is_close`].
+    pub fn is_close(&self) -> bool {
+        self.parser.is_close()
+    }
 
-                match response.parse(&self.connection.inbound) {
-                    Ok(Status::Complete(len)) => {
-                        log::debug!("Completed({})", len);
+    fn fail(&mut self, err: Error) {
+        self.parser.fail(err, &mut self.connection.observer());
+    }
 
-                        let content_size = response
-                            .headers
-                            .iter()
-                            .find(|e| e.name.eq_ignore_ascii_case("content-length"));
+    /// Push more inbound data to the HTTP processing.
+    pub fn push_data(&mut self, data: &[u8]) {
+        self.parser
+            .push_data_observed(data, &mut self.connection.observer())
+    }
 
-                        // eval next state
-                        // FIXME: handle error
-                        self.state = match content_size {
-                            Some(header) => from_utf8(header.value)
-                                .map_err(|_| ())
-                                .and_then(|v| v.parse::<usize>().map_err(|_| ()))
-                                .map_or(State::UnlimitedPayload, |size| State::Payload(size)),
-                            None => State::UnlimitedPayload,
-                        };
+    /// Push more inbound data to the HTTP processing, returning the number of bytes which were
+    /// accepted.
+    ///
+    /// Data which can't be buffered, as the handler doesn't consume the payload, is left to the
+    /// caller, instead of being dropped. Fails with the error, once processing the response
+    /// failed. See [`ResponseParser::try_push_data`].
+    pub fn try_push_data(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.parser
+            .try_push_data_observed(data, &mut self.connection.observer())
+    }
 
-                        // log::debug!("Headers: {:?}", response.headers);
-                        log::debug!("Continue with: {:?}", self.state);
+    /// Get a writable part of the inbound buffer, of up to `max` bytes, for receiving data
+    /// directly into it, e.g. using DMA.
+    ///
+    /// Once the data was received, it must be processed by calling [`Request::commit`], before
+    /// pushing any other data. Calling this function again, before that, returns the same part
+    /// of the buffer. The slice is empty if the buffer is full, as the handler did not consume
+    /// the pending payload yet.
+    ///
+    /// ~~~
+    /// use heapless::{consts::*, Vec};
+    /// use drogue_http_client::*;
+    ///
+    /// # let mut sink = Vec::<u8, U256>::new();
+    /// let mut req = HttpConnection::<U1024>::new()
+    ///     .begin("GET", "/")
+    ///     .handler(BufferResponseHandler::<U64>::new())
+    ///     .execute(&mut sink);
+    ///
+    /// let data = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+    /// let buffer = req.recv_grant(512);
+    /// buffer[..data.len()].copy_from_slice(data);
+    /// req.commit(data.len());
+    ///
+    /// assert!(req.is_complete());
+    /// ~~~
+    pub fn recv_grant(&mut self, max: usize) -> &mut [u8] {
+        self.parser.recv_grant(max)
+    }
 
-                        // handle response
-                        self.handler.response(Response {
-                            version: response.version.unwrap_or_default(),
-                            code: response.code.unwrap_or_default(),
-                            reason: response.reason.unwrap_or_default(),
-                        });
+    /// Process `len` bytes, which were received into the part of the buffer returned by
+    /// [`Request::recv_grant`].
+    ///
+    /// The remaining part of the buffer is released, committing zero bytes just releases the
+    /// buffer.
+    pub fn commit(&mut self, len: usize) {
+        self.parser
+            .commit_observed(len, &mut self.connection.observer())
+    }
 
-                        // clear connection buffer
+    /// Retry delivering payload, which the handler did not consume yet.
+    pub fn redeliver(&mut self) {
+        self.parser
+            .redeliver_observed(&mut self.connection.observer())
+    }
 
-                        let buffer_len = self.connection.inbound.len();
-                        let data_len = data.len();
+    /// The number of payload bytes, which are buffered as the handler did not consume them yet.
+    pub fn pending_payload(&self) -> usize {
+        self.parser.pending_payload()
+    }
 
-                        log::debug!("Len = {}, dLen = {}, bLen = {}", len, data_len, buffer_len);
+    /// Save the state of processing the response, returning the number of bytes written to
+    /// `out`.
+    ///
+    /// The request can be continued later, using [`RequestBuilder::resume`]. See
+    /// [`ResponseParser::suspend`].
+    pub fn suspend(&self, out: &mut [u8]) -> Result<usize, Error> {
+        self.parser.suspend(out)
+    }
 
-                        // push on remaining data
+    /// Record a failure of the transport, when reading data.
+    pub(crate) fn transport_failed(&mut self) {
+        increment(&mut self.connection.metrics.transport_errors, 1);
+    }
 
-                        let start = len - (buffer_len - data_len);
-                        let rem_data = &data[start..];
+    /// Notify the HTTP processing that the source has closed.
+    pub fn push_close(&mut self) {
+        self.parser
+            .push_close_observed(&mut self.connection.observer())
+    }
 
-                        log::debug!(
-                            "Push bytes [{}..] after header to payload processing",
-                            start
-                        );
+    /// Stop processing the request, giving back the connection and the handler.
+    ///
+    /// This also gives them back if the response is not complete, in which case the handler
+    /// might not even have received the status code. The data buffered for the next response
+    /// stays with the connection. Use [`Request::try_complete`] or [`Request::into_completed`]
+    /// to only complete finished responses.
+    pub fn into_parts(mut self) -> (HttpConnection<IN, I>, R) {
+        self.parser.release_grant();
+        if self.parser.is_close() {
+            // no further request can be sent
+            self.connection.poisoned = true;
+        }
+        let (handler, inbound) = self.parser.into_parts();
+        self.connection.inbound = inbound;
+        (self.connection, handler)
+    }
 
-                        self.push(Ok(Some(rem_data)));
+    /// Stop processing the request, gives back the handler and connection.
+    ///
+    /// This also gives them back if the response is not complete, in which case the handler
+    /// might not even have received the status code.
+    #[deprecated(
+        note = "use `try_complete` or `into_completed`, or `into_parts` to give up on an incomplete response"
+    )]
+    pub fn complete(self) -> (HttpConnection<IN, I>, R) {
+        self.into_parts()
+    }
 
-                        // clear buffer
+    /// Continue with the completed request, once the response was processed completely.
+    ///
+    /// Unlike [`Request::into_parts`], this can't give back the handler of a response which is
+    /// still in progress, or which failed. If the response isn't complete, or the server switched
+    /// protocols, the request is given back.
+    #[allow(clippy::result_large_err)]
+    pub fn into_completed(self) -> Result<CompletedRequest<IN, R, I>, Self> {
+        if !self.is_complete() || self.is_upgraded() {
+            return Err(self);
+        }
+        Ok(CompletedRequest { request: self })
+    }
 
-                        self.connection.inbound.clear();
-                    }
-                    Ok(Status::Partial) => {}
-                    Err(e) => {
-                        log::info!("Parse error: {:?}", e);
-                    }
-                }
-            }
-            Ok(None) => {
-                // FIXME: handle close
-            }
-            Err(_) => {
-                // FIXME: handle error
-            }
+    /// Take over the connection, after the server switched to a different protocol, or
+    /// established the tunnel of a `CONNECT` request.
+    ///
+    /// Returns the connection, the handler, and the data which was received after the response
+    /// header, and so belongs to the new protocol. The connection is poisoned, as it is no
+    /// longer in sync with the transport. If the server didn't switch protocols, the request
+    /// is given back.
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    pub fn into_upgraded(self) -> Result<(HttpConnection<IN, I>, R, Vec<u8, IN>), Self> {
+        if !self.is_upgraded() {
+            return Err(self);
         }
+
+        let (mut connection, handler) = self.into_parts();
+        let data = core::mem::take(&mut connection.inbound).into_vec();
+        connection.poisoned = true;
+        Ok((connection, handler, data))
     }
 
-    fn push_payload(&mut self, data: Result<Option<&[u8]>, ()>) {
-        log::debug!("More data: {:?}", data);
+    /// Stop processing the request, giving back the connection, and the output of the handler.
+    ///
+    /// ~~~
+    /// # use drogue_http_client::*;
+    /// # use heapless::{consts::*, Vec};
+    /// # let mut sink = Vec::<u8, U1024>::new();
+    /// let mut req = HttpConnection::<U1024>::new()
+    ///     .begin("GET", "/")
+    ///     .handler(BufferResponseHandler::<U64>::new())
+    ///     .execute(&mut sink);
+    /// req.push_data(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+    ///
+    /// let (con, result) = req.finish();
+    /// let (code, payload) = result.unwrap();
+    /// assert_eq!((code, payload.as_ref()), (200, b"ok".as_ref()));
+    /// ~~~
+    pub fn finish(self) -> (HttpConnection<IN, I>, Result<R::Output, Error>)
+    where
+        R: FinishResponse,
+    {
+        let error = self.error();
+        let (connection, handler) = self.into_parts();
+        let result = match error {
+            Some(err) => Err(err),
+            None => handler.finish(),
+        };
+        (connection, result)
+    }
 
-        self.handler.more_payload(data);
+    /// Get the reason why the response is not complete, or `None` if it is.
+    pub fn incomplete_reason(&self) -> Option<IncompleteReason> {
+        self.parser.incomplete_reason()
     }
 
-    fn push_complete_payload(&mut self, data: Result<Option<&[u8]>, ()>) {
-        log::debug!("More data (overflow): {:?}", data);
-        match data {
-            Ok(Some(data)) => {
-                // FIXME: handle error
-                self.connection.inbound.extend_from_slice(data);
-            }
-            Ok(None) | Err(_) => self.connection.closed(),
-        }
-    }
-
-    fn push_sized_payload(&mut self, expected_bytes: usize, data: Result<Option<&[u8]>, ()>) {
-        log::debug!("More data (sized): {:?}", data);
-
-        match data {
-            Ok(Some(data)) => {
-                let len = data.len();
-                let rem = expected_bytes - self.processed_bytes;
-                if len >= rem {
-                    self.handler.more_payload(Ok(Some(&data[0..rem])));
-                    // mark as complete
-                    self.state = State::Complete;
-                    // notify about complete
-                    self.handler.more_payload(Ok(None));
-                } else {
-                    self.handler.more_payload(Ok(Some(data)));
-                    self.processed_bytes += len;
-                }
-            }
-            Ok(None) => {
-                // FIXME: check for error
-            }
-            Err(_) => {}
+    /// Stop processing the request, once the response is complete.
+    ///
+    /// If the response is not complete, or processing it failed, the request is given back
+    /// with the reason. It can still be stopped using [`Request::into_parts`], to get back the
+    /// handler and connection.
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    pub fn try_complete(self) -> Result<(HttpConnection<IN, I>, R), (Self, IncompleteReason)> {
+        match self.incomplete_reason() {
+            None => Ok(self.into_parts()),
+            Some(reason) => Err((self, reason)),
         }
     }
+}
 
-    /// Push more inbound data to the HTTP processing.
-    pub fn push_data(&mut self, data: &[u8]) {
-        self.push(Ok(Some(data)))
+impl<IN, R, I> core::fmt::Debug for Request<IN, R, I>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+    I: Interceptor,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Request")
+            .field("incomplete_reason", &self.incomplete_reason())
+            .field("upgraded", &self.is_upgraded())
+            .finish()
     }
+}
 
-    /// Notify the HTTP processing that the source has closed.
-    pub fn push_close(&mut self) {
-        self.push(Ok(None))
+/// A request, whose response was processed completely.
+///
+/// See [`Request::into_completed`]. Data received after the response belongs to the next one,
+/// it can only be retained for the next request on the connection.
+pub struct CompletedRequest<IN, R, I = ()>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+    I: Interceptor,
+{
+    request: Request<IN, R, I>,
+}
+
+impl<IN, R, I> CompletedRequest<IN, R, I>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+    I: Interceptor,
+{
+    /// Get the handler.
+    pub fn handler(&self) -> &R {
+        self.request.parser.handler()
     }
 
-    /// Stop processing the request, gives back the handler and connection.
-    pub fn complete(self) -> (HttpConnection<IN>, R) {
-        (self.connection, self.handler)
+    /// The timings of the request, if recorded by an interceptor, like
+    /// [`Timing`](crate::Timing).
+    pub fn timings(&self) -> Option<Timings> {
+        self.request.connection.interceptors.request_timings()
+    }
+
+    /// Keep data, which was received after the response, for the next request.
+    ///
+    /// Fails with [`Error::Overflow`] if the data doesn't fit into the inbound buffer, in which
+    /// case nothing is kept.
+    pub fn push_overflow(&mut self, data: &[u8]) -> Result<(), Error> {
+        let request = &mut self.request;
+        request
+            .parser
+            .retain(data, &mut request.connection.observer())
+    }
+
+    /// Give back the handler and connection.
+    pub fn complete(self) -> (HttpConnection<IN, I>, R) {
+        self.request.into_parts()
+    }
+}
+
+impl<IN, R, I> Inbound for Request<IN, R, I>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+    I: Interceptor,
+{
+    fn push_data(&mut self, data: &[u8]) {
+        Request::push_data(self, data)
+    }
+
+    fn push_close(&mut self) {
+        Request::push_close(self)
+    }
+
+    fn redeliver(&mut self) {
+        Request::redeliver(self)
+    }
+
+    fn pending_payload(&self) -> usize {
+        Request::pending_payload(self)
+    }
+
+    fn is_complete(&self) -> bool {
+        Request::is_complete(self)
+    }
+
+    fn error(&self) -> Option<Error> {
+        Request::error(self)
+    }
+
+    fn transport_failed(&mut self) {
+        Request::transport_failed(self)
     }
 }
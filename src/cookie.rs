@@ -0,0 +1,284 @@
+//! Cookies, as set by the server.
+//!
+//! A [`CookieHandler`] wraps another response handler, and passes the cookies of the
+//! `Set-Cookie` headers on to a [`CookieStore`]. [`CookieJar`] is a simple store, which keeps
+//! the cookies for sending them with the next requests.
+//!
+//! ~~~
+//! use heapless::{consts::*, Vec};
+//!
+//! use drogue_http_client::cookie::{CookieHandler, CookieJar};
+//! use drogue_http_client::headers::COOKIE;
+//! use drogue_http_client::*;
+//!
+//! let mut jar = CookieJar::<U128>::new();
+//! let mut sink = Vec::<u8, U1024>::new();
+//!
+//! let mut req = HttpConnection::<U1024>::new()
+//!     .post("/login")
+//!     .handler(CookieHandler::new(&mut jar, NoOpResponseHandler))
+//!     .execute(&mut sink);
+//!
+//! req.push_data(b"HTTP/1.1 204 No Content\r\nSet-Cookie: session=1234; HttpOnly\r\n");
+//! req.push_data(b"Content-Length: 0\r\n\r\n");
+//! let (con, _) = req.try_complete().unwrap();
+//!
+//! let req = con
+//!     .begin("GET", "/portal")
+//!     .header(COOKIE, jar.as_str())
+//!     .execute(&mut sink);
+//! ~~~
+
+use crate::headers::{ResponseHeaders, SET_COOKIE};
+use crate::{Error, Response, ResponseHandler};
+use core::fmt::Write;
+use heapless::{ArrayLength, String};
+
+/// A cookie, parsed from the value of a `Set-Cookie` header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SetCookie<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+    /// The `Expires` attribute, as received.
+    pub expires: Option<&'a str>,
+    /// The `Max-Age` attribute, in seconds. Zero or less expires the cookie immediately.
+    pub max_age: Option<i64>,
+    pub domain: Option<&'a str>,
+    pub path: Option<&'a str>,
+    pub secure: bool,
+    pub http_only: bool,
+}
+
+impl<'a> SetCookie<'a> {
+    /// Parse the value of a `Set-Cookie` header.
+    ///
+    /// Unknown attributes are ignored, as are attributes with an invalid value.
+    ///
+    /// ~~~
+    /// use drogue_http_client::cookie::SetCookie;
+    ///
+    /// let cookie = SetCookie::parse("id=a3fWa; Max-Age=2592000; Path=/; Secure").unwrap();
+    /// assert_eq!(cookie.name, "id");
+    /// assert_eq!(cookie.value, "a3fWa");
+    /// assert_eq!(cookie.max_age, Some(2592000));
+    /// assert_eq!(cookie.path, Some("/"));
+    /// assert!(cookie.secure);
+    /// ~~~
+    pub fn parse(value: &'a str) -> Option<Self> {
+        let mut parts = value.split(';');
+        let (name, value) = split_pair(parts.next()?)?;
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = SetCookie {
+            name,
+            value: value.trim_matches('"'),
+            expires: None,
+            max_age: None,
+            domain: None,
+            path: None,
+            secure: false,
+            http_only: false,
+        };
+
+        for attribute in parts {
+            let (name, value) = split_pair(attribute).unwrap_or((attribute.trim(), ""));
+            if name.eq_ignore_ascii_case("Expires") {
+                cookie.expires = Some(value);
+            } else if name.eq_ignore_ascii_case("Max-Age") {
+                cookie.max_age = value.parse().ok().or(cookie.max_age);
+            } else if name.eq_ignore_ascii_case("Domain") {
+                cookie.domain = Some(value.trim_start_matches('.'));
+            } else if name.eq_ignore_ascii_case("Path") {
+                cookie.path = Some(value);
+            } else if name.eq_ignore_ascii_case("Secure") {
+                cookie.secure = true;
+            } else if name.eq_ignore_ascii_case("HttpOnly") {
+                cookie.http_only = true;
+            }
+        }
+
+        Some(cookie)
+    }
+
+    /// Check if the server asked to remove the cookie, by setting its `Max-Age` to zero or less.
+    pub fn is_removal(&self) -> bool {
+        matches!(self.max_age, Some(max_age) if max_age <= 0)
+    }
+}
+
+fn split_pair(s: &str) -> Option<(&str, &str)> {
+    let i = s.find('=')?;
+    Some((s[..i].trim(), s[i + 1..].trim()))
+}
+
+/// Receives the cookies set by the server.
+pub trait CookieStore {
+    fn set_cookie(&mut self, cookie: &SetCookie<'_>);
+}
+
+/// A response handler, passing the cookies of the response on to a store, and everything
+/// else to the wrapped handler.
+pub struct CookieHandler<'s, S, R>
+where
+    S: CookieStore,
+    R: ResponseHandler,
+{
+    store: &'s mut S,
+    handler: R,
+}
+
+impl<'s, S, R> CookieHandler<'s, S, R>
+where
+    S: CookieStore,
+    R: ResponseHandler,
+{
+    pub fn new(store: &'s mut S, handler: R) -> Self {
+        CookieHandler { store, handler }
+    }
+
+    /// Get the wrapped handler.
+    pub fn handler(&self) -> &R {
+        &self.handler
+    }
+
+    /// Give back the wrapped handler.
+    pub fn into_inner(self) -> R {
+        self.handler
+    }
+}
+
+impl<'s, S, R> ResponseHandler for CookieHandler<'s, S, R>
+where
+    S: CookieStore,
+    R: ResponseHandler,
+{
+    fn response(&mut self, response: Response) {
+        for (name, value) in response.headers.iter() {
+            if !SET_COOKIE.matches(name) {
+                continue;
+            }
+            match core::str::from_utf8(value).ok().and_then(SetCookie::parse) {
+                Some(cookie) => self.store.set_cookie(&cookie),
+                None => log::info!("Ignoring invalid cookie: {:?}", value),
+            }
+        }
+        self.handler.response(response);
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        self.handler.more_payload(payload);
+    }
+
+    fn consume_payload(&mut self, payload: &[u8]) -> usize {
+        self.handler.consume_payload(payload)
+    }
+
+    fn trailers(&mut self, trailers: ResponseHeaders) {
+        self.handler.trailers(trailers);
+    }
+
+    fn failed(&self) -> Option<Error> {
+        self.handler.failed()
+    }
+}
+
+/// Keeps cookies, in the form of a `Cookie` header value, in a string of capacity `N`.
+///
+/// The jar keeps cookies until the server removes them, attributes like the domain or path are
+/// not considered. Cookies which don't fit are dropped, with a warning.
+pub struct CookieJar<N>
+where
+    N: ArrayLength<u8>,
+{
+    data: String<N>,
+}
+
+impl<N> Default for CookieJar<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N> CookieJar<N>
+where
+    N: ArrayLength<u8>,
+{
+    /// Create a new, empty jar.
+    pub fn new() -> Self {
+        CookieJar {
+            data: String::new(),
+        }
+    }
+
+    /// Iterate over the name/value pairs of the cookies.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.data.split("; ").filter_map(split_pair)
+    }
+
+    /// Get the value of a cookie.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// Check if there is no cookie.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Remove all cookies.
+    pub fn clear(&mut self) {
+        self.data = String::new();
+    }
+
+    /// The value for a `Cookie` header, sending all cookies.
+    pub fn as_str(&self) -> &str {
+        self.data.as_str()
+    }
+
+    /// Remove a cookie, returns `true` if it was present.
+    pub fn remove(&mut self, name: &str) -> bool {
+        if self.get(name).is_none() {
+            return false;
+        }
+
+        let mut data = String::<N>::new();
+        for (n, v) in self.iter().filter(|(n, _)| *n != name) {
+            // can't fail, as it is less than before
+            write_pair(&mut data, n, v).ok();
+        }
+        self.data = data;
+
+        true
+    }
+}
+
+fn write_pair<N>(data: &mut String<N>, name: &str, value: &str) -> Result<(), ()>
+where
+    N: ArrayLength<u8>,
+{
+    let separator = if data.is_empty() { "" } else { "; " };
+    if data.len() + separator.len() + name.len() + value.len() + 1 > data.capacity() {
+        return Err(());
+    }
+    write!(data, "{}{}={}", separator, name, value).map_err(|_| ())
+}
+
+impl<N> CookieStore for CookieJar<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn set_cookie(&mut self, cookie: &SetCookie<'_>) {
+        self.remove(cookie.name);
+        if cookie.is_removal() {
+            return;
+        }
+        if write_pair(&mut self.data, cookie.name, cookie.value).is_err() {
+            log::warn!("Cookie jar full, dropping: {}", cookie.name);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Digests of request payloads.
+//!
+//! A [`BodyDigest`] computes a hash over the payload of a request, which is sent in the
+//! `Content-Digest` header, as defined by RFC 9530, or the legacy `Content-MD5` header. This
+//! allows the server to verify that the payload was received intact. The hash function is
+//! pluggable, with the `sha2` feature, an implementation for SHA-256 is provided.
+//!
+//! ~~~
+//! use heapless::{consts::*, Vec};
+//!
+//! use drogue_http_client::digest::BodyDigest;
+//! use drogue_http_client::*;
+//!
+//! /// Not a real hash function.
+//! #[derive(Default)]
+//! struct Sum(u8);
+//!
+//! impl BodyDigest for Sum {
+//!     const KEY: &'static str = "sum";
+//!     type Output = [u8; 1];
+//!
+//!     fn update(&mut self, data: &[u8]) {
+//!         self.0 = data.iter().fold(self.0, |sum, b| sum.wrapping_add(*b));
+//!     }
+//!
+//!     fn finish(self) -> [u8; 1] {
+//!         [self.0]
+//!     }
+//! }
+//!
+//! let mut sink = Vec::<u8, U1024>::new();
+//! let req = HttpConnection::<U1024>::new()
+//!     .post("/upload")
+//!     .execute_with_digest(&mut sink, b"ab", Sum::default());
+//!
+//! assert_eq!(
+//!     sink,
+//!     b"POST /upload HTTP/1.1\r\nContent-Length: 2\r\nContent-Digest: sum=:ww==:\r\n\r\nab"
+//!         .as_ref()
+//! );
+//! ~~~
+
+use core::fmt::{self, Write};
+
+/// A hash function, for computing the digest of a payload.
+pub trait BodyDigest {
+    /// The key of the algorithm in the `Content-Digest` header, like `sha-256`.
+    const KEY: &'static str;
+
+    /// The size of the blocks processed by the hash function, in bytes, used for the HMAC.
+    const BLOCK_SIZE: usize = 64;
+
+    /// The digest.
+    type Output: AsRef<[u8]>;
+
+    /// Process more data.
+    fn update(&mut self, data: &[u8]);
+
+    /// Finish processing, returning the digest.
+    fn finish(self) -> Self::Output;
+}
+
+#[cfg(feature = "sha2")]
+impl BodyDigest for sha2::Sha256 {
+    const KEY: &'static str = "sha-256";
+    type Output = [u8; 32];
+
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(self, data)
+    }
+
+    fn finish(self) -> [u8; 32] {
+        sha2::Digest::finalize(self).into()
+    }
+}
+
+/// Compute the HMAC of the data, using the hash function `D`.
+///
+/// The data is processed as if the parts were concatenated. Hash functions with a block size
+/// above 128 bytes are not supported.
+///
+/// ~~~
+/// # #[cfg(feature = "sha2")] {
+/// use drogue_http_client::digest::hmac;
+///
+/// let mac = hmac::<sha2::Sha256>(b"key", &[b"The quick brown fox ", b"jumps over the lazy dog"]);
+/// assert_eq!(mac[..4], [0xf7, 0xbc, 0x83, 0xf4]);
+/// # }
+/// ~~~
+pub fn hmac<D>(key: &[u8], data: &[&[u8]]) -> D::Output
+where
+    D: BodyDigest + Default,
+{
+    let mut hmac = Hmac::<D>::new(key);
+    for part in data {
+        hmac.update(part);
+    }
+    hmac.finish()
+}
+
+/// Computes an HMAC incrementally, using the hash function `D`.
+///
+/// See [`hmac`].
+pub struct Hmac<D>
+where
+    D: BodyDigest + Default,
+{
+    inner: D,
+    // the key, with the outer padding applied
+    outer_key: [u8; 128],
+}
+
+impl<D> Hmac<D>
+where
+    D: BodyDigest + Default,
+{
+    /// Create a new instance, using the key.
+    pub fn new(key: &[u8]) -> Self {
+        let mut block = [0u8; 128];
+        if key.len() > D::BLOCK_SIZE {
+            let mut digest = D::default();
+            digest.update(key);
+            let key = digest.finish();
+            block[..key.as_ref().len()].copy_from_slice(key.as_ref());
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = D::default();
+        for b in block.iter_mut() {
+            *b ^= 0x36;
+        }
+        inner.update(&block[..D::BLOCK_SIZE]);
+        for b in block.iter_mut() {
+            // undo the inner padding as well
+            *b ^= 0x36 ^ 0x5c;
+        }
+
+        Hmac {
+            inner,
+            outer_key: block,
+        }
+    }
+
+    /// Process more data.
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Finish processing, returning the HMAC.
+    pub fn finish(self) -> D::Output {
+        let inner = self.inner.finish();
+        let mut outer = D::default();
+        outer.update(&self.outer_key[..D::BLOCK_SIZE]);
+        outer.update(inner.as_ref());
+        outer.finish()
+    }
+}
+
+/// A writer, passing everything written on to a hash function.
+#[cfg(feature = "sigv4")]
+pub(crate) struct DigestWriter<'d, D: BodyDigest>(pub &'d mut D);
+
+#[cfg(feature = "sigv4")]
+impl<'d, D: BodyDigest> Write for DigestWriter<'d, D> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.update(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Write the data, encoded as lowercase hex string.
+pub(crate) fn write_hex<W>(w: &mut W, data: &[u8]) -> fmt::Result
+where
+    W: Write + ?Sized,
+{
+    for b in data {
+        write!(w, "{:02x}", b)?;
+    }
+    Ok(())
+}
+
+/// Write the data, encoded using the standard base64 alphabet, with padding.
+pub(crate) fn write_base64<W>(w: &mut W, data: &[u8]) -> fmt::Result
+where
+    W: Write + ?Sized,
+{
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or_default(),
+            chunk.get(2).copied().unwrap_or_default(),
+        ];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                w.write_char(ALPHABET[(n >> (18 - 6 * i)) & 0x3f] as char)?;
+            } else {
+                w.write_char('=')?;
+            }
+        }
+    }
+    Ok(())
+}
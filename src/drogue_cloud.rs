@@ -0,0 +1,185 @@
+//! Publishing telemetry to the HTTP endpoint of [Drogue Cloud](https://drogue.io).
+//!
+//! A [`Publisher`] holds the identity of a device, and publishes events to a channel, using
+//! an [`HttpClient`]. The device authenticates using its password, with HTTP basic auth, and
+//! the status code of the response is mapped to an [`Outcome`].
+//!
+//! ~~~no_run
+//! use heapless::consts::*;
+//!
+//! use drogue_network::addr::HostSocketAddr;
+//!
+//! use drogue_http_client::client::HttpClient;
+//! use drogue_http_client::drogue_cloud::{Outcome, Publisher};
+//! use drogue_http_client::*;
+//!
+//! # use drogue_http_client::mock;
+//! # let (network, _) = mock::mock_connection();
+//! let remote = HostSocketAddr::from("192.168.1.1", 8080).unwrap();
+//! let mut client = HttpClient::new(&network, remote, HttpConnection::<U1024>::new());
+//!
+//! let publisher = Publisher::new("my-app", "device-1")
+//!     .password("hey-rodney")
+//!     .content_type("application/json");
+//!
+//! match publisher.publish(&mut client, "telemetry", br#"{"temp":21.5}"#) {
+//!     Ok(Outcome::Accepted) => {}
+//!     Ok(outcome) => log::warn!("Rejected: {:?}", outcome),
+//!     Err(err) => log::warn!("Failed to publish: {:?}", err),
+//! }
+//! ~~~
+
+use crate::client::HttpClient;
+use crate::digest::write_base64;
+use crate::headers::{AUTHORIZATION, CONTENT_TYPE};
+use crate::{Error, Interceptor, PipeError, StatusOnlyResponseHandler};
+use core::fmt::{self, Write};
+use drogue_network::tcp::TcpStack;
+use heapless::{consts, ArrayLength, String};
+
+/// The outcome of publishing an event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The event was accepted.
+    Accepted,
+    /// The request was malformed, e.g. an invalid channel or payload.
+    BadRequest,
+    /// The credentials are missing, or were rejected.
+    Unauthorized,
+    /// The application or device doesn't exist, or is disabled.
+    NotFound,
+    /// The endpoint can't process the event right now, publishing may be retried later.
+    Unavailable,
+    /// The event was rejected, with another status code.
+    Rejected(u16),
+}
+
+impl Outcome {
+    /// Map the status code of a response.
+    pub fn from_code(code: u16) -> Self {
+        match code {
+            200..=299 => Outcome::Accepted,
+            400 => Outcome::BadRequest,
+            401 | 403 => Outcome::Unauthorized,
+            404 => Outcome::NotFound,
+            503 => Outcome::Unavailable,
+            code => Outcome::Rejected(code),
+        }
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        *self == Outcome::Accepted
+    }
+}
+
+/// Publishes events of a device.
+#[derive(Copy, Clone, Debug)]
+pub struct Publisher<'a> {
+    application: &'a str,
+    device: &'a str,
+    password: Option<&'a str>,
+    content_type: Option<&'a str>,
+    data_schema: Option<&'a str>,
+}
+
+impl<'a> Publisher<'a> {
+    /// Create a new publisher, for the device in the application.
+    pub fn new(application: &'a str, device: &'a str) -> Self {
+        Publisher {
+            application,
+            device,
+            password: None,
+            content_type: None,
+            data_schema: None,
+        }
+    }
+
+    /// Authenticate with the password of the device.
+    ///
+    /// Without a password, the endpoint must be configured to accept unauthenticated devices.
+    pub fn password(mut self, password: &'a str) -> Self {
+        self.password = Some(password);
+        self
+    }
+
+    /// Set the content type of the payload.
+    pub fn content_type(mut self, content_type: &'a str) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    /// Set the schema of the payload, which is passed on with the event.
+    pub fn data_schema(mut self, data_schema: &'a str) -> Self {
+        self.data_schema = Some(data_schema);
+        self
+    }
+
+    /// Publish the payload to the channel, like `telemetry`.
+    ///
+    /// An error is only returned if the request failed, rejections are reported as
+    /// [`Outcome`].
+    pub fn publish<T, IN, I>(
+        &self,
+        client: &mut HttpClient<'_, T, IN, I>,
+        channel: &str,
+        payload: &[u8],
+    ) -> Result<Outcome, PipeError<T::Error>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+    {
+        let mut path = String::<consts::U128>::new();
+        write!(path, "/v1/{}", channel).map_err(|_| Error::Url)?;
+
+        let authorization = self.authorization().map_err(|_| {
+            log::warn!("Credentials exceed the buffer");
+            Error::Send
+        })?;
+
+        let handler = client.request("POST", &path, |req, sink| {
+            let mut req = req;
+            if self.password.is_none() {
+                req = req
+                    .query_param("application", self.application)
+                    .query_param("device", self.device);
+            }
+            if let Some(data_schema) = self.data_schema {
+                req = req.query_param("data_schema", data_schema);
+            }
+            if let Some(authorization) = &authorization {
+                req = req.header(AUTHORIZATION, authorization);
+            }
+            if let Some(content_type) = self.content_type {
+                req = req.header(CONTENT_TYPE, content_type);
+            }
+            req.handler(StatusOnlyResponseHandler::new())
+                .execute_with(sink, Some(payload))
+        })?;
+
+        let outcome = Outcome::from_code(handler.code());
+        if !outcome.is_accepted() {
+            log::info!("Event rejected: {}", handler.code());
+        }
+        Ok(outcome)
+    }
+
+    /// The value of the `Authorization` header, using the username `device@application`.
+    fn authorization(&self) -> Result<Option<String<consts::U256>>, fmt::Error> {
+        let password = match self.password {
+            Some(password) => password,
+            None => return Ok(None),
+        };
+
+        let mut credentials = String::<consts::U128>::new();
+        write!(
+            credentials,
+            "{}@{}:{}",
+            self.device, self.application, password
+        )?;
+        let mut value = String::new();
+        value.push_str("Basic ").map_err(|_| fmt::Error)?;
+        write_base64(&mut value, credentials.as_bytes())?;
+        Ok(Some(value))
+    }
+}
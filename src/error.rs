@@ -0,0 +1,159 @@
+use core::fmt;
+
+/// Errors of the HTTP processing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The response could not be parsed.
+    Parse(httparse::Error),
+    /// The connection was closed before the complete response, e.g. the full body announced by
+    /// `Content-Length`, was received.
+    IncompleteBody,
+    /// Sending the request failed.
+    Send,
+    /// The URL is invalid, or not supported.
+    Url,
+    /// Resolving the hostname failed.
+    Resolve,
+    /// The URL uses `https`, but the client only supports plain TCP connections.
+    Tls,
+    /// An HTTP/2 frame was malformed, or violated the protocol.
+    Frame,
+    /// The server responded with an unexpected status code.
+    Status(u16),
+    /// The server reset the HTTP/2 stream, or shut down the connection, with the error code.
+    Reset(u32),
+    /// Data exceeded a buffer: the response body the buffer of the handler, data kept for the
+    /// next response or restored from a suspended state the inbound buffer, or a suspended
+    /// state the provided output.
+    Overflow,
+    /// The "chunked" transfer encoding of the response body is malformed.
+    Chunk,
+    /// The response body exceeds the configured maximum size.
+    BodyTooLarge,
+    /// The response header exceeds the configured maximum size.
+    HeaderTooLarge,
+    /// A suspended state of a request can't be restored.
+    InvalidState,
+}
+
+impl From<httparse::Error> for Error {
+    fn from(err: httparse::Error) -> Self {
+        Error::Parse(err)
+    }
+}
+
+/// Why a response is not complete.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum IncompleteReason {
+    /// The response header was not received completely yet.
+    Header,
+    /// The response body was not received completely yet, or the handler did not consume it.
+    Body,
+    /// Processing the response failed.
+    Failed(Error),
+}
+
+/// Errors when piping data from a [`Source`](crate::Source) to a request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PipeError<E> {
+    /// The transport failed.
+    Transport(E),
+    /// Processing the response failed.
+    Protocol(Error),
+    /// No data was received within the configured limit.
+    Timeout,
+}
+
+impl<E> From<Error> for PipeError<E> {
+    fn from(err: Error) -> Self {
+        PipeError::Protocol(err)
+    }
+}
+
+impl<E> PipeError<E> {
+    /// Map the transport error.
+    pub fn map_transport<F, EN>(self, f: F) -> PipeError<EN>
+    where
+        F: FnOnce(E) -> EN,
+    {
+        match self {
+            PipeError::Transport(err) => PipeError::Transport(f(err)),
+            PipeError::Protocol(err) => PipeError::Protocol(err),
+            PipeError::Timeout => PipeError::Timeout,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(err) => write!(f, "Failed to parse response: {}", err),
+            Error::IncompleteBody => {
+                f.write_str("Connection closed before the response was complete")
+            }
+            Error::Send => f.write_str("Failed to send the request"),
+            Error::Url => f.write_str("Invalid or unsupported URL"),
+            Error::Resolve => f.write_str("Failed to resolve the hostname"),
+            Error::Tls => f.write_str("TLS is not supported"),
+            Error::Status(code) => write!(f, "Unexpected status code: {}", code),
+            Error::Frame => f.write_str("Invalid HTTP/2 frame"),
+            Error::Reset(code) => write!(f, "Stream reset by the server: {:#x}", code),
+            Error::Overflow => f.write_str("Data exceeds the buffer"),
+            Error::Chunk => f.write_str("Invalid chunked encoding"),
+            Error::BodyTooLarge => f.write_str("Response body exceeds the maximum size"),
+            Error::HeaderTooLarge => f.write_str("Response header exceeds the maximum size"),
+            Error::InvalidState => f.write_str("Invalid suspended state"),
+        }
+    }
+}
+
+impl<E> fmt::Display for PipeError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipeError::Transport(err) => write!(f, "Transport failed: {}", err),
+            PipeError::Protocol(err) => err.fmt(f),
+            PipeError::Timeout => f.write_str("Timed out waiting for data"),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match self {
+            Error::Parse(err) => defmt::write!(f, "Parse({=str})", parse_error_name(*err)),
+            Error::IncompleteBody => defmt::write!(f, "IncompleteBody"),
+            Error::Send => defmt::write!(f, "Send"),
+            Error::Url => defmt::write!(f, "Url"),
+            Error::Resolve => defmt::write!(f, "Resolve"),
+            Error::Tls => defmt::write!(f, "Tls"),
+            Error::Frame => defmt::write!(f, "Frame"),
+            Error::Status(code) => defmt::write!(f, "Status({=u16})", code),
+            Error::Reset(code) => defmt::write!(f, "Reset({=u32:#x})", code),
+            Error::Overflow => defmt::write!(f, "Overflow"),
+            Error::Chunk => defmt::write!(f, "Chunk"),
+            Error::BodyTooLarge => defmt::write!(f, "BodyTooLarge"),
+            Error::HeaderTooLarge => defmt::write!(f, "HeaderTooLarge"),
+            Error::InvalidState => defmt::write!(f, "InvalidState"),
+        }
+    }
+}
+
+/// The name of the parse error, as `httparse` doesn't implement `defmt::Format`.
+#[cfg(feature = "defmt")]
+fn parse_error_name(err: httparse::Error) -> &'static str {
+    match err {
+        httparse::Error::HeaderName => "HeaderName",
+        httparse::Error::HeaderValue => "HeaderValue",
+        httparse::Error::NewLine => "NewLine",
+        httparse::Error::Status => "Status",
+        httparse::Error::Token => "Token",
+        httparse::Error::TooManyHeaders => "TooManyHeaders",
+        httparse::Error::Version => "Version",
+    }
+}
@@ -0,0 +1,283 @@
+//! Filtering of unsolicited notifications, which got mixed into the inbound data.
+//!
+//! When tunneling HTTP through an AT modem, some drivers don't separate the unsolicited
+//! notifications of the modem, like `+IPD,32:` or `WIFI DISCONNECT`, from the data of the
+//! connection. A [`FilterSource`] wraps the source, and strips such notifications, before they
+//! reach the HTTP processing. Optionally, they are reported to a callback.
+//!
+//! ~~~
+//! use heapless::consts::*;
+//!
+//! use drogue_http_client::filter::{FilterSource, Pattern};
+//! use drogue_http_client::loopback::*;
+//! use drogue_http_client::*;
+//!
+//! const PATTERNS: &[Pattern] = &[Pattern::line("WIFI "), Pattern::until("+IPD,", b':')];
+//!
+//! let modem = Loopback::<U1024, _>::new(CannedResponse::new(200, "OK").body(b"foo"));
+//! let mut disconnected = false;
+//! let mut on_notification = |notification: &[u8]| {
+//!     disconnected |= notification.starts_with(b"WIFI DISCONNECT");
+//! };
+//! let mut modem = FilterSource::<_>::new(modem, PATTERNS).on_notification(&mut on_notification);
+//!
+//! let mut req = HttpConnection::<U1024>::new()
+//!     .begin("GET", "/")
+//!     .handler(BufferResponseHandler::<U64>::new())
+//!     .execute(&mut modem);
+//! modem.pipe_data(&mut req).unwrap();
+//! ~~~
+
+use crate::{Budget, Error, Inbound, PipeError, Progress, Sink, Source};
+use heapless::{consts, ArrayLength, Vec};
+
+/// A pattern of a notification, which starts with a prefix, and runs up to an end marker.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pattern<'p> {
+    prefix: &'p [u8],
+    end: u8,
+}
+
+impl<'p> Pattern<'p> {
+    /// A notification starting with the prefix, up to the end of the line.
+    pub const fn line(prefix: &'p str) -> Self {
+        Self::until(prefix, b'\n')
+    }
+
+    /// A notification starting with the prefix, up to and including the end marker.
+    pub const fn until(prefix: &'p str, end: u8) -> Self {
+        Pattern {
+            prefix: prefix.as_bytes(),
+            end,
+        }
+    }
+}
+
+/// A [`Source`], which strips notifications matching one of the patterns from the data of the
+/// wrapped source.
+///
+/// Notifications are matched anywhere in the data, also when they are split across multiple
+/// reads. Data which could be the start of a notification is held back, until it is known if
+/// it is one. When the source runs out of data, held back data is passed on, so that a
+/// response ending with such data doesn't stall. Notifications are reported with up to `N`
+/// bytes.
+///
+/// If the wrapped source is a [`Sink`] as well, so is the filter.
+pub struct FilterSource<'p, S, N = consts::U64>
+where
+    N: ArrayLength<u8>,
+{
+    source: S,
+    filter: Filter<'p, N>,
+}
+
+impl<'p, S, N> FilterSource<'p, S, N>
+where
+    N: ArrayLength<u8>,
+{
+    pub fn new(source: S, patterns: &'p [Pattern<'p>]) -> Self {
+        FilterSource {
+            source,
+            filter: Filter {
+                patterns,
+                held: Vec::new(),
+                end: None,
+                on_notification: None,
+            },
+        }
+    }
+
+    /// Call the function with each notification which was stripped.
+    pub fn on_notification(mut self, f: &'p mut dyn FnMut(&[u8])) -> Self {
+        self.filter.on_notification = Some(f);
+        self
+    }
+
+    /// Give back the wrapped source.
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<'p, S, N> Source for FilterSource<'p, S, N>
+where
+    S: Source,
+    N: ArrayLength<u8>,
+{
+    type Error = S::Error;
+
+    fn pipe_data_limited<Q>(
+        &mut self,
+        request: &mut Q,
+        budget: Budget,
+    ) -> Result<Progress, PipeError<Self::Error>>
+    where
+        Q: Inbound,
+    {
+        let mut inbound = FilteredInbound {
+            filter: &mut self.filter,
+            request,
+        };
+        self.source.pipe_data_limited(&mut inbound, budget)
+    }
+}
+
+impl<'p, S, N> Sink for FilterSource<'p, S, N>
+where
+    S: Sink,
+    N: ArrayLength<u8>,
+{
+    fn send(&mut self, data: &[u8]) -> Result<usize, ()> {
+        self.source.send(data)
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        self.source.flush()
+    }
+}
+
+/// The state of the filter, kept between reads.
+struct Filter<'p, N>
+where
+    N: ArrayLength<u8>,
+{
+    patterns: &'p [Pattern<'p>],
+    // data which may be the start of a notification, or the notification being stripped
+    held: Vec<u8, N>,
+    // the end marker of the notification being stripped
+    end: Option<u8>,
+    #[allow(clippy::type_complexity)]
+    on_notification: Option<&'p mut dyn FnMut(&[u8])>,
+}
+
+impl<'p, N> Filter<'p, N>
+where
+    N: ArrayLength<u8>,
+{
+    fn push<Q>(&mut self, request: &mut Q, data: &[u8])
+    where
+        Q: Inbound,
+    {
+        // the start of the data, which is passed on as is
+        let mut start = 0;
+        for (i, &b) in data.iter().enumerate() {
+            if self.end.is_none() && self.held.is_empty() {
+                if !self.patterns.iter().any(|p| p.prefix.first() == Some(&b)) {
+                    continue;
+                }
+                if i > start {
+                    request.push_data(&data[start..i]);
+                }
+            }
+            self.feed(request, b);
+            start = i + 1;
+        }
+        if start < data.len() {
+            request.push_data(&data[start..]);
+        }
+    }
+
+    fn feed<Q>(&mut self, request: &mut Q, b: u8)
+    where
+        Q: Inbound,
+    {
+        if let Some(end) = self.end {
+            // the notification gets truncated, if it doesn't fit
+            self.held.push(b).ok();
+            if b == end {
+                if let Some(f) = &mut self.on_notification {
+                    f(&self.held);
+                }
+                debug!(
+                    "Stripped notification: {:?}",
+                    core::str::from_utf8(&self.held)
+                );
+                self.held = Vec::new();
+                self.end = None;
+            }
+            return;
+        }
+
+        if self.held.push(b).is_err() {
+            // a prefix longer than the buffer can't be matched
+            self.flush(request);
+            request.push_data(&[b]);
+            return;
+        }
+        while !self.held.is_empty() {
+            if let Some(pattern) = self.patterns.iter().find(|p| p.prefix == &self.held[..]) {
+                self.end = Some(pattern.end);
+                return;
+            }
+            if self
+                .patterns
+                .iter()
+                .any(|p| p.prefix.starts_with(&self.held))
+            {
+                // wait for more data
+                return;
+            }
+            // not a notification, but the rest may start one
+            request.push_data(&self.held[..1]);
+            self.held = Vec::from_slice(&self.held[1..]).unwrap_or_default();
+        }
+    }
+
+    /// Pass on the data held back, unless it is part of a notification.
+    fn flush<Q>(&mut self, request: &mut Q)
+    where
+        Q: Inbound,
+    {
+        if self.end.is_none() && !self.held.is_empty() {
+            request.push_data(&self.held);
+            self.held = Vec::new();
+        }
+    }
+}
+
+/// Filters the data pushed to a request.
+struct FilteredInbound<'f, 'p, Q, N>
+where
+    Q: Inbound,
+    N: ArrayLength<u8>,
+{
+    filter: &'f mut Filter<'p, N>,
+    request: &'f mut Q,
+}
+
+impl<'f, 'p, Q, N> Inbound for FilteredInbound<'f, 'p, Q, N>
+where
+    Q: Inbound,
+    N: ArrayLength<u8>,
+{
+    fn push_data(&mut self, data: &[u8]) {
+        self.filter.push(self.request, data);
+    }
+
+    fn push_close(&mut self) {
+        self.filter.flush(self.request);
+        self.request.push_close();
+    }
+
+    fn redeliver(&mut self) {
+        // the source ran out of data, for now
+        self.filter.flush(self.request);
+        self.request.redeliver();
+    }
+
+    fn pending_payload(&self) -> usize {
+        self.request.pending_payload()
+    }
+
+    fn is_complete(&self) -> bool {
+        self.request.is_complete()
+    }
+
+    fn error(&self) -> Option<Error> {
+        self.request.error()
+    }
+
+    fn transport_failed(&mut self) {
+        self.request.transport_failed();
+    }
+}
@@ -0,0 +1,228 @@
+//! Downloads, straight into NOR flash.
+//!
+//! A [`FlashResponseHandler`] writes the payload of a response into a region of a
+//! [`NorFlash`], e.g. for an OTA image, or a large configuration blob. The payload is collected
+//! into pages of `NP` bytes, so that the flash is written in aligned blocks, and sectors get
+//! erased just before they are written to.
+//!
+//! This module requires the `flash` feature.
+//!
+//! ~~~no_run
+//! # use embedded_storage::nor_flash::{ErrorType, NorFlash, ReadNorFlash, NorFlashErrorKind};
+//! # struct Flash;
+//! # impl ErrorType for Flash { type Error = NorFlashErrorKind; }
+//! # impl ReadNorFlash for Flash {
+//! #     const READ_SIZE: usize = 1;
+//! #     fn read(&mut self, _: u32, _: &mut [u8]) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn capacity(&self) -> usize { 0x40000 }
+//! # }
+//! # impl NorFlash for Flash {
+//! #     const WRITE_SIZE: usize = 4;
+//! #     const ERASE_SIZE: usize = 4096;
+//! #     fn erase(&mut self, _: u32, _: u32) -> Result<(), Self::Error> { Ok(()) }
+//! #     fn write(&mut self, _: u32, _: &[u8]) -> Result<(), Self::Error> { Ok(()) }
+//! # }
+//! # let flash = Flash;
+//! use heapless::{consts::*, Vec};
+//!
+//! use drogue_http_client::flash::FlashResponseHandler;
+//! use drogue_http_client::*;
+//!
+//! # let mut sink = Vec::<u8, U1024>::new();
+//! let mut req = HttpConnection::<U1024>::new()
+//!     .begin("GET", "/firmware/1.2.0")
+//!     // the second half of the flash
+//!     .handler(FlashResponseHandler::<_, U256>::new(flash, 0x20000..0x40000))
+//!     .execute(&mut sink);
+//! ~~~
+
+use crate::headers::CONTENT_LENGTH;
+use crate::{Error, Response, ResponseHandler};
+use core::ops::Range;
+use embedded_storage::nor_flash::NorFlash;
+use heapless::{ArrayLength, Vec};
+
+/// Errors of writing a payload to flash.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FlashError<E> {
+    /// Processing the response failed.
+    Request(Error),
+    /// Erasing, or writing to, the flash failed.
+    Flash(E),
+    /// The payload doesn't fit into the region.
+    TooLarge,
+}
+
+/// A response handler, that writes the payload of a successful response into a region of a
+/// NOR flash.
+///
+/// The start and end of the region must be aligned to the erase size of the flash, and the page
+/// size `NP` must be a multiple of its write size. Sectors are erased on demand, right before the
+/// first page is written to them. The last page is padded with `0xFF`, to the write size.
+///
+/// The payload of responses which don't have a `2xx` status code is not written.
+pub struct FlashResponseHandler<F, NP>
+where
+    F: NorFlash,
+    NP: ArrayLength<u8>,
+{
+    flash: F,
+    region: Range<u32>,
+    // the payload of the next page
+    page: Vec<u8, NP>,
+    // the number of bytes written to the region
+    written: u32,
+    // the end of the erased part of the region
+    erased: u32,
+    code: u16,
+    complete: bool,
+    error: Option<FlashError<F::Error>>,
+}
+
+impl<F, NP> FlashResponseHandler<F, NP>
+where
+    F: NorFlash,
+    NP: ArrayLength<u8>,
+{
+    /// Create a new instance, writing to the region of the flash.
+    pub fn new(flash: F, region: Range<u32>) -> Self {
+        FlashResponseHandler {
+            flash,
+            erased: region.start,
+            region,
+            page: Vec::new(),
+            written: 0,
+            code: 0,
+            complete: false,
+            error: None,
+        }
+    }
+
+    /// The status code of the response.
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// Check if the response was processed completely, and all of its payload written.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The number of payload bytes, which were written to the flash.
+    pub fn len(&self) -> u32 {
+        self.written
+    }
+
+    /// Check if no payload was written to the flash.
+    pub fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+
+    /// The error, in case writing the payload failed.
+    pub fn error(&self) -> Option<&FlashError<F::Error>> {
+        self.error.as_ref()
+    }
+
+    /// Give back the flash.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    fn append(&mut self, mut data: &[u8]) {
+        let size = self.region.end - self.region.start;
+        if self.written as usize + self.page.len() + data.len() > size as usize {
+            log::info!("Payload exceeds the region of {} bytes", size);
+            self.error = Some(FlashError::TooLarge);
+            return;
+        }
+
+        while !data.is_empty() && self.error.is_none() {
+            let len = data.len().min(self.page.capacity() - self.page.len());
+            // fits, can't fail
+            self.page.extend_from_slice(&data[..len]).ok();
+            data = &data[len..];
+
+            if self.page.len() == self.page.capacity() {
+                self.flush();
+            }
+        }
+    }
+
+    /// Write the page to the flash, padding it to the write size.
+    fn flush(&mut self) {
+        let len = self.page.len();
+        if len == 0 {
+            return;
+        }
+
+        let padded = align_up(len as u32, F::WRITE_SIZE as u32);
+        // growing only, within the capacity when it's a multiple of the write size
+        self.page.resize(padded as usize, 0xFF).ok();
+
+        let address = self.region.start + self.written;
+        let result = self
+            .erase(address + padded)
+            .and_then(|_| self.flash.write(address, &self.page));
+        match result {
+            Ok(_) => self.written += len as u32,
+            Err(err) => self.error = Some(FlashError::Flash(err)),
+        }
+        // replace, as `clear()` of heapless 0.5 trips the UB checks of recent Rust versions
+        self.page = Vec::new();
+    }
+
+    /// Erase the region, up to at least the address.
+    fn erase(&mut self, end: u32) -> Result<(), F::Error> {
+        if end > self.erased {
+            let to = align_up(end, F::ERASE_SIZE as u32).min(self.region.end);
+            debug!("Erasing {:#x}..{:#x}", self.erased, to);
+            self.flash.erase(self.erased, to)?;
+            self.erased = to;
+        }
+        Ok(())
+    }
+}
+
+impl<F, NP> ResponseHandler for FlashResponseHandler<F, NP>
+where
+    F: NorFlash,
+    NP: ArrayLength<u8>,
+{
+    fn response(&mut self, response: Response) {
+        self.code = response.code;
+
+        let len = response
+            .headers
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.parse::<u32>().ok());
+        let size = self.region.end - self.region.start;
+        if matches!(self.code, 200..=299) && matches!(len, Some(len) if len > size) {
+            log::info!("Announced payload exceeds the region");
+            self.error = Some(FlashError::TooLarge);
+        }
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        if self.error.is_some() {
+            return;
+        }
+
+        match payload {
+            Ok(Some(data)) if matches!(self.code, 200..=299) => self.append(data),
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                self.flush();
+                self.complete = self.error.is_none();
+            }
+            Err(err) => self.error = Some(FlashError::Request(err)),
+        }
+    }
+}
+
+/// Round up the value, to a multiple of the alignment.
+fn align_up(value: u32, align: u32) -> u32 {
+    match value % align {
+        0 => value,
+        rem => value + (align - rem),
+    }
+}
@@ -0,0 +1,230 @@
+//! Parsing of `application/x-www-form-urlencoded` response payloads.
+//!
+//! Some endpoints, like OAuth token endpoints of legacy servers, respond with a form encoded
+//! payload. A [`FormResponseHandler`] parses it while it is received, and reports each
+//! key/value pair, percent-decoded, to a callback. The pairs are decoded into buffers provided by
+//! the caller, so the payload never needs to be buffered as a whole.
+//!
+//! ~~~
+//! use heapless::{consts::*, String, Vec};
+//!
+//! use drogue_http_client::form::FormResponseHandler;
+//! use drogue_http_client::*;
+//!
+//! let mut token = String::<U64>::new();
+//! let mut key = [0u8; 16];
+//! let mut value = [0u8; 64];
+//! let handler = FormResponseHandler::new(&mut key, &mut value, |key, value| {
+//!     if key == "access_token" {
+//!         token.push_str(value).ok();
+//!     }
+//! });
+//!
+//! # let mut sink = Vec::<u8, U1024>::new();
+//! let mut req = HttpConnection::<U1024>::new()
+//!     .post("/oauth/token")
+//!     .handler(handler)
+//!     .execute(&mut sink);
+//! req.push_data(b"HTTP/1.1 200 OK\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 50\r\n\r\n");
+//! req.push_data(b"access_token=a%2Bb%3D&token_type=bearer&scope=read");
+//!
+//! assert_eq!(req.finish().1, Ok(200));
+//! assert_eq!(token, "a+b=");
+//! ~~~
+
+use crate::{Error, FinishResponse, Response, ResponseHandler};
+use core::str::from_utf8;
+
+/// The state of decoding a percent-encoded byte.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Escape {
+    None,
+    // after the `%`
+    Percent,
+    // after the first hex digit
+    Hex(u8),
+}
+
+/// A response handler, which parses a form encoded payload into key/value pairs.
+///
+/// Keys and values are decoded into the provided buffers, and passed on to the callback once the
+/// pair is complete. Pairs which don't fit into the buffers, or aren't valid UTF-8 after
+/// decoding, are skipped and counted. Invalid percent-encoded sequences are kept as they are.
+///
+/// The payload of all responses is parsed, use [`FormResponseHandler::code`] to tell a
+/// successful response from an error.
+pub struct FormResponseHandler<'b, F>
+where
+    F: FnMut(&str, &str),
+{
+    key: &'b mut [u8],
+    key_len: usize,
+    value: &'b mut [u8],
+    value_len: usize,
+    // the `=` of the current pair was received
+    in_value: bool,
+    escape: Escape,
+    // the current pair doesn't fit into the buffers
+    overflow: bool,
+    skipped: usize,
+    f: F,
+    code: u16,
+    complete: bool,
+    error: Option<Error>,
+}
+
+impl<'b, F> FormResponseHandler<'b, F>
+where
+    F: FnMut(&str, &str),
+{
+    /// Create a new instance, decoding keys and values into the buffers, and passing the pairs
+    /// on to the callback.
+    pub fn new(key: &'b mut [u8], value: &'b mut [u8], f: F) -> Self {
+        FormResponseHandler {
+            key,
+            key_len: 0,
+            value,
+            value_len: 0,
+            in_value: false,
+            escape: Escape::None,
+            overflow: false,
+            skipped: 0,
+            f,
+            code: 0,
+            complete: false,
+            error: None,
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The error, in case processing the response failed.
+    pub fn error(&self) -> Option<Error> {
+        self.error
+    }
+
+    /// The number of pairs which were skipped, as they didn't fit into the buffers, or weren't
+    /// valid UTF-8.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    fn parse(&mut self, b: u8) {
+        match (self.escape, b) {
+            (Escape::None, b'&') => self.end_pair(),
+            (Escape::None, b'=') if !self.in_value => self.in_value = true,
+            (Escape::None, b'+') => self.push(b' '),
+            (Escape::None, b'%') => self.escape = Escape::Percent,
+            (Escape::None, b) => self.push(b),
+            (Escape::Percent, b) if b.is_ascii_hexdigit() => self.escape = Escape::Hex(b),
+            (Escape::Hex(hi), b) if b.is_ascii_hexdigit() => {
+                self.escape = Escape::None;
+                self.push(hex(hi) << 4 | hex(b));
+            }
+            (_, b) => {
+                // not an escape sequence after all
+                self.flush_escape();
+                self.parse(b);
+            }
+        }
+    }
+
+    /// Keep the incomplete escape sequence as it is.
+    fn flush_escape(&mut self) {
+        match core::mem::replace(&mut self.escape, Escape::None) {
+            Escape::None => {}
+            Escape::Percent => self.push(b'%'),
+            Escape::Hex(hi) => {
+                self.push(b'%');
+                self.push(hi);
+            }
+        }
+    }
+
+    fn push(&mut self, b: u8) {
+        let (buffer, len) = match self.in_value {
+            true => (&mut *self.value, &mut self.value_len),
+            false => (&mut *self.key, &mut self.key_len),
+        };
+        match buffer.get_mut(*len) {
+            Some(slot) => {
+                *slot = b;
+                *len += 1;
+            }
+            None => self.overflow = true,
+        }
+    }
+
+    fn end_pair(&mut self) {
+        self.flush_escape();
+
+        let empty = self.key_len == 0 && self.value_len == 0 && !self.in_value;
+        if !empty {
+            let key = from_utf8(&self.key[..self.key_len]);
+            let value = from_utf8(&self.value[..self.value_len]);
+            match (self.overflow, key, value) {
+                (false, Ok(key), Ok(value)) => (self.f)(key, value),
+                _ => {
+                    log::warn!("Skipping form field, which is too long or invalid");
+                    self.skipped += 1;
+                }
+            }
+        }
+
+        self.key_len = 0;
+        self.value_len = 0;
+        self.in_value = false;
+        self.overflow = false;
+    }
+}
+
+impl<'b, F> ResponseHandler for FormResponseHandler<'b, F>
+where
+    F: FnMut(&str, &str),
+{
+    fn response(&mut self, response: Response) {
+        self.code = response.code;
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        match payload {
+            Ok(Some(data)) => data.iter().for_each(|b| self.parse(*b)),
+            Ok(None) => {
+                self.end_pair();
+                self.complete = true;
+            }
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+impl<'b, F> FinishResponse for FormResponseHandler<'b, F>
+where
+    F: FnMut(&str, &str),
+{
+    /// The status code.
+    type Output = u16;
+
+    fn finish(self) -> Result<u16, Error> {
+        match self.error {
+            Some(err) => Err(err),
+            None if !self.complete => Err(Error::IncompleteBody),
+            None => Ok(self.code),
+        }
+    }
+}
+
+/// The value of a hex digit.
+fn hex(b: u8) -> u8 {
+    match b {
+        b'0'..=b'9' => b - b'0',
+        b'a'..=b'f' => b - b'a' + 10,
+        _ => b - b'A' + 10,
+    }
+}
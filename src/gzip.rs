@@ -0,0 +1,219 @@
+//! Compression of request payloads, using gzip.
+//!
+//! The payload is compressed into a buffer, so that its length can be announced using
+//! `Content-Length`. The encoder uses a small hash table for finding repeated sequences, and
+//! the fixed Huffman codes of deflate. This doesn't achieve the ratio of a full implementation,
+//! but works well for verbose payloads like JSON, without requiring any heap.
+//!
+//! This module requires the `gzip` feature.
+//!
+//! ~~~
+//! use heapless::{consts::*, Vec};
+//!
+//! use drogue_http_client::*;
+//!
+//! let payload = br#"[{"temp":21.5},{"temp":21.5},{"temp":21.5},{"temp":21.5}]"#;
+//!
+//! let mut sink = Vec::<u8, U1024>::new();
+//! let req = HttpConnection::<U1024>::new()
+//!     .post("/telemetry")
+//!     .json()
+//!     .execute_gzip::<_, U256>(&mut sink, payload, 32);
+//!
+//! assert!(sink.starts_with(b"POST /telemetry HTTP/1.1\r\nContent-Length: 38\r\n"));
+//! ~~~
+
+use heapless::{ArrayLength, Vec};
+
+/// The number of bits of the hash table, finding repeated sequences.
+const HASH_BITS: usize = 8;
+/// The maximum distance of a repeated sequence.
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+/// The header of a gzip member, without a file name or modification time.
+const HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+
+/// The base lengths of the length codes 257..=285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// The base distances of the distance codes 0..=29.
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Compress the data into the buffer, using the gzip format.
+///
+/// Fails if the compressed data doesn't fit into the buffer, which is cleared before.
+pub fn compress<N>(data: &[u8], out: &mut Vec<u8, N>) -> Result<(), ()>
+where
+    N: ArrayLength<u8>,
+{
+    *out = Vec::new();
+    out.extend_from_slice(&HEADER)?;
+
+    let mut w = BitWriter {
+        out,
+        bits: 0,
+        len: 0,
+    };
+    // a single, final block, using the fixed codes
+    w.write(0b011, 3)?;
+
+    let mut table = [u16::MAX; 1 << HASH_BITS];
+    let mut pos = 0;
+    while pos < data.len() {
+        let (len, dist) = find_match(data, pos, &mut table);
+        if len >= MIN_MATCH {
+            w.length(len)?;
+            w.distance(dist)?;
+            // index the skipped positions, so that later data can refer to them
+            for p in pos + 1..pos + len {
+                insert(data, p, &mut table);
+            }
+            pos += len;
+        } else {
+            w.literal(data[pos] as u16)?;
+            pos += 1;
+        }
+    }
+
+    // end of block
+    w.literal(256)?;
+    w.flush()?;
+
+    out.extend_from_slice(&crc32(data).to_le_bytes())?;
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes())
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let v = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (v.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Record the position in the hash table, returning the previous position of the same hash.
+fn insert(data: &[u8], pos: usize, table: &mut [u16]) -> Option<usize> {
+    if pos + MIN_MATCH > data.len() {
+        return None;
+    }
+    let h = hash(data, pos);
+    // positions are stored relative to the window, as they must fit into 16 bits
+    let previous = table[h];
+    table[h] = (pos % WINDOW) as u16;
+    if previous == u16::MAX {
+        return None;
+    }
+    let previous = pos - (pos % WINDOW) + previous as usize;
+    let previous = if previous >= pos {
+        previous.checked_sub(WINDOW)?
+    } else {
+        previous
+    };
+    Some(previous)
+}
+
+/// Find a repetition of the data at the position, returning its length and distance.
+fn find_match(data: &[u8], pos: usize, table: &mut [u16]) -> (usize, usize) {
+    let candidate = match insert(data, pos, table) {
+        Some(candidate) if pos - candidate <= WINDOW => candidate,
+        _ => return (0, 0),
+    };
+    let max = (data.len() - pos).min(MAX_MATCH);
+    let len = (0..max)
+        .take_while(|&i| data[candidate + i] == data[pos + i])
+        .count();
+    (len, pos - candidate)
+}
+
+/// Writes the bits of a deflate stream, starting with the least significant bit.
+struct BitWriter<'o, N>
+where
+    N: ArrayLength<u8>,
+{
+    out: &'o mut Vec<u8, N>,
+    bits: u32,
+    len: u8,
+}
+
+impl<'o, N> BitWriter<'o, N>
+where
+    N: ArrayLength<u8>,
+{
+    fn write(&mut self, value: u32, len: u8) -> Result<(), ()> {
+        self.bits |= value << self.len;
+        self.len += len;
+        while self.len >= 8 {
+            self.out.push(self.bits as u8).map_err(|_| ())?;
+            self.bits >>= 8;
+            self.len -= 8;
+        }
+        Ok(())
+    }
+
+    /// Write a Huffman code, which starts with the most significant bit.
+    fn code(&mut self, code: u32, len: u8) -> Result<(), ()> {
+        let reversed = code.reverse_bits() >> (32 - len as u32);
+        self.write(reversed, len)
+    }
+
+    /// Write a literal, or a length code, using the fixed codes.
+    fn literal(&mut self, value: u16) -> Result<(), ()> {
+        let value = value as u32;
+        match value {
+            0..=143 => self.code(0x30 + value, 8),
+            144..=255 => self.code(0x190 + value - 144, 9),
+            256..=279 => self.code(value - 256, 7),
+            _ => self.code(0xc0 + value - 280, 8),
+        }
+    }
+
+    fn length(&mut self, len: usize) -> Result<(), ()> {
+        let i = LENGTH_BASE
+            .iter()
+            .rposition(|&base| base as usize <= len)
+            .unwrap_or(0);
+        self.literal(257 + i as u16)?;
+        self.write((len - LENGTH_BASE[i] as usize) as u32, LENGTH_EXTRA[i])
+    }
+
+    fn distance(&mut self, dist: usize) -> Result<(), ()> {
+        let i = DIST_BASE
+            .iter()
+            .rposition(|&base| base as usize <= dist)
+            .unwrap_or(0);
+        self.code(i as u32, 5)?;
+        self.write((dist - DIST_BASE[i] as usize) as u32, DIST_EXTRA[i])
+    }
+
+    /// Write the remaining bits, padding the last byte.
+    fn flush(&mut self) -> Result<(), ()> {
+        if self.len > 0 {
+            self.write(0, 8 - self.len)?;
+        }
+        Ok(())
+    }
+}
+
+/// The CRC-32 checksum of gzip.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &b| {
+        (0..8).fold(crc ^ b as u32, |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
@@ -0,0 +1,933 @@
+//! HTTP/2 over cleartext TCP (`h2c`), with prior knowledge.
+//!
+//! Some servers, like collectors on an internal network, only speak HTTP/2. With prior
+//! knowledge, the client starts with HTTP/2 right away, without an upgrade from HTTP/1.1. The
+//! [`H2Connection`] executes one request at a time, each on a new stream, using the same
+//! [`Sink`] and [`Source`](crate::Source) transports as the [`HttpConnection`](crate::HttpConnection).
+//!
+//! Frames the client has to reply to, like the settings of the server, are acknowledged with
+//! the next request. During longer idle periods, they can be sent using
+//! [`H2Connection::send_pending`].
+//!
+//! This module requires the `h2` feature.
+//!
+//! ~~~no_run
+//! use heapless::consts::*;
+//!
+//! use drogue_http_client::h2::H2Connection;
+//! use drogue_http_client::tcp::TcpSocketSinkSource;
+//! use drogue_http_client::*;
+//!
+//! # use drogue_http_client::mock;
+//! # let (network, mut socket) = mock::mock_connection();
+//! let mut tcp = TcpSocketSinkSource::from(&network, &mut socket);
+//!
+//! let mut req = H2Connection::<U1024>::new()
+//!     .post("/telemetry")
+//!     .authority("collector:8080")
+//!     .json()
+//!     .handler(BufferResponseHandler::<U1024>::new())
+//!     .execute_with(&mut tcp, Some(b"{\"temp\":21.5}"));
+//!
+//! tcp.pipe_data(&mut req).unwrap();
+//!
+//! let (con, handler) = req.try_complete().unwrap();
+//! println!("Response: {}", handler.code());
+//! ~~~
+
+use crate::headers::{ResponseHeaders, CONTENT_TYPE};
+use crate::hpack::{self, EMPTY_FIELD};
+use crate::{
+    Error, Inbound, IncompleteReason, NoOpResponseHandler, Response, ResponseHandler, Sink,
+};
+use core::fmt::Write;
+use core::str::from_utf8;
+use heapless::{consts, ArrayLength, String, Vec};
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+const DEFAULT_WINDOW: u32 = 65_535;
+const MAX_WINDOW: u32 = 0x7fff_ffff;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+
+/// Connection specific headers, which must not be sent with HTTP/2.
+const CONNECTION_HEADERS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// The head of a frame.
+#[derive(Copy, Clone, Debug)]
+struct FrameHead {
+    len: usize,
+    kind: u8,
+    flags: u8,
+    stream: u32,
+}
+
+impl FrameHead {
+    fn parse(head: &[u8; 9]) -> Self {
+        FrameHead {
+            len: (head[0] as usize) << 16 | (head[1] as usize) << 8 | head[2] as usize,
+            kind: head[3],
+            flags: head[4],
+            stream: read_u32(&head[5..]) & MAX_WINDOW,
+        }
+    }
+
+    fn encode(&self) -> [u8; 9] {
+        let len = self.len as u32;
+        let stream = self.stream.to_be_bytes();
+        [
+            (len >> 16) as u8,
+            (len >> 8) as u8,
+            len as u8,
+            self.kind,
+            self.flags,
+            stream[0],
+            stream[1],
+            stream[2],
+            stream[3],
+        ]
+    }
+
+    fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// The number of payload bytes which must be buffered, the rest is ignored.
+    fn buffered(&self) -> usize {
+        match self.kind {
+            HEADERS | CONTINUATION | RST_STREAM | SETTINGS | PING | WINDOW_UPDATE => self.len,
+            // the debug data is of no interest
+            GOAWAY => self.len.min(8),
+            _ => 0,
+        }
+    }
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+/// An HTTP/2 connection, with prior knowledge.
+///
+/// Like the [`HttpConnection`](crate::HttpConnection), this only manages the state of the
+/// protocol. The transport must be established separately.
+pub struct H2Connection<IN>
+where
+    IN: ArrayLength<u8>,
+{
+    // inbound buffer, for header blocks and control frames
+    inbound: Vec<u8, IN>,
+    // the preface was sent
+    started: bool,
+    next_stream: u32,
+    // the stream is out of sync, and must be re-established
+    poisoned: bool,
+    // frames to send with the next request
+    pending: Vec<u8, consts::U64>,
+    // received payload, not yet returned to the flow control window
+    received: u32,
+    // the flow control window of the server
+    send_window: i64,
+    // settings of the server
+    initial_window: i64,
+    max_frame_size: usize,
+    // the inbound frame
+    head: [u8; 9],
+    head_len: usize,
+    frame: Option<FrameHead>,
+    frame_pos: usize,
+    padding: usize,
+    // a header block is continued by the next frame
+    continuation: Option<FrameHead>,
+}
+
+impl<IN> Default for H2Connection<IN>
+where
+    IN: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IN> H2Connection<IN>
+where
+    IN: ArrayLength<u8>,
+{
+    /// Create a new instance.
+    ///
+    /// The connection preface will be sent with the first request.
+    pub fn new() -> Self {
+        H2Connection {
+            inbound: Vec::new(),
+            started: false,
+            next_stream: 1,
+            poisoned: false,
+            pending: Vec::new(),
+            received: 0,
+            send_window: DEFAULT_WINDOW as i64,
+            initial_window: DEFAULT_WINDOW as i64,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            head: [0u8; 9],
+            head_len: 0,
+            frame: None,
+            frame_pos: 0,
+            padding: 0,
+            continuation: None,
+        }
+    }
+
+    /// Check if the connection is poisoned.
+    ///
+    /// A connection gets poisoned when processing a frame failed, or the server shut down the
+    /// connection. The underlying transport must be re-established, and the connection
+    /// [`reset`](H2Connection::reset).
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Reset the state of the connection, after the underlying transport was re-established.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Send frames, which are pending to acknowledge frames of the server.
+    pub fn send_pending<S>(&mut self, sink: &mut S) -> Result<(), ()>
+    where
+        S: Sink,
+    {
+        if !self.started {
+            sink.send_all(PREFACE)?;
+            self.send_frame(sink, SETTINGS, 0, 0, &settings())?;
+            self.send_frame(
+                sink,
+                WINDOW_UPDATE,
+                0,
+                0,
+                &(MAX_WINDOW - DEFAULT_WINDOW).to_be_bytes(),
+            )?;
+            self.started = true;
+        }
+
+        if self.received > 0 {
+            let increment = core::mem::take(&mut self.received);
+            self.send_frame(sink, WINDOW_UPDATE, 0, 0, &increment.to_be_bytes())?;
+        }
+
+        let pending = core::mem::take(&mut self.pending);
+        sink.send_all(&pending)?;
+        sink.flush()
+    }
+
+    /// Begin a new HTTP request.
+    ///
+    /// The request will only be sent to the sink (server) when one of the "execute" functions
+    /// is being invoked. If the connection is poisoned, the request will not be sent.
+    pub fn begin<'req>(
+        self,
+        method: &'req str,
+        path: &'req str,
+    ) -> H2RequestBuilder<'req, IN, NoOpResponseHandler> {
+        debug!("Begin new request - method: {}, path: {}", method, path);
+
+        if self.poisoned {
+            log::warn!("Connection is poisoned, reset it before executing requests");
+        }
+
+        H2RequestBuilder {
+            connection: self,
+            method,
+            path,
+            authority: None,
+            headers: None,
+            content_type: None,
+            handler: NoOpResponseHandler,
+            _buffer: core::marker::PhantomData,
+        }
+    }
+
+    /// Begin a new POST HTTP request.
+    pub fn post<'req>(self, path: &'req str) -> H2RequestBuilder<'req, IN, NoOpResponseHandler> {
+        self.begin("POST", path)
+    }
+
+    fn send_frame<S>(
+        &mut self,
+        sink: &mut S,
+        kind: u8,
+        flags: u8,
+        stream: u32,
+        payload: &[u8],
+    ) -> Result<(), ()>
+    where
+        S: Sink,
+    {
+        let head = FrameHead {
+            len: payload.len(),
+            kind,
+            flags,
+            stream,
+        };
+        sink.send_all(&head.encode())?;
+        sink.send_all(payload)
+    }
+
+    /// Queue a frame to be sent with the next request.
+    fn queue_frame(&mut self, kind: u8, flags: u8, payload: &[u8]) {
+        let head = FrameHead {
+            len: payload.len(),
+            kind,
+            flags,
+            stream: 0,
+        };
+        if self.pending.extend_from_slice(&head.encode()).is_err()
+            || self.pending.extend_from_slice(payload).is_err()
+        {
+            log::warn!("Too many pending frames, dropping frame: {}", kind);
+        }
+    }
+}
+
+/// The settings of the client: no server push, no dynamic header table, and the maximum flow
+/// control window, so that the window only needs to be updated once per request.
+fn settings() -> [u8; 18] {
+    let mut settings = [0u8; 18];
+    for (i, (id, value)) in [
+        (SETTINGS_HEADER_TABLE_SIZE, 0),
+        (SETTINGS_ENABLE_PUSH, 0),
+        (SETTINGS_INITIAL_WINDOW_SIZE, MAX_WINDOW),
+    ]
+    .iter()
+    .enumerate()
+    {
+        settings[i * 6..i * 6 + 2].copy_from_slice(&id.to_be_bytes());
+        settings[i * 6 + 2..i * 6 + 6].copy_from_slice(&value.to_be_bytes());
+    }
+    settings
+}
+
+/// A request builder for an HTTP/2 request.
+///
+/// The header block is encoded into a buffer of capacity `N`.
+pub struct H2RequestBuilder<'req, IN, R, N = consts::U256>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+    N: ArrayLength<u8>,
+{
+    connection: H2Connection<IN>,
+    method: &'req str,
+    path: &'req str,
+    authority: Option<&'req str>,
+    headers: Option<&'req [(&'req str, &'req str)]>,
+    content_type: Option<&'req str>,
+    handler: R,
+    _buffer: core::marker::PhantomData<N>,
+}
+
+impl<'req, IN, R, N> H2RequestBuilder<'req, IN, R, N>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+    N: ArrayLength<u8>,
+{
+    /// Set the authority (host and port) of the request.
+    pub fn authority(mut self, authority: &'req str) -> Self {
+        self.authority = Some(authority);
+        self
+    }
+
+    /// Set the HTTP headers to send.
+    ///
+    /// Connection specific headers, which are not allowed with HTTP/2, are dropped. A `Host`
+    /// header is sent as authority, unless one was set.
+    pub fn headers(mut self, headers: &'req [(&'req str, &'req str)]) -> Self {
+        self.headers = Some(headers);
+        self
+    }
+
+    /// Set the content type of the request payload.
+    ///
+    /// This overrides a `Content-Type` header, set using [`H2RequestBuilder::headers`].
+    pub fn content_type(mut self, content_type: &'req str) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    /// Set the content type to `application/json`.
+    pub fn json(self) -> Self {
+        self.content_type("application/json")
+    }
+
+    /// Set the content type to `text/plain`.
+    pub fn text(self) -> Self {
+        self.content_type("text/plain")
+    }
+
+    /// Set the content type to `application/octet-stream`.
+    pub fn octet_stream(self) -> Self {
+        self.content_type("application/octet-stream")
+    }
+
+    /// Change the capacity of the buffer for the header block.
+    pub fn buffer_capacity<NN>(self) -> H2RequestBuilder<'req, IN, R, NN>
+    where
+        NN: ArrayLength<u8>,
+    {
+        H2RequestBuilder {
+            connection: self.connection,
+            method: self.method,
+            path: self.path,
+            authority: self.authority,
+            headers: self.headers,
+            content_type: self.content_type,
+            handler: self.handler,
+            _buffer: core::marker::PhantomData,
+        }
+    }
+
+    /// Set the handler that will process the response.
+    pub fn handler<RN: ResponseHandler>(self, handler: RN) -> H2RequestBuilder<'req, IN, RN, N> {
+        H2RequestBuilder {
+            connection: self.connection,
+            method: self.method,
+            path: self.path,
+            authority: self.authority,
+            headers: self.headers,
+            content_type: self.content_type,
+            handler,
+            _buffer: core::marker::PhantomData,
+        }
+    }
+
+    /// Execute the request, without a payload.
+    pub fn execute<S>(self, sink: &mut S) -> H2Request<IN, R>
+    where
+        S: Sink,
+    {
+        self.execute_with(sink, None)
+    }
+
+    /// Execute the request, with an optional payload.
+    ///
+    /// The payload must fit into the flow control window of the server, which is at least
+    /// 64 KiB.
+    pub fn execute_with<S>(mut self, sink: &mut S, payload: Option<&[u8]>) -> H2Request<IN, R>
+    where
+        S: Sink,
+    {
+        let stream = self.connection.next_stream;
+        let sent = !self.connection.poisoned
+            && stream <= MAX_WINDOW
+            && self.send(sink, stream, payload.unwrap_or_default()).is_ok();
+        self.connection.next_stream = stream.saturating_add(2);
+
+        let mut request = H2Request {
+            connection: self.connection,
+            handler: self.handler,
+            stream,
+            state: State::Header,
+        };
+        if !sent {
+            request.fail(Error::Send);
+        }
+        request
+    }
+
+    fn send<S>(&mut self, sink: &mut S, stream: u32, payload: &[u8]) -> Result<(), ()>
+    where
+        S: Sink,
+    {
+        let block = self.header_block(payload)?;
+        if block.len() > self.connection.max_frame_size {
+            log::warn!("Header block exceeds the frame size: {}", block.len());
+            return Err(());
+        }
+        let window = self
+            .connection
+            .send_window
+            .min(self.connection.initial_window);
+        if payload.len() as i64 > window {
+            log::warn!("Payload exceeds the flow control window: {}", window);
+            return Err(());
+        }
+
+        self.connection.send_pending(sink)?;
+
+        let flags = if payload.is_empty() {
+            END_HEADERS | END_STREAM
+        } else {
+            END_HEADERS
+        };
+        self.connection
+            .send_frame(sink, HEADERS, flags, stream, &block)?;
+
+        let mut chunks = payload.chunks(self.connection.max_frame_size).peekable();
+        while let Some(chunk) = chunks.next() {
+            let flags = if chunks.peek().is_none() {
+                END_STREAM
+            } else {
+                0
+            };
+            self.connection
+                .send_frame(sink, DATA, flags, stream, chunk)?;
+        }
+        self.connection.send_window -= payload.len() as i64;
+
+        sink.flush()
+    }
+
+    fn header_block(&self, payload: &[u8]) -> Result<Vec<u8, N>, ()> {
+        let mut authority = self.authority;
+        let mut block = Vec::new();
+        hpack::encode_field(&mut block, ":method", self.method)?;
+        hpack::encode_field(&mut block, ":scheme", "http")?;
+        hpack::encode_field(&mut block, ":path", self.path)?;
+
+        let mut regular = Vec::<u8, N>::new();
+        for (name, value) in self.headers.unwrap_or_default() {
+            if name.eq_ignore_ascii_case("host") {
+                authority.get_or_insert(value);
+            } else if CONNECTION_HEADERS
+                .iter()
+                .any(|n| n.eq_ignore_ascii_case(name))
+                || (self.content_type.is_some() && CONTENT_TYPE.matches(name))
+            {
+                debug!("Dropping header: {}", name);
+            } else {
+                hpack::encode_field(&mut regular, name, value)?;
+            }
+        }
+        if let Some(content_type) = self.content_type {
+            hpack::encode_field(&mut regular, "content-type", content_type)?;
+        }
+        if !payload.is_empty() {
+            let mut len = String::<consts::U20>::new();
+            write!(len, "{}", payload.len()).map_err(|_| ())?;
+            hpack::encode_field(&mut regular, "content-length", &len)?;
+        }
+
+        // pseudo headers must come first
+        if let Some(authority) = authority {
+            hpack::encode_field(&mut block, ":authority", authority)?;
+        }
+        block.extend_from_slice(&regular)?;
+
+        Ok(block)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum State {
+    Header,
+    Payload,
+    Complete,
+    Failed(Error),
+}
+
+/// The ongoing HTTP/2 request.
+pub struct H2Request<IN, R>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+{
+    connection: H2Connection<IN>,
+    handler: R,
+    stream: u32,
+    state: State,
+}
+
+impl<IN, R> H2Request<IN, R>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+{
+    /// Check if the request is completely processed.
+    pub fn is_complete(&self) -> bool {
+        matches!(self.state, State::Complete)
+    }
+
+    /// Get the error, if processing the response failed.
+    pub fn error(&self) -> Option<Error> {
+        match self.state {
+            State::Failed(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Stop processing the request, gives back the handler and connection.
+    pub fn complete(self) -> (H2Connection<IN>, R) {
+        (self.connection, self.handler)
+    }
+
+    /// Stop processing the request, once the response is complete.
+    ///
+    /// If the response is not complete, or processing it failed, the request is given back
+    /// with the reason. It can still be stopped using [`H2Request::complete`], to get back the
+    /// handler and connection.
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    pub fn try_complete(self) -> Result<(H2Connection<IN>, R), (Self, IncompleteReason)> {
+        let reason = match self.state {
+            State::Header => IncompleteReason::Header,
+            State::Payload => IncompleteReason::Body,
+            State::Complete => return Ok(self.complete()),
+            State::Failed(err) => IncompleteReason::Failed(err),
+        };
+        Err((self, reason))
+    }
+
+    /// Fail the request, and poison the connection.
+    fn fail(&mut self, err: Error) {
+        self.fail_stream(err);
+        self.connection.poisoned = true;
+        self.connection.inbound = Vec::new();
+    }
+
+    /// Fail the request, keeping the connection.
+    fn fail_stream(&mut self, err: Error) {
+        if matches!(self.state, State::Complete | State::Failed(_)) {
+            return;
+        }
+        log::info!("Request failed: {:?}", err);
+        self.state = State::Failed(err);
+        self.handler.more_payload(Err(err));
+    }
+
+    fn complete_response(&mut self) {
+        self.state = State::Complete;
+        self.handler.more_payload(Ok(None));
+    }
+
+    /// Push more inbound data to the HTTP processing.
+    pub fn push_data(&mut self, mut data: &[u8]) {
+        while !data.is_empty() && !self.connection.poisoned {
+            let con = &mut self.connection;
+            let frame = match con.frame {
+                Some(frame) => frame,
+                None => {
+                    let len = data.len().min(9 - con.head_len);
+                    con.head[con.head_len..con.head_len + len].copy_from_slice(&data[..len]);
+                    con.head_len += len;
+                    data = &data[len..];
+                    if con.head_len < 9 {
+                        return;
+                    }
+
+                    let frame = FrameHead::parse(&con.head);
+                    con.head_len = 0;
+                    con.frame_pos = 0;
+                    con.padding = 0;
+                    if let Err(err) = self.begin_frame(frame) {
+                        self.fail(err);
+                        return;
+                    }
+                    self.connection.frame = Some(frame);
+                    frame
+                }
+            };
+
+            let con = &mut self.connection;
+            let len = data.len().min(frame.len - con.frame_pos);
+            let (chunk, rest) = data.split_at(len);
+            data = rest;
+
+            let result = if frame.kind == DATA {
+                self.push_frame_data(frame, chunk)
+            } else {
+                let keep = frame.buffered().saturating_sub(con.frame_pos).min(len);
+                con.inbound
+                    .extend_from_slice(&chunk[..keep])
+                    .map_err(|_| match frame.kind {
+                        HEADERS | CONTINUATION => Error::Parse(httparse::Error::TooManyHeaders),
+                        _ => Error::Frame,
+                    })
+            };
+            self.connection.frame_pos += len;
+
+            let result = result.and_then(|_| {
+                if self.connection.frame_pos == frame.len {
+                    self.connection.frame = None;
+                    self.end_frame(frame)
+                } else {
+                    Ok(())
+                }
+            });
+            if let Err(err) = result {
+                self.fail(err);
+            }
+        }
+    }
+
+    /// Validate the head of a new frame.
+    fn begin_frame(&mut self, frame: FrameHead) -> Result<(), Error> {
+        debug!("Frame: {:?}", frame);
+        if frame.len > DEFAULT_MAX_FRAME_SIZE {
+            return Err(Error::Frame);
+        }
+        match self.connection.continuation {
+            // a header block must not be interrupted
+            Some(headers) if frame.kind != CONTINUATION || frame.stream != headers.stream => {
+                Err(Error::Frame)
+            }
+            None if frame.kind == CONTINUATION => Err(Error::Frame),
+            _ if frame.kind == DATA && frame.stream == 0 => Err(Error::Frame),
+            _ if frame.kind == PUSH_PROMISE => Err(Error::Frame),
+            _ => Ok(()),
+        }
+    }
+
+    fn push_frame_data(&mut self, frame: FrameHead, mut chunk: &[u8]) -> Result<(), Error> {
+        let con = &mut self.connection;
+        con.received = con.received.saturating_add(chunk.len() as u32);
+
+        let mut pos = con.frame_pos;
+        if frame.has(PADDED) && pos == 0 && !chunk.is_empty() {
+            con.padding = chunk[0] as usize;
+            if con.padding >= frame.len {
+                return Err(Error::Frame);
+            }
+            chunk = &chunk[1..];
+            pos += 1;
+        }
+
+        if frame.stream != self.stream {
+            return Ok(());
+        }
+        if !matches!(self.state, State::Payload) {
+            return match self.state {
+                State::Header => Err(Error::Frame),
+                _ => Ok(()),
+            };
+        }
+
+        let end = frame.len - self.connection.padding;
+        let len = end.saturating_sub(pos).min(chunk.len());
+        if len > 0 {
+            self.handler.more_payload(Ok(Some(&chunk[..len])));
+        }
+        Ok(())
+    }
+
+    /// Process a completely received frame.
+    fn end_frame(&mut self, frame: FrameHead) -> Result<(), Error> {
+        let payload = core::mem::take(&mut self.connection.inbound);
+
+        match frame.kind {
+            DATA if frame.stream == self.stream && frame.has(END_STREAM) => {
+                self.end_stream();
+            }
+            HEADERS => {
+                let mut start = 0;
+                let mut end = payload.len();
+                if frame.has(PADDED) {
+                    start += 1;
+                    end = end
+                        .checked_sub(*payload.first().ok_or(Error::Frame)? as usize)
+                        .ok_or(Error::Frame)?;
+                }
+                if frame.has(PRIORITY) {
+                    start += 5;
+                }
+                let fragment = payload.get(start..end).ok_or(Error::Frame)?;
+                self.header_fragment(frame, fragment)?;
+            }
+            CONTINUATION => {
+                let headers = self.connection.continuation.take().ok_or(Error::Frame)?;
+                let headers = FrameHead {
+                    flags: headers.flags | frame.flags & END_HEADERS,
+                    ..headers
+                };
+                self.header_fragment(headers, &payload)?;
+            }
+            RST_STREAM if payload.len() != 4 => return Err(Error::Frame),
+            RST_STREAM if frame.stream == self.stream => {
+                self.fail_stream(Error::Reset(read_u32(&payload)));
+            }
+            SETTINGS if frame.stream != 0 || !payload.chunks_exact(6).remainder().is_empty() => {
+                return Err(Error::Frame)
+            }
+            SETTINGS if !frame.has(ACK) => {
+                for setting in payload.chunks(6) {
+                    self.setting(
+                        u16::from_be_bytes([setting[0], setting[1]]),
+                        read_u32(&setting[2..]),
+                    )?;
+                }
+                self.connection.queue_frame(SETTINGS, ACK, &[]);
+            }
+            PING if payload.len() != 8 => return Err(Error::Frame),
+            PING if !frame.has(ACK) => {
+                self.connection.queue_frame(PING, ACK, &payload);
+            }
+            GOAWAY if payload.len() < 8 => return Err(Error::Frame),
+            GOAWAY => {
+                let last_stream = read_u32(&payload) & MAX_WINDOW;
+                let code = read_u32(&payload[4..]);
+                log::info!("Server shuts down the connection: {:#x}", code);
+                if last_stream < self.stream {
+                    // the request was not processed, and may be retried
+                    self.fail_stream(Error::Reset(code));
+                }
+                self.connection.poisoned = true;
+            }
+            WINDOW_UPDATE if payload.len() != 4 => return Err(Error::Frame),
+            WINDOW_UPDATE if frame.stream == 0 => {
+                self.connection.send_window += (read_u32(&payload) & MAX_WINDOW) as i64;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn setting(&mut self, id: u16, value: u32) -> Result<(), Error> {
+        debug!("Setting: {} = {}", id, value);
+        match id {
+            SETTINGS_INITIAL_WINDOW_SIZE if value > MAX_WINDOW => return Err(Error::Frame),
+            SETTINGS_INITIAL_WINDOW_SIZE => self.connection.initial_window = value as i64,
+            SETTINGS_MAX_FRAME_SIZE if !(16_384..=0xff_ffff).contains(&value) => {
+                return Err(Error::Frame)
+            }
+            SETTINGS_MAX_FRAME_SIZE => self.connection.max_frame_size = value as usize,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Process a fragment of a header block, `frame` is the head of the `HEADERS` frame.
+    fn header_fragment(&mut self, frame: FrameHead, fragment: &[u8]) -> Result<(), Error> {
+        if !frame.has(END_HEADERS) {
+            // keep the fragment, until the block is complete
+            self.connection.inbound = Vec::from_slice(fragment)
+                .map_err(|_| Error::Parse(httparse::Error::TooManyHeaders))?;
+            self.connection.continuation = Some(frame);
+            return Ok(());
+        }
+
+        if frame.stream == self.stream {
+            if let State::Header = self.state {
+                self.response(fragment)?;
+            }
+            if frame.has(END_STREAM) {
+                self.end_stream();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn end_stream(&mut self) {
+        match self.state {
+            State::Payload => self.complete_response(),
+            State::Header => self.fail_stream(Error::Frame),
+            State::Complete | State::Failed(_) => {}
+        }
+    }
+
+    /// Pass on the response header to the handler.
+    fn response(&mut self, block: &[u8]) -> Result<(), Error> {
+        let mut buffer = Vec::<u8, IN>::new();
+        let mut fields = [EMPTY_FIELD; 16];
+        let len = hpack::decode(block, &mut buffer, &mut fields)?;
+
+        let mut code = None;
+        let mut headers = [httparse::EMPTY_HEADER; 16];
+        let mut count = 0;
+        for field in &fields[..len] {
+            let name = from_utf8(field.name.as_bytes(&buffer)).map_err(|_| Error::Frame)?;
+            let value = field.value.as_bytes(&buffer);
+            if name == ":status" {
+                code = from_utf8(value).ok().and_then(|v| v.parse::<u16>().ok());
+            } else if !name.starts_with(':') {
+                headers[count] = httparse::Header { name, value };
+                count += 1;
+            }
+        }
+        let code = code.ok_or(Error::Frame)?;
+
+        if (100..200).contains(&code) {
+            debug!("Skipping informational response: {}", code);
+            return Ok(());
+        }
+
+        self.state = State::Payload;
+        self.handler.response(Response {
+            version: 2,
+            code,
+            reason: "",
+            headers: ResponseHeaders::new(&headers[..count]),
+        });
+
+        Ok(())
+    }
+
+    /// Notify the HTTP processing that the source has closed.
+    pub fn push_close(&mut self) {
+        if !self.is_complete() {
+            self.fail(Error::IncompleteBody);
+        }
+        self.connection.poisoned = true;
+    }
+}
+
+impl<IN, R> core::fmt::Debug for H2Request<IN, R>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("H2Request")
+            .field("stream", &self.stream)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl<IN, R> Inbound for H2Request<IN, R>
+where
+    IN: ArrayLength<u8>,
+    R: ResponseHandler,
+{
+    fn push_data(&mut self, data: &[u8]) {
+        H2Request::push_data(self, data)
+    }
+
+    fn push_close(&mut self) {
+        H2Request::push_close(self)
+    }
+
+    fn is_complete(&self) -> bool {
+        H2Request::is_complete(self)
+    }
+
+    fn error(&self) -> Option<Error> {
+        H2Request::error(self)
+    }
+}
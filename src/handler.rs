@@ -1,4 +1,10 @@
-use crate::Response;
+use crate::headers::{
+    allowed_methods, media_type, HeaderEntries, HeaderMap, HeaderName, ResponseHeaders, ALLOW,
+    CONTENT_TYPE,
+};
+use crate::{Error, Response};
+
+use core::str::{from_utf8, Utf8Error};
 
 use heapless::consts;
 use heapless::String;
@@ -9,46 +15,187 @@ pub struct NoOpResponseHandler;
 
 impl ResponseHandler for NoOpResponseHandler {
     fn response(&mut self, _: Response) {}
-    fn more_payload(&mut self, _: Result<Option<&[u8]>, ()>) {}
+    fn more_payload(&mut self, _: Result<Option<&[u8]>, Error>) {}
 }
 
 /// A trait handling responses to an HTTP request.
 pub trait ResponseHandler {
     fn response(&mut self, response: Response);
-    fn more_payload(&mut self, payload: Result<Option<&[u8]>, ()>);
+
+    /// Handle more payload.
+    ///
+    /// `Ok(None)` marks the end of the payload, while an error reports that processing the
+    /// response failed. In both cases, no more payload will follow.
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>);
+
+    /// Consume payload data, returning the number of bytes which have been processed.
+    ///
+    /// Bytes which have not been consumed will be buffered by the request, and delivered again
+    /// later. The default implementation passes all data on to
+    /// [`ResponseHandler::more_payload`].
+    fn consume_payload(&mut self, payload: &[u8]) -> usize {
+        self.more_payload(Ok(Some(payload)));
+        payload.len()
+    }
+
+    /// Handle the trailer of a body using the "chunked" transfer encoding.
+    ///
+    /// This is called once the body was received, before the end of the payload is reported,
+    /// and only if the trailer has any fields. The default implementation ignores them.
+    fn trailers(&mut self, _trailers: ResponseHeaders) {}
+
+    /// Get the error, if the handler failed processing the response.
+    ///
+    /// This fails the request, so no more of the response is read. The default implementation
+    /// never fails.
+    fn failed(&self) -> Option<Error> {
+        None
+    }
+}
+
+/// A response handler, which produces an output once the response is complete.
+///
+/// This is separate from [`ResponseHandler`], so that handlers which don't have a meaningful
+/// output don't need to declare one. It allows [`Request::finish`](crate::Request::finish) to
+/// return the result directly, instead of digging it out of the handler.
+pub trait FinishResponse: ResponseHandler {
+    /// The result of processing the response.
+    type Output;
+
+    /// Take the output, failing if processing the response failed, or it is incomplete.
+    fn finish(self) -> Result<Self::Output, Error>;
 }
 
+impl FinishResponse for NoOpResponseHandler {
+    type Output = ();
+
+    fn finish(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// What to do when the body of a response exceeds the buffer.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Fail the request, with [`Error::Overflow`], without reading the rest of the body.
+    Fail,
+    /// Keep the beginning of the body, dropping the rest.
+    #[default]
+    Truncate,
+    /// Keep the end of the body, dropping data from the beginning.
+    KeepLast,
+}
+
+/// The default capacity for the reason phrase.
+#[cfg(not(feature = "tiny"))]
+type DefaultReason = consts::U128;
+#[cfg(feature = "tiny")]
+type DefaultReason = consts::U0;
+
 /// A response handler, that will buffer all data.
-pub struct BufferResponseHandler<N, NR = consts::U128>
+///
+/// The reason phrase is stored in a string of capacity `NR`. If you don't need the reason
+/// phrase, you can set `NR` to `U0` and disable capturing it on the connection or request. This
+/// is the default with the `tiny` feature.
+///
+/// Response headers are only kept when selected using
+/// [`BufferResponseHandler::capture_headers`], they are stored in a [`HeaderMap`] of capacity
+/// `NH`.
+///
+/// A body exceeding the buffer is handled according to the [`OverflowPolicy`], which defaults to
+/// truncating it.
+pub struct BufferResponseHandler<N, NR = DefaultReason, NH = consts::U0>
 where
     N: ArrayLength<u8>,
     NR: ArrayLength<u8>,
+    NH: ArrayLength<u8>,
 {
     version: u8,
     code: u16,
     reason: Option<String<NR>>,
+    capture: &'static [HeaderName<'static>],
+    headers: HeaderMap<NH>,
     payload: Vec<u8, N>,
+    overflow: OverflowPolicy,
+    truncated: bool,
     complete: bool,
+    error: Option<Error>,
 }
 
-impl<N> BufferResponseHandler<N>
+impl<N, NR, NH> Default for BufferResponseHandler<N, NR, NH>
 where
     N: ArrayLength<u8>,
+    NR: ArrayLength<u8>,
+    NH: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N, NR, NH> BufferResponseHandler<N, NR, NH>
+where
+    N: ArrayLength<u8>,
+    NR: ArrayLength<u8>,
+    NH: ArrayLength<u8>,
 {
     pub fn new() -> Self {
         BufferResponseHandler {
             version: 0u8,
             code: 0u16,
             reason: None,
+            capture: &[],
+            headers: HeaderMap::new(),
             payload: Vec::new(),
+            overflow: OverflowPolicy::default(),
+            truncated: false,
             complete: false,
+            error: None,
         }
     }
 
+    /// Set what to do when the body exceeds the buffer.
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Check if data of the body was dropped, because it exceeded the buffer.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Select the response headers to keep.
+    ///
+    /// Headers which don't fit into the storage are dropped, with a warning.
+    ///
+    /// ~~~
+    /// use heapless::consts::*;
+    /// use drogue_http_client::headers::{ETAG, LOCATION};
+    /// use drogue_http_client::BufferResponseHandler;
+    ///
+    /// let handler = BufferResponseHandler::<U1024, U0, U128>::new()
+    ///     .capture_headers(&[ETAG, LOCATION]);
+    /// ~~~
+    pub fn capture_headers(mut self, names: &'static [HeaderName<'static>]) -> Self {
+        self.capture = names;
+        self
+    }
+
+    /// The captured response headers.
+    pub fn headers(&self) -> HeaderEntries<'_> {
+        self.headers.entries()
+    }
+
     pub fn is_complete(&self) -> bool {
         self.complete
     }
 
+    /// The error, in case processing the response failed.
+    pub fn error(&self) -> Option<Error> {
+        self.error
+    }
+
     pub fn code(&self) -> u16 {
         self.code
     }
@@ -64,29 +211,849 @@ where
     pub fn payload(&self) -> &[u8] {
         &self.payload
     }
+
+    /// The payload, as a string.
+    ///
+    /// Fails if the payload isn't valid UTF-8. This may also happen if the payload was truncated
+    /// in the middle of a character.
+    pub fn payload_str(&self) -> Result<&str, Utf8Error> {
+        from_utf8(&self.payload)
+    }
+
+    /// Take the payload, without copying it.
+    pub fn into_payload(self) -> Vec<u8, N> {
+        self.payload
+    }
+
+    /// Take the status code, reason phrase, captured headers, and payload.
+    pub fn into_parts(self) -> (u16, Option<String<NR>>, HeaderMap<NH>, Vec<u8, N>) {
+        (self.code, self.reason, self.headers, self.payload)
+    }
+
+    fn append(&mut self, data: &[u8]) {
+        let free = self.payload.capacity() - self.payload.len();
+        if data.len() <= free {
+            // can't fail, as it fits
+            self.payload.extend_from_slice(data).ok();
+            return;
+        }
+
+        debug!("Payload exceeds buffer: {:?}", self.overflow);
+        self.truncated = true;
+        match self.overflow {
+            OverflowPolicy::Fail => self.error = Some(Error::Overflow),
+            OverflowPolicy::Truncate => {
+                self.payload.extend_from_slice(&data[..free]).ok();
+            }
+            OverflowPolicy::KeepLast => {
+                let capacity = self.payload.capacity();
+                let data = &data[data.len().saturating_sub(capacity)..];
+                let keep = capacity - data.len();
+                let mut payload =
+                    Vec::from_slice(&self.payload[self.payload.len() - keep..]).unwrap_or_default();
+                payload.extend_from_slice(data).ok();
+                self.payload = payload;
+            }
+        }
+    }
 }
 
-impl<N> ResponseHandler for BufferResponseHandler<N>
+impl<N, NR, NH> ResponseHandler for BufferResponseHandler<N, NR, NH>
 where
     N: ArrayLength<u8>,
+    NR: ArrayLength<u8>,
+    NH: ArrayLength<u8>,
 {
     fn response(&mut self, response: Response<'_>) {
         self.version = response.version;
         self.code = response.code;
-        self.reason = Some(String::from(response.reason));
+        self.reason = match response.reason {
+            // reason capturing is disabled, or there simply is none
+            "" => None,
+            reason => {
+                let mut s = String::new();
+                // truncate at a character boundary, if the reason doesn't fit
+                for c in reason.chars() {
+                    if s.push(c).is_err() {
+                        break;
+                    }
+                }
+                Some(s)
+            }
+        };
+
+        self.headers = capture_headers(self.capture, &response);
     }
 
-    fn more_payload(&mut self, payload: Result<Option<&[u8]>, ()>) {
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        if self.error.is_some() {
+            // the body exceeded the buffer
+            return;
+        }
+
         match payload {
             Ok(Some(data)) => {
-                log::debug!("Append payload data: {:?}", data);
-                self.payload.extend_from_slice(data).ok();
+                debug!("Append payload data: {:?}", data);
+                self.append(data);
             }
             Ok(None) => {
-                log::debug!("Complete response");
+                debug!("Complete response");
                 self.complete = true;
             }
-            Err(_) => {}
+            Err(err) => {
+                debug!("Failed response: {:?}", err);
+                self.error = Some(err);
+            }
+        }
+    }
+
+    fn failed(&self) -> Option<Error> {
+        self.error
+    }
+}
+
+/// A response handler, that writes the payload into a borrowed buffer.
+///
+/// This allows re-using a single buffer for multiple requests. A payload exceeding the buffer is
+/// handled according to the [`OverflowPolicy`], which defaults to truncating it.
+///
+/// Finishing the request reports the status code, and the number of bytes written to the buffer,
+/// see [`Request::finish`](crate::Request::finish).
+///
+/// ~~~
+/// use heapless::{consts::*, Vec};
+/// use drogue_http_client::*;
+///
+/// let mut buffer = [0u8; 512];
+///
+/// # let mut sink = Vec::<u8, U256>::new();
+/// let mut req = HttpConnection::<U1024>::new()
+///     .begin("GET", "/config")
+///     .handler(SliceResponseHandler::new(&mut buffer).overflow(OverflowPolicy::Fail))
+///     .execute(&mut sink);
+/// req.push_data(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHello");
+///
+/// let (_, result) = req.finish();
+/// assert_eq!(result, Ok((200, 5)));
+/// assert_eq!(&buffer[..5], b"Hello");
+/// ~~~
+pub struct SliceResponseHandler<'b> {
+    code: u16,
+    buffer: &'b mut [u8],
+    len: usize,
+    overflow: OverflowPolicy,
+    truncated: bool,
+    complete: bool,
+    error: Option<Error>,
+}
+
+impl<'b> SliceResponseHandler<'b> {
+    pub fn new(buffer: &'b mut [u8]) -> Self {
+        SliceResponseHandler {
+            code: 0u16,
+            buffer,
+            len: 0,
+            overflow: OverflowPolicy::default(),
+            truncated: false,
+            complete: false,
+            error: None,
+        }
+    }
+
+    /// Set what to do when the payload exceeds the buffer.
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The error, in case processing the response failed.
+    pub fn error(&self) -> Option<Error> {
+        self.error
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// Check if data of the payload was dropped, because it exceeded the buffer.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The number of bytes written to the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if no payload was written to the buffer.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// Release the buffer, returning the number of bytes written to it.
+    pub fn into_len(self) -> usize {
+        self.len
+    }
+
+    fn append(&mut self, data: &[u8]) {
+        let free = self.buffer.len() - self.len;
+        if data.len() <= free {
+            self.buffer[self.len..self.len + data.len()].copy_from_slice(data);
+            self.len += data.len();
+            return;
+        }
+
+        debug!("Payload exceeds buffer: {:?}", self.overflow);
+        self.truncated = true;
+        match self.overflow {
+            OverflowPolicy::Fail => self.error = Some(Error::Overflow),
+            OverflowPolicy::Truncate => {
+                self.buffer[self.len..].copy_from_slice(&data[..free]);
+                self.len = self.buffer.len();
+            }
+            OverflowPolicy::KeepLast => {
+                let capacity = self.buffer.len();
+                let data = &data[data.len().saturating_sub(capacity)..];
+                let keep = capacity - data.len();
+                self.buffer.copy_within(self.len - keep..self.len, 0);
+                self.buffer[keep..].copy_from_slice(data);
+                self.len = capacity;
+            }
+        }
+    }
+}
+
+impl<'b> ResponseHandler for SliceResponseHandler<'b> {
+    fn response(&mut self, response: Response<'_>) {
+        self.code = response.code;
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        if self.error.is_some() {
+            // the payload exceeded the buffer
+            return;
+        }
+
+        match payload {
+            Ok(Some(data)) => self.append(data),
+            Ok(None) => self.complete = true,
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    fn failed(&self) -> Option<Error> {
+        self.error
+    }
+}
+
+impl<'b> FinishResponse for SliceResponseHandler<'b> {
+    /// The status code, and the number of bytes written to the buffer.
+    type Output = (u16, usize);
+
+    fn finish(self) -> Result<Self::Output, Error> {
+        match self.error {
+            Some(err) => Err(err),
+            None if !self.complete => Err(Error::IncompleteBody),
+            None => Ok((self.code, self.len)),
+        }
+    }
+}
+
+impl<N, NR, NH> FinishResponse for BufferResponseHandler<N, NR, NH>
+where
+    N: ArrayLength<u8>,
+    NR: ArrayLength<u8>,
+    NH: ArrayLength<u8>,
+{
+    /// The status code, and the payload.
+    type Output = (u16, Vec<u8, N>);
+
+    fn finish(self) -> Result<Self::Output, Error> {
+        match self.error {
+            Some(err) => Err(err),
+            None if !self.complete => Err(Error::IncompleteBody),
+            None => Ok((self.code, self.payload)),
+        }
+    }
+}
+
+/// A response handler, that only keeps the status code.
+///
+/// The payload is discarded. This is the handler with the smallest footprint, for cases where
+/// all that matters is whether the request succeeded.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StatusOnlyResponseHandler {
+    code: u16,
+    complete: bool,
+}
+
+impl StatusOnlyResponseHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// Check if the response was complete, and its status code is `2xx`.
+    pub fn is_success(&self) -> bool {
+        self.complete && (200..300).contains(&self.code)
+    }
+}
+
+impl ResponseHandler for StatusOnlyResponseHandler {
+    fn response(&mut self, response: Response<'_>) {
+        self.code = response.code;
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        if let Ok(None) = payload {
+            self.complete = true;
+        }
+    }
+}
+
+impl FinishResponse for StatusOnlyResponseHandler {
+    /// The status code.
+    type Output = u16;
+
+    fn finish(self) -> Result<u16, Error> {
+        match self.complete {
+            true => Ok(self.code),
+            false => Err(Error::IncompleteBody),
+        }
+    }
+}
+
+/// A response handler, that discards the payload, counting its bytes.
+///
+/// This is intended for requests where only the status code matters, like sending telemetry.
+/// The payload still gets read, so that the connection can be re-used. Response headers are
+/// only kept when selected using [`DrainResponseHandler::capture_headers`], they are stored in
+/// a [`HeaderMap`] of capacity `NH`.
+pub struct DrainResponseHandler<NH = consts::U0>
+where
+    NH: ArrayLength<u8>,
+{
+    code: u16,
+    capture: &'static [HeaderName<'static>],
+    headers: HeaderMap<NH>,
+    len: usize,
+    complete: bool,
+    error: Option<Error>,
+}
+
+impl<NH> Default for DrainResponseHandler<NH>
+where
+    NH: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<NH> DrainResponseHandler<NH>
+where
+    NH: ArrayLength<u8>,
+{
+    pub fn new() -> Self {
+        DrainResponseHandler {
+            code: 0u16,
+            capture: &[],
+            headers: HeaderMap::new(),
+            len: 0,
+            complete: false,
+            error: None,
+        }
+    }
+
+    /// Select the response headers to keep.
+    ///
+    /// Headers which don't fit into the storage are dropped, with a warning.
+    pub fn capture_headers(mut self, names: &'static [HeaderName<'static>]) -> Self {
+        self.capture = names;
+        self
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The error, in case processing the response failed.
+    pub fn error(&self) -> Option<Error> {
+        self.error
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// The captured response headers.
+    pub fn headers(&self) -> HeaderEntries<'_> {
+        self.headers.entries()
+    }
+
+    /// The number of payload bytes, which have been discarded.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the response had no payload, so far.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<NH> ResponseHandler for DrainResponseHandler<NH>
+where
+    NH: ArrayLength<u8>,
+{
+    fn response(&mut self, response: Response<'_>) {
+        self.code = response.code;
+        self.headers = capture_headers(self.capture, &response);
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        match payload {
+            Ok(Some(data)) => self.len += data.len(),
+            Ok(None) => self.complete = true,
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+impl<NH> FinishResponse for DrainResponseHandler<NH>
+where
+    NH: ArrayLength<u8>,
+{
+    /// The status code, and the number of discarded payload bytes.
+    type Output = (u16, usize);
+
+    fn finish(self) -> Result<Self::Output, Error> {
+        match self.error {
+            Some(err) => Err(err),
+            None if !self.complete => Err(Error::IncompleteBody),
+            None => Ok((self.code, self.len)),
+        }
+    }
+}
+
+/// A response handler, that captures the methods of the `Allow` header.
+///
+/// This is intended for `OPTIONS` requests, probing the capabilities of the server. The header
+/// value is stored in a string of capacity `N`, the payload is discarded.
+pub struct AllowResponseHandler<N = consts::U64>
+where
+    N: ArrayLength<u8>,
+{
+    code: u16,
+    allow: Option<String<N>>,
+    complete: bool,
+}
+
+impl<N> Default for AllowResponseHandler<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N> AllowResponseHandler<N>
+where
+    N: ArrayLength<u8>,
+{
+    pub fn new() -> Self {
+        AllowResponseHandler {
+            code: 0u16,
+            allow: None,
+            complete: false,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// Check if the response contained an `Allow` header, which fit into the buffer.
+    pub fn has_allow(&self) -> bool {
+        self.allow.is_some()
+    }
+
+    /// Iterate over the allowed methods.
+    pub fn methods(&self) -> impl Iterator<Item = &str> {
+        allowed_methods(self.allow.as_ref().map_or("", |s| s.as_str()))
+    }
+
+    /// Check if the method is allowed.
+    ///
+    /// Method names are case-sensitive.
+    pub fn allows(&self, method: &str) -> bool {
+        self.methods().any(|m| m == method)
+    }
+}
+
+impl<N> ResponseHandler for AllowResponseHandler<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn response(&mut self, response: Response<'_>) {
+        self.code = response.code;
+        self.allow = response.headers.get(ALLOW).and_then(|value| {
+            let mut s = String::new();
+            match s.push_str(value) {
+                Ok(_) => Some(s),
+                Err(_) => {
+                    log::warn!("Allow header exceeds buffer: {}", value);
+                    None
+                }
+            }
+        });
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        if let Ok(None) = payload {
+            self.complete = true;
+        }
+    }
+}
+
+/// Fine-grained events of processing a response.
+///
+/// This is an alternative to implementing [`ResponseHandler`], for consumers which stream the
+/// response, and want to react to the status and headers before the body arrives. Use
+/// [`EventResponseHandler`] to execute a request with it.
+///
+/// ~~~
+/// use drogue_http_client::*;
+/// use heapless::{consts::*, Vec};
+///
+/// #[derive(Default)]
+/// struct Events {
+///     code: u16,
+///     length: Option<usize>,
+///     received: usize,
+/// }
+///
+/// impl ResponseEvents for Events {
+///     fn on_status(&mut self, _version: u8, code: u16, _reason: &str) {
+///         self.code = code;
+///     }
+///
+///     fn on_header(&mut self, name: &str, value: &[u8]) {
+///         if headers::CONTENT_LENGTH.matches(name) {
+///             self.length = core::str::from_utf8(value).ok().and_then(|v| v.parse().ok());
+///         }
+///     }
+///
+///     fn on_body_chunk(&mut self, chunk: &[u8]) {
+///         self.received += chunk.len();
+///     }
+/// }
+///
+/// # let mut sink = Vec::<u8, U1024>::new();
+/// let mut req = HttpConnection::<U1024>::new()
+///     .begin("GET", "/")
+///     .handler(EventResponseHandler::new(Events::default()))
+///     .execute(&mut sink);
+/// req.push_data(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHello");
+///
+/// let events = req.try_complete().unwrap().1.into_inner();
+/// assert_eq!((events.code, events.length, events.received), (200, Some(5), 5));
+/// ~~~
+pub trait ResponseEvents {
+    /// The status line was received.
+    fn on_status(&mut self, _version: u8, _code: u16, _reason: &str) {}
+
+    /// A header of the response was received, following the status line.
+    ///
+    /// Values are passed on as received, they are not required to be valid UTF-8.
+    fn on_header(&mut self, _name: &str, _value: &[u8]) {}
+
+    /// All headers of the response were received.
+    fn on_headers_complete(&mut self) {}
+
+    /// Data of the body was received.
+    fn on_body_chunk(&mut self, _chunk: &[u8]) {}
+
+    /// The trailer of a body using the "chunked" transfer encoding was received.
+    fn on_trailers(&mut self, _trailers: ResponseHeaders) {}
+
+    /// Processing the response completed, or failed.
+    ///
+    /// No more events will follow.
+    fn on_complete(&mut self, _result: Result<(), Error>) {}
+}
+
+/// A response handler, passing the response on as [`ResponseEvents`].
+pub struct EventResponseHandler<E>
+where
+    E: ResponseEvents,
+{
+    events: E,
+}
+
+impl<E> EventResponseHandler<E>
+where
+    E: ResponseEvents,
+{
+    pub fn new(events: E) -> Self {
+        EventResponseHandler { events }
+    }
+
+    /// Get the wrapped events.
+    pub fn events(&self) -> &E {
+        &self.events
+    }
+
+    /// Get the wrapped events, mutably.
+    pub fn events_mut(&mut self) -> &mut E {
+        &mut self.events
+    }
+
+    /// Give back the wrapped events.
+    pub fn into_inner(self) -> E {
+        self.events
+    }
+}
+
+impl<E> ResponseHandler for EventResponseHandler<E>
+where
+    E: ResponseEvents,
+{
+    fn response(&mut self, response: Response<'_>) {
+        self.events
+            .on_status(response.version, response.code, response.reason);
+        for (name, value) in response.headers.iter() {
+            self.events.on_header(name, value);
+        }
+        self.events.on_headers_complete();
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        match payload {
+            Ok(Some(chunk)) => self.events.on_body_chunk(chunk),
+            Ok(None) => self.events.on_complete(Ok(())),
+            Err(err) => self.events.on_complete(Err(err)),
+        }
+    }
+
+    fn trailers(&mut self, trailers: ResponseHeaders) {
+        self.events.on_trailers(trailers);
+    }
+}
+
+/// The handler selected by a [`ContentTypeResponseHandler`], or its output.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Dispatched<A, B> {
+    /// The content type of the response matched.
+    Matched(A),
+    /// The content type of the response didn't match, or was missing.
+    Other(B),
+}
+
+/// A response handler, which routes the response to one of two handlers, depending on its
+/// `Content-Type`.
+///
+/// This is intended for endpoints which return different representations, e.g. a firmware
+/// image on success, but a JSON document describing an error. Only the selected handler gets
+/// the response and its payload. The content type is matched ignoring its parameters and the
+/// case, a pattern like `text/*` matches all subtypes.
+///
+/// To route to more than two handlers, use another instance as the `other` handler.
+///
+/// ~~~
+/// use heapless::{consts::*, Vec};
+/// use drogue_http_client::*;
+///
+/// # let mut sink = Vec::<u8, U1024>::new();
+/// let handler = ContentTypeResponseHandler::new(
+///     "application/json",
+///     BufferResponseHandler::<U128>::new(),
+///     DrainResponseHandler::<U0>::new(),
+/// );
+/// let mut req = HttpConnection::<U1024>::new()
+///     .begin("GET", "/status")
+///     .handler(handler)
+///     .execute(&mut sink);
+/// req.push_data(b"HTTP/1.1 200 OK\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: 2\r\n\r\n{}");
+///
+/// match req.finish().1 {
+///     Ok(Dispatched::Matched((_, payload))) => assert_eq!(payload, b"{}"),
+///     Ok(Dispatched::Other((code, _))) => panic!("Unexpected response: {}", code),
+///     Err(err) => panic!("Request failed: {}", err),
+/// }
+/// ~~~
+pub struct ContentTypeResponseHandler<'t, A, B>
+where
+    A: ResponseHandler,
+    B: ResponseHandler,
+{
+    content_type: &'t str,
+    matched: A,
+    other: B,
+    selected: Option<Dispatched<(), ()>>,
+    // an error reported before a handler was selected
+    error: Option<Error>,
+}
+
+impl<'t, A, B> ContentTypeResponseHandler<'t, A, B>
+where
+    A: ResponseHandler,
+    B: ResponseHandler,
+{
+    /// Create a new instance, routing responses of the content type to `matched`, and all
+    /// others to `other`.
+    pub fn new(content_type: &'t str, matched: A, other: B) -> Self {
+        ContentTypeResponseHandler {
+            content_type,
+            matched,
+            other,
+            selected: None,
+            error: None,
+        }
+    }
+
+    /// The handler which got the response, `None` if no response was received.
+    pub fn selected(&self) -> Option<Dispatched<&A, &B>> {
+        match self.selected? {
+            Dispatched::Matched(_) => Some(Dispatched::Matched(&self.matched)),
+            Dispatched::Other(_) => Some(Dispatched::Other(&self.other)),
+        }
+    }
+
+    /// Get the handler for matching responses.
+    pub fn matched(&self) -> &A {
+        &self.matched
+    }
+
+    /// Get the handler for all other responses.
+    pub fn other(&self) -> &B {
+        &self.other
+    }
+
+    /// Give back both handlers.
+    pub fn into_inner(self) -> (A, B) {
+        (self.matched, self.other)
+    }
+
+    fn matches(&self, content_type: &str) -> bool {
+        let content_type = media_type(content_type);
+        match self.content_type.strip_suffix("/*") {
+            Some(prefix) => matches!(
+                content_type.split('/').next(),
+                Some(t) if t.eq_ignore_ascii_case(prefix)
+            ),
+            None => content_type.eq_ignore_ascii_case(self.content_type),
+        }
+    }
+}
+
+impl<'t, A, B> ResponseHandler for ContentTypeResponseHandler<'t, A, B>
+where
+    A: ResponseHandler,
+    B: ResponseHandler,
+{
+    fn response(&mut self, response: Response<'_>) {
+        let matched = matches!(
+            response.headers.get(CONTENT_TYPE),
+            Some(content_type) if self.matches(content_type)
+        );
+        if matched {
+            self.selected = Some(Dispatched::Matched(()));
+            self.matched.response(response);
+        } else {
+            self.selected = Some(Dispatched::Other(()));
+            self.other.response(response);
+        }
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        match self.selected {
+            Some(Dispatched::Matched(_)) => self.matched.more_payload(payload),
+            Some(Dispatched::Other(_)) => self.other.more_payload(payload),
+            None => {
+                if let Err(err) = payload {
+                    self.error = Some(err);
+                }
+            }
+        }
+    }
+
+    fn consume_payload(&mut self, payload: &[u8]) -> usize {
+        match self.selected {
+            Some(Dispatched::Matched(_)) => self.matched.consume_payload(payload),
+            Some(Dispatched::Other(_)) => self.other.consume_payload(payload),
+            None => payload.len(),
+        }
+    }
+
+    fn trailers(&mut self, trailers: ResponseHeaders) {
+        match self.selected {
+            Some(Dispatched::Matched(_)) => self.matched.trailers(trailers),
+            Some(Dispatched::Other(_)) => self.other.trailers(trailers),
+            None => {}
+        }
+    }
+
+    fn failed(&self) -> Option<Error> {
+        match self.selected {
+            Some(Dispatched::Matched(_)) => self.matched.failed(),
+            Some(Dispatched::Other(_)) => self.other.failed(),
+            None => None,
+        }
+    }
+}
+
+impl<'t, A, B> FinishResponse for ContentTypeResponseHandler<'t, A, B>
+where
+    A: FinishResponse,
+    B: FinishResponse,
+{
+    /// The output of the selected handler.
+    type Output = Dispatched<A::Output, B::Output>;
+
+    fn finish(self) -> Result<Self::Output, Error> {
+        match self.selected {
+            Some(Dispatched::Matched(_)) => self.matched.finish().map(Dispatched::Matched),
+            Some(Dispatched::Other(_)) => self.other.finish().map(Dispatched::Other),
+            None => Err(self.error.unwrap_or(Error::IncompleteBody)),
+        }
+    }
+}
+
+/// Copy the selected headers of the response.
+fn capture_headers<N>(names: &[HeaderName<'_>], response: &Response<'_>) -> HeaderMap<N>
+where
+    N: ArrayLength<u8>,
+{
+    let mut headers = HeaderMap::new();
+    for (name, value) in response.headers.iter() {
+        if !names.iter().any(|n| n.matches(name)) {
+            continue;
+        }
+        let value = core::str::from_utf8(value).unwrap_or_default();
+        if headers.append(name, value).is_err() {
+            log::warn!("Dropping response header: {}", name);
         }
     }
+    headers
 }
@@ -0,0 +1,622 @@
+//! A client for the [Eclipse hawkBit](https://www.eclipse.org/hawkbit/) Direct Device
+//! Integration (DDI) API.
+//!
+//! The [`Ddi`] helper implements the flow of a device, on top of an [`HttpClient`]: polling the
+//! base resource of the controller, fetching a deployment when one is pending, downloading
+//! its artifacts, and reporting the outcome as feedback. The client must be created using
+//! [`HttpClient::from_url`], as the links returned by hawkBit are absolute URLs, which must
+//! point to the same server.
+//!
+//! Responses are buffered, with a capacity of `N`, and fields are looked up when accessed,
+//! without unescaping strings. Artifacts are downloaded using [`ota::Download`].
+//!
+//! This module requires the `hawkbit` feature.
+//!
+//! ~~~no_run
+//! # use embedded_storage::{ReadStorage, Storage};
+//! # struct Flash;
+//! # impl ReadStorage for Flash {
+//! #     type Error = ();
+//! #     fn read(&mut self, _: u32, _: &mut [u8]) -> Result<(), ()> { Ok(()) }
+//! #     fn capacity(&self) -> usize { 0 }
+//! # }
+//! # impl Storage for Flash {
+//! #     fn write(&mut self, _: u32, _: &[u8]) -> Result<(), ()> { Ok(()) }
+//! # }
+//! use heapless::consts::*;
+//!
+//! use drogue_http_client::client::HttpClient;
+//! use drogue_http_client::hawkbit::*;
+//! use drogue_http_client::*;
+//!
+//! # use drogue_http_client::mock;
+//! # let (network, _) = mock::mock_connection();
+//! let mut client = HttpClient::from_url(
+//!     &network,
+//!     "http://hawkbit.local:8080",
+//!     HttpConnection::<U1024>::new(),
+//! )
+//! .unwrap();
+//!
+//! let ddi = Ddi::<U2048>::new("DEFAULT", "device-1").target_token("secret");
+//!
+//! let poll = ddi.poll(&mut client).unwrap();
+//! if let Some(href) = poll.deployment_base() {
+//!     let deployment = ddi.deployment(&mut client, href).unwrap();
+//!     let id = deployment.id().unwrap();
+//!
+//!     let outcome = match deployment.artifacts().next() {
+//!         Some(artifact) => match artifact.download(&client, Flash, 0u32) {
+//!             Some(mut download) => download.run(&mut client).is_ok(),
+//!             None => false,
+//!         },
+//!         None => false,
+//!     };
+//!
+//!     let feedback = match outcome {
+//!         true => Feedback::new(Execution::Closed, Finished::Success),
+//!         false => Feedback::new(Execution::Closed, Finished::Failure).details("Download failed"),
+//!     };
+//!     ddi.feedback(&mut client, id, &feedback).unwrap();
+//! }
+//! ~~~
+
+use crate::client::HttpClient;
+use crate::headers::{ACCEPT, AUTHORIZATION};
+use crate::ota::{Download, OffsetStore};
+use crate::uri::Url;
+use crate::{BufferResponseHandler, Error, Interceptor, PipeError, StatusOnlyResponseHandler};
+use core::fmt::{self, Write};
+use core::marker::PhantomData;
+use drogue_network::tcp::TcpStack;
+use embedded_storage::Storage;
+use heapless::{consts, ArrayLength, String, Vec};
+
+const HAL_JSON: &str = "application/hal+json";
+
+/// The DDI API of a controller, which is the device, in a tenant.
+///
+/// Responses are buffered with a capacity of `N`.
+pub struct Ddi<'a, N = consts::U2048>
+where
+    N: ArrayLength<u8>,
+{
+    tenant: &'a str,
+    controller_id: &'a str,
+    // the value of the authorization header, scheme and token
+    token: Option<(&'static str, &'a str)>,
+    _marker: PhantomData<N>,
+}
+
+impl<'a, N> Ddi<'a, N>
+where
+    N: ArrayLength<u8>,
+{
+    /// Create a new instance, for the controller in the tenant.
+    pub fn new(tenant: &'a str, controller_id: &'a str) -> Self {
+        Ddi {
+            tenant,
+            controller_id,
+            token: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Authenticate using the security token of the target.
+    pub fn target_token(mut self, token: &'a str) -> Self {
+        self.token = Some(("TargetToken", token));
+        self
+    }
+
+    /// Authenticate using the security token of a gateway, shared by all targets of the tenant.
+    pub fn gateway_token(mut self, token: &'a str) -> Self {
+        self.token = Some(("GatewayToken", token));
+        self
+    }
+
+    /// Poll the base resource of the controller, which links to the pending actions.
+    pub fn poll<T, IN, I>(
+        &self,
+        client: &mut HttpClient<'_, T, IN, I>,
+    ) -> Result<Poll<N>, PipeError<T::Error>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+    {
+        let mut path = String::<consts::U256>::new();
+        self.write_base(&mut path).map_err(|_| Error::Url)?;
+
+        let authorization = self.authorization();
+        let handler = client.request("GET", &path, |req, sink| {
+            let mut req = req.header(ACCEPT, HAL_JSON);
+            if let Some(authorization) = &authorization {
+                req = req.header(AUTHORIZATION, authorization);
+            }
+            req.handler(BufferResponseHandler::<N>::new()).execute(sink)
+        })?;
+
+        Ok(Poll {
+            payload: into_payload(handler)?,
+        })
+    }
+
+    /// Fetch the deployment, using the link of the base resource.
+    pub fn deployment<T, IN, I>(
+        &self,
+        client: &mut HttpClient<'_, T, IN, I>,
+        href: &str,
+    ) -> Result<Deployment<N>, PipeError<T::Error>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+    {
+        let authorization = self.authorization();
+        let handler = client.request_url("GET", href, |req, sink| {
+            let mut req = req.header(ACCEPT, HAL_JSON);
+            if let Some(authorization) = &authorization {
+                req = req.header(AUTHORIZATION, authorization);
+            }
+            req.handler(BufferResponseHandler::<N>::new()).execute(sink)
+        })?;
+
+        Ok(Deployment {
+            payload: into_payload(handler)?,
+        })
+    }
+
+    /// Report the progress, or outcome, of the deployment action.
+    pub fn feedback<T, IN, I>(
+        &self,
+        client: &mut HttpClient<'_, T, IN, I>,
+        action_id: &str,
+        feedback: &Feedback,
+    ) -> Result<(), PipeError<T::Error>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+    {
+        let mut path = String::<consts::U256>::new();
+        self.write_base(&mut path)
+            .and_then(|_| write!(path, "/deploymentBase/{}/feedback", action_id))
+            .map_err(|_| Error::Url)?;
+
+        let authorization = self.authorization();
+        let handler = client.request("POST", &path, |req, sink| {
+            let mut req = req.json();
+            if let Some(authorization) = &authorization {
+                req = req.header(AUTHORIZATION, authorization);
+            }
+            req.handler(StatusOnlyResponseHandler::new())
+                .execute_with_fmt(sink, |w| feedback.write_json(w, action_id))
+        })?;
+
+        match handler.code() {
+            200..=299 => Ok(()),
+            code => Err(Error::Status(code).into()),
+        }
+    }
+
+    /// Write the path of the base resource.
+    fn write_base<W>(&self, w: &mut W) -> fmt::Result
+    where
+        W: Write,
+    {
+        write!(w, "/{}/controller/v1/{}", self.tenant, self.controller_id)
+    }
+
+    /// The value of the `Authorization` header.
+    fn authorization(&self) -> Option<String<consts::U128>> {
+        let (scheme, token) = self.token?;
+        let mut value = String::new();
+        match write!(value, "{} {}", scheme, token) {
+            Ok(_) => Some(value),
+            Err(_) => {
+                log::warn!("Token exceeds the buffer, sending requests without it");
+                None
+            }
+        }
+    }
+}
+
+/// Check the outcome of a request, and return its payload.
+fn into_payload<N, E>(handler: BufferResponseHandler<N>) -> Result<Vec<u8, N>, PipeError<E>>
+where
+    N: ArrayLength<u8>,
+{
+    if let Some(err) = handler.error() {
+        return Err(err.into());
+    }
+    if handler.is_truncated() {
+        return Err(Error::Overflow.into());
+    }
+    match handler.code() {
+        200 => Ok(handler.into_payload()),
+        code => Err(Error::Status(code).into()),
+    }
+}
+
+/// The base resource of a controller.
+pub struct Poll<N>
+where
+    N: ArrayLength<u8>,
+{
+    payload: Vec<u8, N>,
+}
+
+impl<N> Poll<N>
+where
+    N: ArrayLength<u8>,
+{
+    /// The time to wait until the next poll, in seconds.
+    pub fn sleep(&self) -> Option<u32> {
+        let sleep = json::path(json(&self.payload), &["config", "polling", "sleep"])?;
+        let mut seconds = 0u32;
+        for part in json::string(sleep)?.split(':') {
+            seconds = seconds.checked_mul(60)?.checked_add(part.parse().ok()?)?;
+        }
+        Some(seconds)
+    }
+
+    /// The link to the pending deployment, if there is one.
+    pub fn deployment_base(&self) -> Option<&str> {
+        self.link("deploymentBase")
+    }
+
+    /// The link to the pending cancellation of an action, if there is one.
+    pub fn cancel_action(&self) -> Option<&str> {
+        self.link("cancelAction")
+    }
+
+    /// The link for providing the attributes of the target, if requested by the server.
+    pub fn config_data(&self) -> Option<&str> {
+        self.link("configData")
+    }
+
+    fn link(&self, name: &str) -> Option<&str> {
+        json::string(json::path(json(&self.payload), &["_links", name, "href"])?)
+    }
+}
+
+/// A deployment, assigned to a controller.
+pub struct Deployment<N>
+where
+    N: ArrayLength<u8>,
+{
+    payload: Vec<u8, N>,
+}
+
+impl<N> Deployment<N>
+where
+    N: ArrayLength<u8>,
+{
+    /// The ID of the action, required for sending feedback.
+    pub fn id(&self) -> Option<&str> {
+        json::string(json::path(json(&self.payload), &["id"])?)
+    }
+
+    /// How the artifacts should be downloaded, `skip`, `attempt`, or `forced`.
+    pub fn download_type(&self) -> Option<&str> {
+        json::string(json::path(
+            json(&self.payload),
+            &["deployment", "download"],
+        )?)
+    }
+
+    /// How the update should be installed, `skip`, `attempt`, or `forced`.
+    pub fn update_type(&self) -> Option<&str> {
+        json::string(json::path(json(&self.payload), &["deployment", "update"])?)
+    }
+
+    /// Iterate over the artifacts, of all chunks of the deployment.
+    pub fn artifacts(&self) -> impl Iterator<Item = Artifact<'_>> + '_ {
+        json::path(json(&self.payload), &["deployment", "chunks"])
+            .into_iter()
+            .flat_map(json::Elements::new)
+            .flat_map(|chunk| {
+                let part = json::member(chunk, "part").and_then(json::string);
+                json::member(chunk, "artifacts")
+                    .into_iter()
+                    .flat_map(json::Elements::new)
+                    .map(move |artifact| Artifact::parse(part, artifact))
+            })
+    }
+}
+
+/// An artifact of a deployment.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Artifact<'a> {
+    /// The part of the software module, the artifact belongs to, like `os` or `bApp`.
+    pub part: Option<&'a str>,
+    /// The name of the file.
+    pub filename: Option<&'a str>,
+    /// The size, in bytes.
+    pub size: Option<u32>,
+    /// The SHA-256 digest.
+    pub sha256: Option<[u8; 32]>,
+    /// The link to download the artifact via HTTP.
+    pub href: Option<&'a str>,
+}
+
+impl<'a> Artifact<'a> {
+    fn parse(part: Option<&'a str>, artifact: &'a str) -> Self {
+        let field = |path: &[&str]| json::path(artifact, path);
+        Artifact {
+            part,
+            filename: field(&["filename"]).and_then(json::string),
+            size: field(&["size"]).and_then(|size| size.parse().ok()),
+            sha256: field(&["hashes", "sha256"])
+                .and_then(json::string)
+                .and_then(parse_sha256),
+            href: field(&["_links", "download-http", "href"]).and_then(json::string),
+        }
+    }
+
+    /// Prepare the download of the artifact, into the storage.
+    ///
+    /// Fails if the artifact has no HTTP link, or SHA-256 digest, or if it isn't served by the
+    /// server of the client.
+    pub fn download<T, IN, I, S, O>(
+        &self,
+        client: &HttpClient<'_, T, IN, I>,
+        storage: S,
+        offsets: O,
+    ) -> Option<Download<'a, S, O>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+        S: Storage,
+        O: OffsetStore,
+    {
+        let (href, sha256) = match (self.href, self.sha256) {
+            (Some(href), Some(sha256)) => (href, sha256),
+            _ => {
+                log::warn!("Artifact without link, or digest: {:?}", self.filename);
+                return None;
+            }
+        };
+        let url = Url::parse(href).ok()?;
+        if !client.is_same_origin(&url) {
+            log::warn!("Artifact is served by a different server: {}", url.host());
+            return None;
+        }
+        let path = match url.path().as_str() {
+            Some(path) => path,
+            None => {
+                log::warn!("Artifact link without a path: {}", href);
+                return None;
+            }
+        };
+        Some(Download::new(path, storage, offsets, sha256))
+    }
+}
+
+fn parse_sha256(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (i, b) in digest.iter_mut().enumerate() {
+        *b = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+/// The state of the execution of an action.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Execution {
+    Closed,
+    Proceeding,
+    Canceled,
+    Scheduled,
+    Rejected,
+    Resumed,
+    Downloaded,
+    Download,
+}
+
+impl Execution {
+    /// The value, as used in the feedback.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Execution::Closed => "closed",
+            Execution::Proceeding => "proceeding",
+            Execution::Canceled => "canceled",
+            Execution::Scheduled => "scheduled",
+            Execution::Rejected => "rejected",
+            Execution::Resumed => "resumed",
+            Execution::Downloaded => "downloaded",
+            Execution::Download => "download",
+        }
+    }
+}
+
+/// The result of an action.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Finished {
+    Success,
+    Failure,
+    /// The action isn't finished yet.
+    None,
+}
+
+impl Finished {
+    /// The value, as used in the feedback.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Finished::Success => "success",
+            Finished::Failure => "failure",
+            Finished::None => "none",
+        }
+    }
+}
+
+/// The feedback on an action.
+#[derive(Copy, Clone, Debug)]
+pub struct Feedback<'a> {
+    execution: Execution,
+    finished: Finished,
+    details: Option<&'a str>,
+}
+
+impl<'a> Feedback<'a> {
+    /// Create a new feedback, on the state of the execution, and its result.
+    pub fn new(execution: Execution, finished: Finished) -> Self {
+        Feedback {
+            execution,
+            finished,
+            details: None,
+        }
+    }
+
+    /// Add a message, which is shown in the action history.
+    pub fn details(mut self, details: &'a str) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    fn write_json(&self, w: &mut dyn Write, action_id: &str) -> fmt::Result {
+        w.write_str("{\"id\":")?;
+        json::write_string(w, action_id)?;
+        write!(
+            w,
+            ",\"status\":{{\"execution\":\"{}\",\"result\":{{\"finished\":\"{}\"}}",
+            self.execution.as_str(),
+            self.finished.as_str()
+        )?;
+        if let Some(details) = self.details {
+            w.write_str(",\"details\":[")?;
+            json::write_string(w, details)?;
+            w.write_char(']')?;
+        }
+        w.write_str("}}")
+    }
+}
+
+/// The payload as string, empty if it isn't valid UTF-8.
+fn json(payload: &[u8]) -> &str {
+    core::str::from_utf8(payload).unwrap_or_default()
+}
+
+/// Minimal access to JSON documents, by slicing out raw values.
+mod json {
+    use core::fmt::{self, Write};
+
+    /// Split the first value off, returning the value and the remainder.
+    fn split_value(s: &str) -> Option<(&str, &str)> {
+        let s = s.trim_start();
+        let b = s.as_bytes();
+        let end = match b.first()? {
+            b'"' => string_end(b)?,
+            b'{' | b'[' => {
+                let mut depth = 0usize;
+                let mut i = 0;
+                loop {
+                    match b.get(i)? {
+                        b'"' => {
+                            i += string_end(&b[i..])?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break i + 1;
+                            }
+                        }
+                        _ => {}
+                    }
+                    i += 1;
+                }
+            }
+            _ => b
+                .iter()
+                .position(|c| matches!(c, b',' | b'}' | b']') || c.is_ascii_whitespace())
+                .unwrap_or(b.len()),
+        };
+        Some(s.split_at(end))
+    }
+
+    /// The length of the string at the start, including the quotes.
+    fn string_end(b: &[u8]) -> Option<usize> {
+        let mut i = 1;
+        loop {
+            match b.get(i)? {
+                b'\\' => i += 2,
+                b'"' => return Some(i + 1),
+                _ => i += 1,
+            }
+        }
+    }
+
+    /// Skip the separator after a value, failing at the end of the object or array.
+    fn skip_separator(s: &str) -> Option<&str> {
+        s.trim_start().strip_prefix(',')
+    }
+
+    /// The raw value of the member of an object.
+    pub fn member<'j>(object: &'j str, name: &str) -> Option<&'j str> {
+        let mut rest = object.trim_start().strip_prefix('{')?;
+        loop {
+            let (key, r) = split_value(rest)?;
+            let r = r.trim_start().strip_prefix(':')?;
+            let (value, r) = split_value(r)?;
+            if string(key) == Some(name) {
+                return Some(value);
+            }
+            rest = skip_separator(r)?;
+        }
+    }
+
+    /// The raw value, found by following the names through nested objects.
+    pub fn path<'j>(json: &'j str, names: &[&str]) -> Option<&'j str> {
+        names
+            .iter()
+            .try_fold(json, |value, name| member(value, name))
+    }
+
+    /// The content of a string value, without unescaping it.
+    pub fn string(value: &str) -> Option<&str> {
+        value.strip_prefix('"')?.strip_suffix('"')
+    }
+
+    /// Iterates over the raw values of an array.
+    pub struct Elements<'j>(Option<&'j str>);
+
+    impl<'j> Elements<'j> {
+        pub fn new(array: &'j str) -> Self {
+            let rest = array
+                .trim_start()
+                .strip_prefix('[')
+                .filter(|rest| !rest.trim_start().starts_with(']'));
+            Elements(rest)
+        }
+    }
+
+    impl<'j> Iterator for Elements<'j> {
+        type Item = &'j str;
+
+        fn next(&mut self) -> Option<&'j str> {
+            let (value, rest) = split_value(self.0?)?;
+            self.0 = skip_separator(rest);
+            Some(value)
+        }
+    }
+
+    /// Write the value as string, escaping it.
+    pub fn write_string(w: &mut dyn Write, value: &str) -> fmt::Result {
+        w.write_char('"')?;
+        for c in value.chars() {
+            match c {
+                '"' => w.write_str("\\\"")?,
+                '\\' => w.write_str("\\\\")?,
+                c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+                c => w.write_char(c)?,
+            }
+        }
+        w.write_char('"')
+    }
+}
@@ -0,0 +1,340 @@
+//! HTTP header names.
+//!
+//! Header names are case-insensitive, [`HeaderName`] takes care of this when comparing names.
+//!
+//! ~~~
+//! use drogue_http_client::headers::*;
+//!
+//! assert!(CONTENT_TYPE.matches("content-type"));
+//! assert_eq!(HeaderName::new("X-Api-Key"), HeaderName::new("x-api-key"));
+//! ~~~
+
+use core::fmt;
+use core::fmt::Write;
+use heapless::{ArrayLength, String};
+
+/// The name of an HTTP header.
+///
+/// Comparing header names ignores the ASCII case.
+#[derive(Copy, Clone, Debug, Eq)]
+pub struct HeaderName<'a>(&'a str);
+
+impl<'a> HeaderName<'a> {
+    /// Create a new header name.
+    pub const fn new(name: &'a str) -> Self {
+        HeaderName(name)
+    }
+
+    /// The name, as provided when creating it.
+    pub const fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    /// Check if the provided name matches this one, ignoring the case.
+    pub fn matches(&self, name: &str) -> bool {
+        self.0.eq_ignore_ascii_case(name)
+    }
+}
+
+impl<'a> From<&'a str> for HeaderName<'a> {
+    fn from(name: &'a str) -> Self {
+        HeaderName(name)
+    }
+}
+
+impl<'a> AsRef<str> for HeaderName<'a> {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl<'a, 'b> PartialEq<HeaderName<'b>> for HeaderName<'a> {
+    fn eq(&self, other: &HeaderName<'b>) -> bool {
+        self.matches(other.0)
+    }
+}
+
+impl<'a> PartialEq<str> for HeaderName<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.matches(other)
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b str> for HeaderName<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.matches(other)
+    }
+}
+
+impl<'a> fmt::Display for HeaderName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+#[cfg(feature = "ufmt")]
+impl<'a> ufmt::uDisplay for HeaderName<'a> {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        f.write_str(self.0)
+    }
+}
+
+pub const ACCEPT: HeaderName<'static> = HeaderName::new("Accept");
+pub const ACCEPT_ENCODING: HeaderName<'static> = HeaderName::new("Accept-Encoding");
+pub const ALLOW: HeaderName<'static> = HeaderName::new("Allow");
+pub const AUTHORIZATION: HeaderName<'static> = HeaderName::new("Authorization");
+pub const CACHE_CONTROL: HeaderName<'static> = HeaderName::new("Cache-Control");
+pub const CONNECTION: HeaderName<'static> = HeaderName::new("Connection");
+pub const CONTENT_DIGEST: HeaderName<'static> = HeaderName::new("Content-Digest");
+pub const CONTENT_ENCODING: HeaderName<'static> = HeaderName::new("Content-Encoding");
+pub const CONTENT_LENGTH: HeaderName<'static> = HeaderName::new("Content-Length");
+pub const CONTENT_MD5: HeaderName<'static> = HeaderName::new("Content-MD5");
+pub const CONTENT_RANGE: HeaderName<'static> = HeaderName::new("Content-Range");
+pub const CONTENT_TYPE: HeaderName<'static> = HeaderName::new("Content-Type");
+pub const COOKIE: HeaderName<'static> = HeaderName::new("Cookie");
+pub const DATE: HeaderName<'static> = HeaderName::new("Date");
+pub const ETAG: HeaderName<'static> = HeaderName::new("ETag");
+pub const EXPECT: HeaderName<'static> = HeaderName::new("Expect");
+pub const HOST: HeaderName<'static> = HeaderName::new("Host");
+pub const IF_MATCH: HeaderName<'static> = HeaderName::new("If-Match");
+pub const IF_NONE_MATCH: HeaderName<'static> = HeaderName::new("If-None-Match");
+pub const IF_RANGE: HeaderName<'static> = HeaderName::new("If-Range");
+pub const LAST_MODIFIED: HeaderName<'static> = HeaderName::new("Last-Modified");
+pub const LOCATION: HeaderName<'static> = HeaderName::new("Location");
+pub const RANGE: HeaderName<'static> = HeaderName::new("Range");
+pub const RETRY_AFTER: HeaderName<'static> = HeaderName::new("Retry-After");
+pub const SET_COOKIE: HeaderName<'static> = HeaderName::new("Set-Cookie");
+pub const TRANSFER_ENCODING: HeaderName<'static> = HeaderName::new("Transfer-Encoding");
+pub const UPGRADE: HeaderName<'static> = HeaderName::new("Upgrade");
+pub const USER_AGENT: HeaderName<'static> = HeaderName::new("User-Agent");
+pub const WWW_AUTHENTICATE: HeaderName<'static> = HeaderName::new("WWW-Authenticate");
+pub const X_REQUEST_ID: HeaderName<'static> = HeaderName::new("X-Request-Id");
+
+/// A map of headers, owning names and values.
+///
+/// The entries are stored in their serialized form, in a buffer of capacity `N`. Multiple
+/// entries with the same name may be added using [`HeaderMap::append`].
+pub struct HeaderMap<N>
+where
+    N: ArrayLength<u8>,
+{
+    data: String<N>,
+}
+
+impl<N> Default for HeaderMap<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N> HeaderMap<N>
+where
+    N: ArrayLength<u8>,
+{
+    /// Create a new, empty map.
+    pub fn new() -> Self {
+        HeaderMap {
+            data: String::new(),
+        }
+    }
+
+    /// Add a header, keeping existing ones of the same name.
+    ///
+    /// Fails if the name or value are invalid, or the header doesn't fit into the map.
+    pub fn append<'n, H>(&mut self, name: H, value: &str) -> Result<(), ()>
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        if !is_valid_name(name.as_str()) || !is_valid_value(value) {
+            return Err(());
+        }
+
+        let len = name.as_str().len() + value.len() + 4;
+        if self.data.len() + len > self.data.capacity() {
+            return Err(());
+        }
+
+        write!(self.data, "{}: {}\r\n", name, value).map_err(|_| ())
+    }
+
+    /// Set a header, replacing existing ones of the same name.
+    ///
+    /// Fails if the name or value are invalid, or the header doesn't fit into the map. In this
+    /// case the existing headers are kept.
+    pub fn insert<'n, H>(&mut self, name: H, value: &str) -> Result<(), ()>
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        let mut data = String::<N>::new();
+        for (n, v) in self.entries().iter().filter(|(n, _)| !name.matches(n)) {
+            write!(data, "{}: {}\r\n", n, v).map_err(|_| ())?;
+        }
+
+        let previous = core::mem::replace(&mut self.data, data);
+        self.append(name, value).map_err(|_| {
+            self.data = previous;
+        })
+    }
+
+    /// Remove all headers with the name, returns `true` if any was removed.
+    pub fn remove<'n, H>(&mut self, name: H) -> bool
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        if !self.entries().contains(name) {
+            return false;
+        }
+
+        let mut data = String::<N>::new();
+        for (n, v) in self.entries().iter().filter(|(n, _)| !name.matches(n)) {
+            // can't fail, as it is less than before
+            write!(data, "{}: {}\r\n", n, v).ok();
+        }
+        self.data = data;
+
+        true
+    }
+
+    /// Get the value of the first header with the name.
+    pub fn get<'n, H>(&self, name: H) -> Option<&str>
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        self.entries().get(name)
+    }
+
+    /// Check if there is no header.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Remove all headers.
+    pub fn clear(&mut self) {
+        self.data = String::new();
+    }
+
+    /// Get a view of the entries.
+    pub fn entries(&self) -> HeaderEntries<'_> {
+        HeaderEntries(self.data.as_str())
+    }
+}
+
+/// A view on the entries of a [`HeaderMap`].
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HeaderEntries<'a>(&'a str);
+
+impl<'a> HeaderEntries<'a> {
+    /// Iterate over all name/value pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.0
+            .split_terminator("\r\n")
+            .filter_map(|line| line.find(": ").map(|i| (&line[..i], &line[i + 2..])))
+    }
+
+    /// Get the value of the first header with the name.
+    pub fn get<'n, H>(&self, name: H) -> Option<&'a str>
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        self.iter().find(|(n, _)| name.matches(n)).map(|(_, v)| v)
+    }
+
+    /// Check if there is a header with the name.
+    pub fn contains<'n, H>(&self, name: H) -> bool
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        self.get(name).is_some()
+    }
+}
+
+/// The headers of a response, or of a request received by the [`server`](crate::server).
+#[derive(Copy, Clone, Debug)]
+pub struct ResponseHeaders<'a>(&'a [httparse::Header<'a>]);
+
+impl<'a> ResponseHeaders<'a> {
+    pub(crate) fn new(headers: &'a [httparse::Header<'a>]) -> Self {
+        ResponseHeaders(headers)
+    }
+
+    /// Iterate over all name/value pairs.
+    ///
+    /// Values are passed on as received, they are not required to be valid UTF-8.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> {
+        self.0.iter().map(|h| (h.name, h.value))
+    }
+
+    /// Get the value of the first header with the name.
+    ///
+    /// Values which are not valid UTF-8 are skipped.
+    pub fn get<'n, H>(&self, name: H) -> Option<&'a str>
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        self.iter()
+            .filter(|(n, _)| name.matches(n))
+            .find_map(|(_, v)| core::str::from_utf8(v).ok())
+    }
+
+    /// Check if there is a header with the name.
+    pub fn contains<'n, H>(&self, name: H) -> bool
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        self.iter().any(|(n, _)| name.matches(n))
+    }
+}
+
+/// Split the value of an `Allow` header into its methods.
+///
+/// ~~~
+/// use drogue_http_client::headers::allowed_methods;
+///
+/// let mut methods = allowed_methods("GET, HEAD,PUT");
+/// assert_eq!(methods.next(), Some("GET"));
+/// assert_eq!(methods.next(), Some("HEAD"));
+/// assert_eq!(methods.next(), Some("PUT"));
+/// assert_eq!(methods.next(), None);
+/// ~~~
+pub fn allowed_methods(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(|method| method.trim_matches(|c| c == ' ' || c == '\t'))
+        .filter(|method| !method.is_empty())
+}
+
+/// Get the media type of a `Content-Type` header value, without its parameters.
+///
+/// ~~~
+/// use drogue_http_client::headers::media_type;
+///
+/// assert_eq!(media_type("text/plain; charset=utf-8"), "text/plain");
+/// assert_eq!(media_type(" application/json "), "application/json");
+/// ~~~
+pub fn media_type(value: &str) -> &str {
+    value
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim_matches(|c| c == ' ' || c == '\t')
+}
+
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic() && b != b':')
+}
+
+fn is_valid_value(value: &str) -> bool {
+    !value.bytes().any(|b| b == b'\r' || b == b'\n')
+}
@@ -0,0 +1,338 @@
+//! HPACK header compression, following [RFC 7541](https://tools.ietf.org/html/rfc7541), as far
+//! as required by the HTTP/2 client.
+//!
+//! The client announces a dynamic table size of zero. So the encoder only emits literals which
+//! are not indexed, and the decoder only needs the static table, and the Huffman code.
+
+use crate::Error;
+use core::str::from_utf8;
+use heapless::{ArrayLength, Vec};
+
+/// The static table, the index of an entry is its position plus one.
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// The number of Huffman codes per code length.
+const HUFFMAN_COUNTS: [u16; 31] = [
+    0, 0, 0, 0, 0, 10, 26, 32, 6, 0, 5, 3, 2, 6, 2, 3, //
+    0, 0, 0, 3, 8, 13, 26, 29, 12, 4, 15, 19, 29, 0, 4,
+];
+
+/// The symbols of the canonical Huffman code, ordered by code length and symbol.
+///
+/// Symbol 256 is the end-of-string marker.
+const HUFFMAN_SYMBOLS: [u16; 257] = [
+    48, 49, 50, 97, 99, 101, 105, 111, 115, 116, 32, 37, 45, 46, 47, 51, //
+    52, 53, 54, 55, 56, 57, 61, 65, 95, 98, 100, 102, 103, 104, 108, 109, //
+    110, 112, 114, 117, 58, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, //
+    77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 89, 106, 107, 113, 118, //
+    119, 120, 121, 122, 38, 42, 44, 59, 88, 90, 33, 34, 40, 41, 63, 39, //
+    43, 124, 35, 62, 0, 36, 64, 91, 93, 126, 94, 125, 60, 96, 123, 92, //
+    195, 208, 128, 130, 131, 162, 184, 194, 224, 226, 153, 161, 167, 172, 176, 177, //
+    179, 209, 216, 217, 227, 229, 230, 129, 132, 133, 134, 136, 146, 154, 156, 160, //
+    163, 164, 169, 170, 173, 178, 181, 185, 186, 187, 189, 190, 196, 198, 228, 232, //
+    233, 1, 135, 137, 138, 139, 140, 141, 143, 147, 149, 150, 151, 152, 155, 157, //
+    158, 165, 166, 168, 174, 175, 180, 182, 183, 188, 191, 197, 231, 239, 9, 142, //
+    144, 145, 148, 159, 171, 206, 215, 225, 236, 237, 199, 207, 234, 235, 192, 193, //
+    200, 201, 202, 205, 210, 213, 218, 219, 238, 240, 242, 243, 255, 203, 204, 211, //
+    212, 214, 221, 222, 223, 241, 244, 245, 246, 247, 248, 250, 251, 252, 253, 254, //
+    2, 3, 4, 5, 6, 7, 8, 11, 12, 14, 15, 16, 17, 18, 19, 20, //
+    21, 23, 24, 25, 26, 27, 28, 29, 30, 31, 127, 220, 249, 10, 13, 22, //
+    256,
+];
+
+/// Encode a header field, using the static table where possible.
+///
+/// Fields are never added to the dynamic table. Names are converted to lower case, as required
+/// by HTTP/2.
+pub(crate) fn encode_field<N>(out: &mut Vec<u8, N>, name: &str, value: &str) -> Result<(), ()>
+where
+    N: ArrayLength<u8>,
+{
+    let mut name_index = None;
+    for (i, (n, v)) in STATIC_TABLE.iter().enumerate() {
+        if n.eq_ignore_ascii_case(name) {
+            if *v == value {
+                // indexed header field
+                return encode_int(out, 0x80, 7, i + 1);
+            }
+            name_index.get_or_insert(i + 1);
+        }
+    }
+
+    // literal header field without indexing
+    match name_index {
+        Some(index) => encode_int(out, 0x00, 4, index)?,
+        None => {
+            out.push(0x00).map_err(|_| ())?;
+            encode_int(out, 0x00, 7, name.len())?;
+            for b in name.bytes() {
+                out.push(b.to_ascii_lowercase()).map_err(|_| ())?;
+            }
+        }
+    }
+    encode_int(out, 0x00, 7, value.len())?;
+    out.extend_from_slice(value.as_bytes())
+}
+
+/// Encode an integer with a prefix of `prefix` bits, `flags` are the remaining upper bits of
+/// the first byte.
+fn encode_int<N>(out: &mut Vec<u8, N>, flags: u8, prefix: u8, mut value: usize) -> Result<(), ()>
+where
+    N: ArrayLength<u8>,
+{
+    let max = (1usize << prefix) - 1;
+    if value < max {
+        return out.push(flags | value as u8).map_err(|_| ());
+    }
+
+    out.push(flags | max as u8).map_err(|_| ())?;
+    value -= max;
+    while value >= 0x80 {
+        out.push((value & 0x7f) as u8 | 0x80).map_err(|_| ())?;
+        value >>= 7;
+    }
+    out.push(value as u8).map_err(|_| ())
+}
+
+/// A decoded string, either from the static table, or a range of the decode buffer.
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Str {
+    Static(&'static str),
+    Buffer(usize, usize),
+}
+
+impl Str {
+    pub(crate) fn as_bytes<'a>(&self, buffer: &'a [u8]) -> &'a [u8] {
+        match *self {
+            Str::Static(s) => s.as_bytes(),
+            Str::Buffer(start, end) => &buffer[start..end],
+        }
+    }
+}
+
+/// A decoded header field.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Field {
+    pub(crate) name: Str,
+    pub(crate) value: Str,
+}
+
+pub(crate) const EMPTY_FIELD: Field = Field {
+    name: Str::Static(""),
+    value: Str::Static(""),
+};
+
+/// Decode a header block, returning the number of fields.
+///
+/// Strings of the header block are copied to the buffer, the fields refer to them.
+pub(crate) fn decode<N>(
+    block: &[u8],
+    buffer: &mut Vec<u8, N>,
+    fields: &mut [Field],
+) -> Result<usize, Error>
+where
+    N: ArrayLength<u8>,
+{
+    let mut pos = 0;
+    let mut count = 0;
+
+    while pos < block.len() {
+        let b = block[pos];
+        let field = if b & 0x80 != 0 {
+            // indexed header field
+            let (name, value) = static_entry(decode_int(block, &mut pos, 7)?)?;
+            Field {
+                name: Str::Static(name),
+                value: Str::Static(value),
+            }
+        } else if b & 0xe0 == 0x20 {
+            // dynamic table size update, there is no dynamic table to resize
+            decode_int(block, &mut pos, 5)?;
+            continue;
+        } else {
+            // literal header field, with (0x40), without (0x00), or never indexed (0x10), all
+            // the same without a dynamic table
+            let prefix = if b & 0x40 != 0 { 6 } else { 4 };
+            let name = match decode_int(block, &mut pos, prefix)? {
+                0 => {
+                    let name = decode_str(block, &mut pos, buffer)?;
+                    from_utf8(name.as_bytes(buffer)).map_err(|_| Error::Frame)?;
+                    name
+                }
+                index => Str::Static(static_entry(index)?.0),
+            };
+            let value = decode_str(block, &mut pos, buffer)?;
+            Field { name, value }
+        };
+
+        *fields
+            .get_mut(count)
+            .ok_or(Error::Parse(httparse::Error::TooManyHeaders))? = field;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+fn static_entry(index: usize) -> Result<(&'static str, &'static str), Error> {
+    match index {
+        1..=61 => Ok(STATIC_TABLE[index - 1]),
+        _ => {
+            log::info!("Invalid header table index: {}", index);
+            Err(Error::Frame)
+        }
+    }
+}
+
+fn decode_int(data: &[u8], pos: &mut usize, prefix: u8) -> Result<usize, Error> {
+    let max = (1usize << prefix) - 1;
+    let mut value = (*data.get(*pos).ok_or(Error::Frame)? as usize) & max;
+    *pos += 1;
+    if value < max {
+        return Ok(value);
+    }
+
+    let mut shift = 0;
+    loop {
+        let b = *data.get(*pos).ok_or(Error::Frame)?;
+        *pos += 1;
+        value += ((b & 0x7f) as usize) << shift;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+        if shift > 21 {
+            // larger than any frame we could receive
+            return Err(Error::Frame);
+        }
+    }
+}
+
+fn decode_str<N>(data: &[u8], pos: &mut usize, buffer: &mut Vec<u8, N>) -> Result<Str, Error>
+where
+    N: ArrayLength<u8>,
+{
+    let huffman = *data.get(*pos).ok_or(Error::Frame)? & 0x80 != 0;
+    let len = decode_int(data, pos, 7)?;
+    let s = data.get(*pos..*pos + len).ok_or(Error::Frame)?;
+    *pos += len;
+
+    let start = buffer.len();
+    if huffman {
+        huffman_decode(s, buffer)?;
+    } else {
+        buffer.extend_from_slice(s).map_err(|_| overflow())?;
+    }
+
+    Ok(Str::Buffer(start, buffer.len()))
+}
+
+fn overflow() -> Error {
+    log::info!("Header block doesn't fit into the buffer");
+    Error::Parse(httparse::Error::TooManyHeaders)
+}
+
+/// Decode a Huffman coded string, appending it to the buffer.
+fn huffman_decode<N>(data: &[u8], out: &mut Vec<u8, N>) -> Result<(), Error>
+where
+    N: ArrayLength<u8>,
+{
+    // decoding of the canonical code: the codes of one length are consecutive, starting at
+    // `first`, with their symbols starting at `index`
+    let mut code = 0u32;
+    let mut first = 0u32;
+    let mut index = 0usize;
+    let mut len = 0usize;
+
+    for b in data {
+        for shift in (0..8).rev() {
+            code |= ((b >> shift) & 1) as u32;
+            len += 1;
+            let count = *HUFFMAN_COUNTS.get(len).ok_or(Error::Frame)? as u32;
+            if code < first + count {
+                match HUFFMAN_SYMBOLS[index + (code - first) as usize] {
+                    // the end-of-string marker must not be encoded
+                    256 => return Err(Error::Frame),
+                    symbol => out.push(symbol as u8).map_err(|_| overflow())?,
+                }
+                code = 0;
+                first = 0;
+                index = 0;
+                len = 0;
+            } else {
+                index += count as usize;
+                first = (first + count) << 1;
+                code <<= 1;
+            }
+        }
+    }
+
+    // the padding must be a prefix of the end-of-string marker, shorter than a byte
+    if len > 7 || code >> 1 != (1 << len) - 1 {
+        return Err(Error::Frame);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,564 @@
+use crate::digest::{write_hex, BodyDigest, Hmac};
+use crate::headers::{HeaderEntries, HeaderMap, HeaderName, X_REQUEST_ID};
+use crate::metrics::increment;
+use crate::{Error, Response, Sink, Timings, UnixClock};
+use core::fmt::Write;
+use core::marker::PhantomData;
+use heapless::{consts, ArrayLength, String};
+
+/// Intercepts the requests and responses of a connection.
+///
+/// Interceptors are registered on an [`HttpConnection`](crate::HttpConnection), and apply to all
+/// requests executed on it. This allows to compose functionality like authentication,
+/// correlation IDs, or metrics, without adding it to each request.
+///
+/// Multiple interceptors form a chain, represented by a tuple. `()` is the empty chain.
+///
+/// The `observe_*` functions allow tracing the exchange on the wire, e.g. mirroring it to a
+/// debug output, without modifying the transport.
+///
+/// ~~~
+/// use drogue_http_client::*;
+/// use heapless::{consts::*, Vec};
+///
+/// struct Auth(&'static str);
+///
+/// impl Interceptor for Auth {
+///     fn before_send(&mut self, request: &mut RequestParts) {
+///         request.header("Authorization", self.0).ok();
+///     }
+/// }
+///
+/// let mut sink = Vec::<u8, U1024>::new();
+/// let req = HttpConnection::<U1024>::new()
+///     .interceptor(Auth("Bearer 1234"))
+///     .begin("GET", "/")
+///     .execute(&mut sink);
+///
+/// assert_eq!(sink, b"GET / HTTP/1.1\r\nAuthorization: Bearer 1234\r\n\r\n".as_ref());
+/// ~~~
+pub trait Interceptor {
+    /// Called before a request is sent.
+    fn before_send(&mut self, _request: &mut RequestParts) {}
+
+    /// Called when the response header was received, before it is passed on to the handler.
+    fn on_response(&mut self, _response: &Response) {}
+
+    /// Observe outbound data, as it was accepted by the sink.
+    fn observe_outbound(&mut self, _data: &[u8]) {}
+
+    /// Observe inbound data, as it was pushed to the request.
+    fn observe_inbound(&mut self, _data: &[u8]) {}
+
+    /// Observe an event of the connection.
+    fn observe_event(&mut self, _event: ConnectionEvent) {}
+
+    /// The timings of the current request, if recorded, like by [`Timing`](crate::Timing).
+    fn request_timings(&self) -> Option<Timings> {
+        None
+    }
+}
+
+/// An event of a connection.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnectionEvent {
+    /// Sending a request started.
+    SendStarted,
+    /// The request was sent completely.
+    RequestSent,
+    /// The response was received completely.
+    ResponseComplete,
+    /// The source reported that the connection was closed.
+    Closed,
+    /// Processing the response failed, the connection is poisoned.
+    Failed(Error),
+    /// The connection was reset.
+    Reset,
+}
+
+impl Interceptor for () {}
+
+impl<A, B> Interceptor for (A, B)
+where
+    A: Interceptor,
+    B: Interceptor,
+{
+    fn before_send(&mut self, request: &mut RequestParts) {
+        self.0.before_send(request);
+        self.1.before_send(request);
+    }
+
+    fn on_response(&mut self, response: &Response) {
+        self.0.on_response(response);
+        self.1.on_response(response);
+    }
+
+    fn observe_outbound(&mut self, data: &[u8]) {
+        self.0.observe_outbound(data);
+        self.1.observe_outbound(data);
+    }
+
+    fn observe_inbound(&mut self, data: &[u8]) {
+        self.0.observe_inbound(data);
+        self.1.observe_inbound(data);
+    }
+
+    fn observe_event(&mut self, event: ConnectionEvent) {
+        self.0.observe_event(event);
+        self.1.observe_event(event);
+    }
+
+    fn request_timings(&self) -> Option<Timings> {
+        self.0
+            .request_timings()
+            .or_else(|| self.1.request_timings())
+    }
+}
+
+/// A sink, passing on all data sent to it to the interceptors.
+pub(crate) struct ObservedSink<'a, S, I>
+where
+    S: Sink,
+    I: Interceptor,
+{
+    pub(crate) sink: &'a mut S,
+    pub(crate) interceptors: &'a mut I,
+    pub(crate) sent: &'a mut usize,
+}
+
+impl<'a, S, I> Sink for ObservedSink<'a, S, I>
+where
+    S: Sink,
+    I: Interceptor,
+{
+    fn send(&mut self, data: &[u8]) -> Result<usize, ()> {
+        let len = self.sink.send(data)?;
+        increment(self.sent, len);
+        self.interceptors.observe_outbound(&data[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        self.sink.flush()
+    }
+}
+
+/// Access to a storage of owned headers, independent of its capacity.
+pub(crate) trait HeaderStore {
+    fn insert(&mut self, name: HeaderName, value: &str) -> Result<(), ()>;
+    fn append(&mut self, name: HeaderName, value: &str) -> Result<(), ()>;
+    fn entries(&self) -> HeaderEntries<'_>;
+}
+
+impl<N> HeaderStore for HeaderMap<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn insert(&mut self, name: HeaderName, value: &str) -> Result<(), ()> {
+        HeaderMap::insert(self, name, value)
+    }
+
+    fn append(&mut self, name: HeaderName, value: &str) -> Result<(), ()> {
+        HeaderMap::append(self, name, value)
+    }
+
+    fn entries(&self) -> HeaderEntries<'_> {
+        HeaderMap::entries(self)
+    }
+}
+
+/// The parts of a request, which is about to be sent.
+pub struct RequestParts<'a> {
+    pub(crate) method: &'a str,
+    pub(crate) path: &'a str,
+    pub(crate) headers: Option<&'a [(&'a str, &'a str)]>,
+    pub(crate) owned_headers: &'a mut dyn HeaderStore,
+    pub(crate) payload: Option<&'a [&'a [u8]]>,
+}
+
+impl<'a> RequestParts<'a> {
+    /// The request method.
+    pub fn method(&self) -> &str {
+        self.method
+    }
+
+    /// The request target.
+    pub fn path(&self) -> &str {
+        self.path
+    }
+
+    /// Get the value of a header, preferring headers set using
+    /// [`RequestBuilder::header`](crate::RequestBuilder::header).
+    pub fn get_header<'n, H>(&self, name: H) -> Option<&str>
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        self.owned_headers.entries().get(name).or_else(|| {
+            self.headers
+                .unwrap_or_default()
+                .iter()
+                .find(|(n, _)| name.matches(n))
+                .map(|(_, v)| *v)
+        })
+    }
+
+    /// The parts of the payload, if it is known before sending the request.
+    ///
+    /// This is `None` for formatted and streamed payloads, which are only produced while
+    /// sending the request. A request without a payload has no parts.
+    pub fn payload(&self) -> Option<&[&[u8]]> {
+        self.payload
+    }
+
+    /// Check if the request has a header with the name.
+    pub fn contains_header<'n, H>(&self, name: H) -> bool
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        self.owned_headers.entries().contains(name)
+            || self
+                .headers
+                .unwrap_or_default()
+                .iter()
+                .any(|(n, _)| name.matches(n))
+    }
+
+    /// Set a header, replacing existing ones of the same name.
+    ///
+    /// Fails if the header is invalid or doesn't fit into the buffer of the request.
+    pub fn header<'n, H>(&mut self, name: H, value: &str) -> Result<(), ()>
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        self.owned_headers.insert(name.into(), value)
+    }
+
+    /// Add a header, keeping existing ones of the same name.
+    ///
+    /// Fails if the header is invalid or doesn't fit into the buffer of the request.
+    pub fn append_header<'n, H>(&mut self, name: H, value: &str) -> Result<(), ()>
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        self.owned_headers.append(name.into(), value)
+    }
+}
+
+/// An interceptor, adding headers to all requests.
+///
+/// Headers are copied into a map of capacity `N`. They are only added to requests, which don't
+/// already set a header of the same name, so that headers of a request take precedence.
+///
+/// ~~~
+/// use drogue_http_client::*;
+/// use heapless::{consts::*, Vec};
+///
+/// let mut sink = Vec::<u8, U1024>::new();
+/// let con = HttpConnection::<U1024>::new().interceptor(
+///     DefaultHeaders::<U128>::new()
+///         .header("Host", "my-server")
+///         .header("User-Agent", "my-device/1.0"),
+/// );
+///
+/// let req = con
+///     .begin("GET", "/")
+///     .header("User-Agent", "my-device/2.0")
+///     .execute(&mut sink);
+///
+/// assert_eq!(
+///     sink,
+///     b"GET / HTTP/1.1\r\nUser-Agent: my-device/2.0\r\nHost: my-server\r\n\r\n".as_ref()
+/// );
+/// ~~~
+pub struct DefaultHeaders<N>
+where
+    N: ArrayLength<u8>,
+{
+    headers: HeaderMap<N>,
+}
+
+impl<N> Default for DefaultHeaders<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N> DefaultHeaders<N>
+where
+    N: ArrayLength<u8>,
+{
+    /// Create a new instance, without any headers.
+    pub fn new() -> Self {
+        DefaultHeaders {
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Set a header, replacing a previously set header with the same name.
+    ///
+    /// If the header is invalid or doesn't fit into the buffer, it is dropped and a warning
+    /// gets logged.
+    pub fn header<'n, H>(mut self, name: H, value: &str) -> Self
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        if self.headers.insert(name, value).is_err() {
+            log::warn!("Unable to add default header: {}", name);
+        }
+        self
+    }
+
+    /// Access the headers.
+    pub fn headers(&self) -> &HeaderMap<N> {
+        &self.headers
+    }
+
+    /// Mutably access the headers, e.g. to update a token.
+    pub fn headers_mut(&mut self) -> &mut HeaderMap<N> {
+        &mut self.headers
+    }
+}
+
+impl<N> Interceptor for DefaultHeaders<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn before_send(&mut self, request: &mut RequestParts) {
+        let entries = self.headers.entries();
+        for (i, (name, _)) in entries.iter().enumerate() {
+            let seen = entries
+                .iter()
+                .take(i)
+                .any(|(n, _)| name.eq_ignore_ascii_case(n));
+            if seen || request.contains_header(name) {
+                continue;
+            }
+            // add all values of the name at once, before the request contains it
+            for (_, value) in entries.iter().filter(|(n, _)| name.eq_ignore_ascii_case(n)) {
+                if request.append_header(name, value).is_err() {
+                    log::warn!("Unable to add default header: {}", name);
+                }
+            }
+        }
+    }
+}
+
+/// An interceptor, adding a correlation ID to each request.
+///
+/// The ID is created by the generator `G`, e.g. a counter or a random number generator, and
+/// sent as hex string in the `X-Request-Id` header, unless the request already has one. The ID
+/// of the last request can be retrieved from the connection, to correlate it with the logs of
+/// the backend. It is logged as well, when processing the response fails.
+///
+/// ~~~
+/// use drogue_http_client::*;
+/// use heapless::{consts::*, Vec};
+///
+/// let mut counter = 0u32;
+/// let mut sink = Vec::<u8, U1024>::new();
+/// let req = HttpConnection::<U1024>::new()
+///     .interceptor(RequestId::new(move || {
+///         counter += 1;
+///         counter
+///     }))
+///     .begin("GET", "/")
+///     .execute(&mut sink);
+///
+/// let (con, _) = req.into_parts();
+/// assert_eq!(con.interceptors().1.last_id(), Some(1));
+/// assert_eq!(sink, b"GET / HTTP/1.1\r\nX-Request-Id: 00000001\r\n\r\n".as_ref());
+/// ~~~
+pub struct RequestId<G>
+where
+    G: FnMut() -> u32,
+{
+    generator: G,
+    name: HeaderName<'static>,
+    last_id: Option<u32>,
+}
+
+impl<G> RequestId<G>
+where
+    G: FnMut() -> u32,
+{
+    /// Create a new instance, using the generator for creating IDs.
+    pub fn new(generator: G) -> Self {
+        RequestId {
+            generator,
+            name: X_REQUEST_ID,
+            last_id: None,
+        }
+    }
+
+    /// Use a different header, like `X-Correlation-Id`.
+    pub fn header(mut self, name: HeaderName<'static>) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// The ID of the last request, `None` if no ID was sent yet.
+    pub fn last_id(&self) -> Option<u32> {
+        self.last_id
+    }
+}
+
+impl<G> Interceptor for RequestId<G>
+where
+    G: FnMut() -> u32,
+{
+    fn before_send(&mut self, request: &mut RequestParts) {
+        if request.contains_header(self.name) {
+            self.last_id = None;
+            return;
+        }
+
+        let id = (self.generator)();
+        let mut value = String::<consts::U8>::new();
+        // eight hex digits always fit
+        write!(value, "{:08x}", id).ok();
+        if request.header(self.name, &value).is_ok() {
+            self.last_id = Some(id);
+        } else {
+            log::warn!("Unable to add request ID: {}", value);
+            self.last_id = None;
+        }
+    }
+
+    fn observe_event(&mut self, event: ConnectionEvent) {
+        if let (ConnectionEvent::Failed(err), Some(id)) = (event, self.last_id) {
+            log::warn!("Request {:08x} failed: {:?}", id, err);
+        }
+    }
+}
+
+/// An interceptor, signing requests using an HMAC with a shared key.
+///
+/// The HMAC is computed using the hash function `D`, over the method, the path, the current
+/// time in seconds since the Unix epoch, and the payload, as:
+///
+/// ~~~text
+/// <method>\n<path>\n<timestamp>\n<payload>
+/// ~~~
+///
+/// The time is sent in the `X-Timestamp` header, and the hex encoded HMAC in the
+/// `X-Signature` header, both names can be changed. Requests with a payload which is not known
+/// before sending, like a formatted or streamed payload, are not signed.
+///
+/// ~~~
+/// # #[cfg(feature = "sha2")] {
+/// use drogue_http_client::*;
+/// use heapless::{consts::*, Vec};
+///
+/// struct Rtc;
+///
+/// impl UnixClock for Rtc {
+///     fn unix_time(&self) -> u64 {
+///         1_700_000_000
+///     }
+/// }
+///
+/// let mut sink = Vec::<u8, U1024>::new();
+/// let req = HttpConnection::<U1024>::new()
+///     .interceptor(HmacSigner::<sha2::Sha256, _>::new(b"device-secret", Rtc))
+///     .post("/telemetry")
+///     .execute_with(&mut sink, Some(b"{}"));
+///
+/// let request = core::str::from_utf8(&sink).unwrap();
+/// assert!(request.contains("X-Timestamp: 1700000000\r\n"));
+/// assert!(request.contains("X-Signature: "));
+/// # }
+/// ~~~
+pub struct HmacSigner<'k, D, C>
+where
+    D: BodyDigest + Default,
+    C: UnixClock,
+{
+    key: &'k [u8],
+    clock: C,
+    signature_header: HeaderName<'static>,
+    timestamp_header: HeaderName<'static>,
+    _marker: PhantomData<D>,
+}
+
+impl<'k, D, C> HmacSigner<'k, D, C>
+where
+    D: BodyDigest + Default,
+    C: UnixClock,
+{
+    /// Create a new instance, signing with the key.
+    pub fn new(key: &'k [u8], clock: C) -> Self {
+        HmacSigner {
+            key,
+            clock,
+            signature_header: HeaderName::new("X-Signature"),
+            timestamp_header: HeaderName::new("X-Timestamp"),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Set the header carrying the signature.
+    pub fn signature_header(mut self, name: HeaderName<'static>) -> Self {
+        self.signature_header = name;
+        self
+    }
+
+    /// Set the header carrying the timestamp.
+    pub fn timestamp_header(mut self, name: HeaderName<'static>) -> Self {
+        self.timestamp_header = name;
+        self
+    }
+
+    /// Access the clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+}
+
+impl<'k, D, C> Interceptor for HmacSigner<'k, D, C>
+where
+    D: BodyDigest + Default,
+    C: UnixClock,
+{
+    fn before_send(&mut self, request: &mut RequestParts) {
+        let payload = match request.payload() {
+            Some(payload) => payload,
+            None => {
+                log::warn!("Payload is not known before sending, not signing the request");
+                return;
+            }
+        };
+
+        let mut timestamp = String::<consts::U20>::new();
+        // a u64 always fits
+        write!(timestamp, "{}", self.clock.unix_time()).ok();
+
+        let mut hmac = Hmac::<D>::new(self.key);
+        for part in &[request.method(), request.path(), &timestamp] {
+            hmac.update(part.as_bytes());
+            hmac.update(b"\n");
+        }
+        for part in payload {
+            hmac.update(part);
+        }
+
+        let mut signature = String::<consts::U128>::new();
+        if write_hex(&mut signature, hmac.finish().as_ref()).is_err() {
+            log::error!("Signature exceeds buffer capacity, not signing the request");
+            return;
+        }
+
+        let result = request
+            .header(self.timestamp_header, &timestamp)
+            .and_then(|_| request.header(self.signature_header, &signature));
+        if result.is_err() {
+            log::warn!("Unable to add the signature, exceeds the buffer of the request");
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! Extraction of fields from JSON payloads, while they are received.
+//!
+//! Documents like update manifests can be a lot larger than the RAM of a device, while only a
+//! few of their fields are of interest. A [`JsonFieldsResponseHandler`] scans the payload as it
+//! streams in, and keeps only the values of the selected members of the top-level object.
+//!
+//! ~~~
+//! use heapless::{consts::*, Vec};
+//!
+//! use drogue_http_client::json::JsonFieldsResponseHandler;
+//! use drogue_http_client::*;
+//!
+//! # let mut sink = Vec::<u8, U1024>::new();
+//! let mut req = HttpConnection::<U1024>::new()
+//!     .begin("GET", "/manifest.json")
+//!     .handler(JsonFieldsResponseHandler::<U64>::new(&["version", "url"]))
+//!     .execute(&mut sink);
+//! req.push_data(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n");
+//! req.push_data(b"1c\r\n{\"url\": \"/fw/1.2.0\", \"files\"\r\n");
+//! req.push_data(b"1f\r\n: [1, 2, 3], \"version\": \"1.2.0\"\r\n");
+//! req.push_data(b"1\r\n}\r\n0\r\n\r\n");
+//!
+//! let (_, handler) = req.try_complete().unwrap();
+//! assert!(handler.is_complete());
+//! assert_eq!(handler.get("version"), Some("1.2.0"));
+//! assert_eq!(handler.get("url"), Some("/fw/1.2.0"));
+//! ~~~
+
+use crate::{Error, Response, ResponseHandler};
+use core::str::from_utf8;
+use heapless::{consts, ArrayLength, Vec};
+
+/// The position within the top-level object.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    // before the object
+    Start,
+    // before the name of a member, or the end of the object
+    Key,
+    // within the name of a member, the flag tells if the last byte was a backslash
+    KeyString(bool),
+    // between the name and the value of a member
+    Colon,
+    // before the value of a member
+    Value,
+    // within a string value
+    StringValue(Escape),
+    // within any other value, like a number, an object, or an array
+    RawValue(Raw),
+    // after the value of a member
+    Comma,
+    // after the object
+    Done,
+    // the payload isn't a JSON object
+    Invalid,
+}
+
+/// The state of unescaping a string value.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Escape {
+    None,
+    // after a backslash
+    Backslash,
+    // within a `\u` escape, with the code and the number of digits received so far
+    Unicode(u32, u8),
+}
+
+/// The state of scanning a value, which is kept as it is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Raw {
+    // the nesting level of objects and arrays, zero for numbers and literals
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+/// A response handler, which extracts the values of selected members of a JSON object.
+///
+/// Only the members of the top-level object are selected, by their name. Values are kept in
+/// buffers of `N` bytes, for up to `NF` members, and at most 32. String values are
+/// unescaped, all other values, including objects and arrays, are kept as they are. Values which
+/// don't fit into the buffer are dropped, and a warning gets logged. If a member is present more
+/// than once, the last value is kept.
+///
+/// The payload of all responses is scanned, use [`JsonFieldsResponseHandler::code`] to tell a
+/// successful response from an error.
+pub struct JsonFieldsResponseHandler<'f, N = consts::U64, NF = consts::U4>
+where
+    N: ArrayLength<u8>,
+    NF: ArrayLength<Option<Vec<u8, N>>>,
+{
+    fields: &'f [&'f str],
+    values: Vec<Option<Vec<u8, N>>, NF>,
+    state: State,
+    // the fields matching the name being received, as bit set
+    candidates: u32,
+    key_len: usize,
+    // the field of the value being received
+    field: Option<usize>,
+    // a high surrogate, waiting for its low surrogate
+    surrogate: Option<u32>,
+    overflow: bool,
+    code: u16,
+    complete: bool,
+    error: Option<Error>,
+}
+
+impl<'f, N, NF> JsonFieldsResponseHandler<'f, N, NF>
+where
+    N: ArrayLength<u8>,
+    NF: ArrayLength<Option<Vec<u8, N>>>,
+{
+    /// Create a new instance, extracting the values of the members with the names.
+    ///
+    /// Names exceeding the capacity of `NF`, or 32, are ignored.
+    pub fn new(fields: &'f [&'f str]) -> Self {
+        let mut values = Vec::new();
+        for name in fields {
+            if values.len() == 32 || values.push(None).is_err() {
+                log::warn!("Too many fields, ignoring: {}", name);
+                break;
+            }
+        }
+
+        JsonFieldsResponseHandler {
+            fields,
+            values,
+            state: State::Start,
+            candidates: 0,
+            key_len: 0,
+            field: None,
+            surrogate: None,
+            overflow: false,
+            code: 0,
+            complete: false,
+            error: None,
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The error, in case processing the response failed.
+    pub fn error(&self) -> Option<Error> {
+        self.error
+    }
+
+    /// Check if the payload is a JSON object, as far as it was received.
+    ///
+    /// Only the structure of the top-level object is checked, not the values of its members.
+    pub fn is_valid(&self) -> bool {
+        self.state != State::Invalid
+    }
+
+    /// Get the value of the member with the name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// Iterate over the names and values of the members which were found.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .zip(self.values.iter())
+            .filter_map(|(name, value)| Some((*name, from_utf8(value.as_ref()?).ok()?)))
+    }
+
+    fn scan(&mut self, b: u8) {
+        match (self.state, b) {
+            (State::Done, _) | (State::Invalid, _) => {}
+            (_, b' ') | (_, b'\t') | (_, b'\r') | (_, b'\n')
+                if !matches!(
+                    self.state,
+                    State::KeyString(_) | State::StringValue(_) | State::RawValue(_)
+                ) => {}
+            (State::Start, b'{') => self.state = State::Key,
+            (State::Key, b'"') => {
+                self.candidates = match self.values.len() {
+                    0 => 0,
+                    len => u32::MAX >> (32 - len),
+                };
+                self.key_len = 0;
+                self.state = State::KeyString(false);
+            }
+            (State::Key, b'}') | (State::Comma, b'}') => self.state = State::Done,
+            (State::KeyString(false), b'"') => self.state = State::Colon,
+            (State::KeyString(escaped), b) => {
+                self.match_key(b);
+                self.state = State::KeyString(!escaped && b == b'\\');
+            }
+            (State::Colon, b':') => self.state = State::Value,
+            (State::Value, b) => self.begin_value(b),
+            (State::StringValue(escape), b) => self.string_value(escape, b),
+            (State::RawValue(raw), b) => self.raw_value(raw, b),
+            (State::Comma, b',') => self.state = State::Key,
+            _ => self.invalid(),
+        }
+    }
+
+    fn invalid(&mut self) {
+        log::info!("Payload is not a JSON object");
+        self.state = State::Invalid;
+    }
+
+    /// Narrow down the fields which match the name being received.
+    fn match_key(&mut self, b: u8) {
+        for (i, name) in self.fields.iter().enumerate().take(self.values.len()) {
+            if name.as_bytes().get(self.key_len) != Some(&b) {
+                self.candidates &= !(1 << i);
+            }
+        }
+        self.key_len += 1;
+    }
+
+    fn begin_value(&mut self, b: u8) {
+        let key_len = self.key_len;
+        let fields = self.fields;
+        self.field = (0..self.values.len())
+            .find(|i| self.candidates & (1 << i) != 0 && fields[*i].len() == key_len);
+        self.overflow = false;
+        if let Some(field) = self.field {
+            self.values[field] = Some(Vec::new());
+        }
+
+        match b {
+            b'"' => self.state = State::StringValue(Escape::None),
+            b'{' | b'[' => {
+                self.push_raw(b);
+                self.state = State::RawValue(Raw {
+                    depth: 1,
+                    in_string: false,
+                    escaped: false,
+                });
+            }
+            b',' | b'}' | b']' | b':' => self.invalid(),
+            b => {
+                self.push_raw(b);
+                self.state = State::RawValue(Raw {
+                    depth: 0,
+                    in_string: false,
+                    escaped: false,
+                });
+            }
+        }
+    }
+
+    fn string_value(&mut self, escape: Escape, b: u8) {
+        let mut next = Escape::None;
+        match (escape, b) {
+            (Escape::None, b'"') => {
+                self.push_surrogate();
+                return self.end_value();
+            }
+            (Escape::None, b'\\') => next = Escape::Backslash,
+            (Escape::None, b) => {
+                self.push_surrogate();
+                self.push_raw(b);
+            }
+            (Escape::Backslash, b'u') => next = Escape::Unicode(0, 0),
+            (Escape::Backslash, b) => {
+                self.push_surrogate();
+                let c = match b {
+                    b'b' => '\u{8}',
+                    b'f' => '\u{c}',
+                    b'n' => '\n',
+                    b'r' => '\r',
+                    b't' => '\t',
+                    b => b as char,
+                };
+                self.push(c);
+            }
+            (Escape::Unicode(code, digits), b) => match (b as char).to_digit(16) {
+                Some(digit) if digits < 3 => next = Escape::Unicode(code << 4 | digit, digits + 1),
+                Some(digit) => self.push_unicode(code << 4 | digit),
+                None => return self.invalid(),
+            },
+        }
+        self.state = State::StringValue(next);
+    }
+
+    fn raw_value(&mut self, mut raw: Raw, b: u8) {
+        if raw.depth == 0 {
+            if matches!(b, b',' | b'}' | b']') || b.is_ascii_whitespace() {
+                // the end of a number or literal belongs to the object
+                self.end_value();
+                return self.scan(b);
+            }
+        } else if raw.in_string {
+            raw.in_string = raw.escaped || b != b'"';
+            raw.escaped = !raw.escaped && b == b'\\';
+        } else {
+            match b {
+                b'"' => raw.in_string = true,
+                b'{' | b'[' => raw.depth += 1,
+                b'}' | b']' => raw.depth -= 1,
+                _ => {}
+            }
+        }
+
+        self.push_raw(b);
+        match raw.depth {
+            0 if matches!(b, b'}' | b']') => self.end_value(),
+            _ => self.state = State::RawValue(raw),
+        }
+    }
+
+    fn end_value(&mut self) {
+        if let Some(field) = self.field.take() {
+            let valid = matches!(&self.values[field], Some(value) if from_utf8(value).is_ok());
+            if self.overflow || !valid {
+                log::warn!(
+                    "Value exceeds buffer, or is invalid: {}",
+                    self.fields[field]
+                );
+                self.values[field] = None;
+            }
+        }
+        self.state = State::Comma;
+    }
+
+    /// Add the character of a `\u` escape, combining surrogate pairs.
+    fn push_unicode(&mut self, code: u32) {
+        match (self.surrogate.take(), code) {
+            (None, 0xD800..=0xDBFF) => self.surrogate = Some(code),
+            (Some(high), 0xDC00..=0xDFFF) => {
+                let code = 0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00);
+                self.push(core::char::from_u32(code).unwrap_or('\u{FFFD}'));
+            }
+            (high, code) => {
+                if high.is_some() {
+                    self.push('\u{FFFD}');
+                }
+                match code {
+                    0xD800..=0xDBFF => self.surrogate = Some(code),
+                    code => self.push(core::char::from_u32(code).unwrap_or('\u{FFFD}')),
+                }
+            }
+        }
+    }
+
+    /// Replace a high surrogate, which isn't followed by its low surrogate.
+    fn push_surrogate(&mut self) {
+        if self.surrogate.take().is_some() {
+            self.push('\u{FFFD}');
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        c.encode_utf8(&mut [0u8; 4])
+            .bytes()
+            .for_each(|b| self.push_raw(b));
+    }
+
+    /// Add a byte of the value as it is, the value is checked to be valid UTF-8 once it ends.
+    fn push_raw(&mut self, b: u8) {
+        if let Some(field) = self.field {
+            if let Some(value) = &mut self.values[field] {
+                self.overflow |= value.push(b).is_err();
+            }
+        }
+    }
+}
+
+impl<'f, N, NF> ResponseHandler for JsonFieldsResponseHandler<'f, N, NF>
+where
+    N: ArrayLength<u8>,
+    NF: ArrayLength<Option<Vec<u8, N>>>,
+{
+    fn response(&mut self, response: Response) {
+        self.code = response.code;
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        match payload {
+            Ok(Some(data)) => data.iter().for_each(|b| self.scan(*b)),
+            Ok(None) => {
+                if let State::RawValue(Raw { depth: 0, .. }) = self.state {
+                    // a number or literal, ending with the payload
+                    self.end_value();
+                }
+                if self.state != State::Done {
+                    log::info!("Incomplete JSON object");
+                    self.state = State::Invalid;
+                }
+                self.complete = true;
+            }
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! A loopback transport, for testing requests without a network.
+//!
+//! The [`Loopback`] acts as [`Sink`] and [`Source`] at the same time. The request sent to it
+//! is recorded, and when piping data, a [`Responder`] is asked to produce the response, which
+//! then gets pushed to the request.
+//!
+//! ~~~
+//! use heapless::consts::*;
+//! use drogue_http_client::*;
+//! use drogue_http_client::loopback::*;
+//!
+//! let mut lo = Loopback::<U1024, _>::new(
+//!     CannedResponse::new(200, "OK")
+//!         .body(b"0123456789")
+//!         .split(&[5, 3]),
+//! );
+//!
+//! let mut req = HttpConnection::<U1024>::new()
+//!     .post("/")
+//!     .handler(BufferResponseHandler::<U1024>::new())
+//!     .execute(&mut lo);
+//!
+//! lo.pipe_data(&mut req).unwrap();
+//!
+//! let (_, handler) = req.try_complete().unwrap();
+//!
+//! assert_eq!(lo.request(), b"POST / HTTP/1.1\r\n\r\n");
+//! assert_eq!(handler.code(), 200);
+//! assert_eq!(handler.payload(), b"0123456789");
+//! ~~~
+
+use crate::{Budget, Inbound, PipeError, Progress, Sink, Source};
+use core::fmt::Write;
+use heapless::{consts, ArrayLength, String, Vec};
+
+/// Produces the response to a request.
+pub trait Responder {
+    /// Write the response for the request to the sink.
+    fn respond(&mut self, request: &[u8], response: &mut dyn Sink) -> Result<(), ()>;
+
+    /// The sizes of the chunks the response will be pushed in.
+    ///
+    /// Any remaining data will be pushed as one last chunk.
+    fn split(&self) -> &[usize] {
+        &[]
+    }
+
+    /// Notify the request that the connection was closed after the response.
+    fn close(&self) -> bool {
+        false
+    }
+}
+
+/// A pre-defined response.
+pub struct CannedResponse<'a> {
+    code: u16,
+    reason: &'a str,
+    headers: &'a [(&'a str, &'a str)],
+    body: &'a [u8],
+    split: &'a [usize],
+    content_length: bool,
+    close: bool,
+}
+
+impl<'a> CannedResponse<'a> {
+    /// Create a new response, with an empty body.
+    pub fn new(code: u16, reason: &'a str) -> Self {
+        CannedResponse {
+            code,
+            reason,
+            headers: &[],
+            body: &[],
+            split: &[],
+            content_length: true,
+            close: false,
+        }
+    }
+
+    /// Set the response headers.
+    pub fn headers(mut self, headers: &'a [(&'a str, &'a str)]) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Set the response body.
+    pub fn body(mut self, body: &'a [u8]) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Set the sizes of the chunks the response will be pushed in.
+    pub fn split(mut self, split: &'a [usize]) -> Self {
+        self.split = split;
+        self
+    }
+
+    /// Set if a `Content-Length` header should be added, enabled by default.
+    pub fn content_length(mut self, content_length: bool) -> Self {
+        self.content_length = content_length;
+        self
+    }
+
+    /// Set if the connection should be closed after the response.
+    pub fn close(mut self, close: bool) -> Self {
+        self.close = close;
+        self
+    }
+}
+
+impl<'a> Responder for CannedResponse<'a> {
+    fn respond(&mut self, _: &[u8], response: &mut dyn Sink) -> Result<(), ()> {
+        let mut line = String::<consts::U256>::new();
+
+        write!(line, "HTTP/1.1 {} {}\r\n", self.code, self.reason).map_err(|_| ())?;
+        if self.content_length {
+            write!(line, "Content-Length: {}\r\n", self.body.len()).map_err(|_| ())?;
+        }
+        response.send(line.as_bytes())?;
+
+        for header in self.headers {
+            line = String::new();
+            write!(line, "{}: {}\r\n", header.0, header.1).map_err(|_| ())?;
+            response.send(line.as_bytes())?;
+        }
+
+        response.send(b"\r\n")?;
+        response.send(self.body)?;
+
+        Ok(())
+    }
+
+    fn split(&self) -> &[usize] {
+        self.split
+    }
+
+    fn close(&self) -> bool {
+        self.close
+    }
+}
+
+/// A loopback [`Sink`] and [`Source`], answering requests using a [`Responder`].
+pub struct Loopback<N, R>
+where
+    N: ArrayLength<u8>,
+    R: Responder,
+{
+    responder: R,
+    request: Vec<u8, N>,
+    responded: bool,
+    requests: usize,
+    // the pending response, and the position of the next byte to push
+    response: Option<(Vec<u8, N>, usize)>,
+}
+
+impl<N, R> Loopback<N, R>
+where
+    N: ArrayLength<u8>,
+    R: Responder,
+{
+    /// Create a new loopback, using the responder for answering requests.
+    pub fn new(responder: R) -> Self {
+        Loopback {
+            responder,
+            request: Vec::new(),
+            responded: false,
+            requests: 0,
+            response: None,
+        }
+    }
+
+    /// The data of the current, or last, request.
+    pub fn request(&self) -> &[u8] {
+        &self.request
+    }
+
+    /// The number of requests which have been answered.
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    /// Access the responder.
+    pub fn responder(&mut self) -> &mut R {
+        &mut self.responder
+    }
+}
+
+impl<N, R> Sink for Loopback<N, R>
+where
+    N: ArrayLength<u8>,
+    R: Responder,
+{
+    fn send(&mut self, data: &[u8]) -> Result<usize, ()> {
+        if self.responded {
+            // start a new request
+            self.request = Vec::new();
+            self.responded = false;
+            self.response = None;
+        }
+        self.request.send(data)
+    }
+}
+
+impl<N, R> Source for Loopback<N, R>
+where
+    N: ArrayLength<u8>,
+    R: Responder,
+{
+    type Error = ();
+
+    fn pipe_data_limited<Q>(
+        &mut self,
+        request: &mut Q,
+        budget: Budget,
+    ) -> Result<Progress, PipeError<Self::Error>>
+    where
+        Q: Inbound,
+    {
+        if !self.responded {
+            let mut response = Vec::<u8, N>::new();
+            self.responder
+                .respond(&self.request, &mut response)
+                .map_err(|err| {
+                    request.transport_failed();
+                    PipeError::Transport(err)
+                })?;
+
+            self.responded = true;
+            self.requests += 1;
+            self.response = Some((response, 0));
+        }
+
+        let mut progress = Progress::default();
+
+        while !request.is_complete() && request.error().is_none() && !budget.is_exhausted(&progress)
+        {
+            let (response, pos) = match &mut self.response {
+                Some(response) => response,
+                None if request.pending_payload() > 0 => {
+                    progress.reads += 1;
+                    request.redeliver();
+                    continue;
+                }
+                None => break,
+            };
+
+            // next chunk boundary, according to the split pattern
+            let end = self
+                .responder
+                .split()
+                .iter()
+                .scan(0usize, |sum, size| {
+                    *sum += size;
+                    Some(*sum)
+                })
+                .find(|end| *end > *pos)
+                .unwrap_or_else(|| response.len())
+                .min(response.len());
+            let len = budget.remaining_bytes(&progress, end - *pos);
+
+            progress.reads += 1;
+            progress.bytes += len;
+            request.push_data(&response[*pos..*pos + len]);
+            *pos += len;
+
+            if *pos >= response.len() {
+                if self.responder.close() {
+                    request.push_close();
+                }
+                self.response = None;
+            }
+        }
+
+        if let Some(err) = request.error() {
+            return Err(err.into());
+        }
+
+        progress.complete = request.is_complete();
+        Ok(progress)
+    }
+}
@@ -0,0 +1,53 @@
+/// Metrics of a connection.
+///
+/// All counters saturate at their maximum value.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Requests which have been sent.
+    pub requests: usize,
+    /// Bytes accepted by the sink.
+    pub bytes_sent: usize,
+    /// Bytes pushed to requests.
+    pub bytes_received: usize,
+    /// Responses with a `1xx` status code.
+    pub informational: usize,
+    /// Responses with a `2xx` status code.
+    pub success: usize,
+    /// Responses with a `3xx` status code.
+    pub redirection: usize,
+    /// Responses with a `4xx` status code.
+    pub client_error: usize,
+    /// Responses with a `5xx` status code.
+    pub server_error: usize,
+    /// Failures of the transport, when sending or receiving.
+    pub transport_errors: usize,
+    /// Responses which failed to process.
+    pub protocol_errors: usize,
+}
+
+impl Metrics {
+    /// The number of responses received, regardless of their status.
+    pub fn responses(&self) -> usize {
+        self.informational
+            .saturating_add(self.success)
+            .saturating_add(self.redirection)
+            .saturating_add(self.client_error)
+            .saturating_add(self.server_error)
+    }
+
+    pub(crate) fn record_response(&mut self, code: u16) {
+        let counter = match code {
+            100..=199 => &mut self.informational,
+            200..=299 => &mut self.success,
+            300..=399 => &mut self.redirection,
+            400..=499 => &mut self.client_error,
+            500..=599 => &mut self.server_error,
+            _ => return,
+        };
+        increment(counter, 1);
+    }
+}
+
+pub(crate) fn increment(counter: &mut usize, amount: usize) {
+    *counter = counter.saturating_add(amount);
+}
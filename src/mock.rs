@@ -1,7 +1,9 @@
 //! Implementations just for the sake of creating compilable documentation.
 
-use drogue_network::addr::HostSocketAddr;
+use drogue_network::addr::{HostAddr, HostSocketAddr, IpAddr};
+use drogue_network::dns::{AddrType, Dns, DnsError};
 use drogue_network::tcp::{Mode, TcpError, TcpStack};
+use heapless::{consts, String};
 
 pub struct MockStack {}
 
@@ -58,3 +60,15 @@ impl TcpStack for MockStack {
         unimplemented!()
     }
 }
+
+impl Dns for MockStack {
+    type Error = DnsError;
+
+    fn gethostbyname(&self, _: &str, _: AddrType) -> Result<HostAddr, Self::Error> {
+        unimplemented!()
+    }
+
+    fn gethostbyaddr(&self, _: IpAddr) -> Result<String<consts::U256>, Self::Error> {
+        unimplemented!()
+    }
+}
@@ -0,0 +1,374 @@
+//! Firmware downloads.
+//!
+//! A [`Download`] fetches an image in chunks, using range requests, and writes it to an
+//! [`embedded-storage`](embedded_storage) storage. The offset of the data written so far is
+//! persisted after each chunk, so that the download can be resumed after a reset. Once
+//! complete, the image is read back from the storage, and verified against its SHA-256 digest.
+//!
+//! This module requires the `ota` feature.
+//!
+//! ~~~no_run
+//! # use embedded_storage::{ReadStorage, Storage};
+//! # struct Flash;
+//! # impl ReadStorage for Flash {
+//! #     type Error = ();
+//! #     fn read(&mut self, _: u32, _: &mut [u8]) -> Result<(), ()> { Ok(()) }
+//! #     fn capacity(&self) -> usize { 0 }
+//! # }
+//! # impl Storage for Flash {
+//! #     fn write(&mut self, _: u32, _: &[u8]) -> Result<(), ()> { Ok(()) }
+//! # }
+//! # struct Settings;
+//! # impl drogue_http_client::ota::OffsetStore for Settings {
+//! #     fn load(&mut self) -> u32 { 0 }
+//! #     fn store(&mut self, _: u32) -> Result<(), ()> { Ok(()) }
+//! # }
+//! # let (flash, settings, digest) = (Flash, Settings, [0u8; 32]);
+//! use heapless::consts::*;
+//!
+//! use drogue_network::addr::HostSocketAddr;
+//!
+//! use drogue_http_client::client::HttpClient;
+//! use drogue_http_client::ota::Download;
+//! use drogue_http_client::*;
+//!
+//! # use drogue_http_client::mock;
+//! # let (network, _) = mock::mock_connection();
+//! let remote = HostSocketAddr::from("192.168.1.1", 8080).unwrap();
+//! let mut client = HttpClient::new(&network, remote, HttpConnection::<U1024>::new());
+//!
+//! let mut download = Download::new("/firmware/1.2.0", flash, settings, digest);
+//! let size = download.run(&mut client).unwrap();
+//! ~~~
+
+use crate::client::HttpClient;
+use crate::headers::{CONTENT_LENGTH, CONTENT_RANGE, RANGE};
+use crate::{Error, Interceptor, PipeError, Response, ResponseHandler};
+use core::cell::RefCell;
+use core::fmt::Write;
+use drogue_network::tcp::TcpStack;
+use embedded_storage::Storage;
+use heapless::{consts, ArrayLength, String};
+use sha2::{Digest, Sha256};
+
+/// Persists the offset of a download.
+pub trait OffsetStore {
+    /// Load the offset, up to which the image was written.
+    fn load(&mut self) -> u32;
+
+    /// Persist the offset.
+    fn store(&mut self, offset: u32) -> Result<(), ()>;
+}
+
+/// An offset which is kept in memory only, so a download can't be resumed after a reset.
+impl OffsetStore for u32 {
+    fn load(&mut self) -> u32 {
+        *self
+    }
+
+    fn store(&mut self, offset: u32) -> Result<(), ()> {
+        *self = offset;
+        Ok(())
+    }
+}
+
+/// Errors of a download.
+#[derive(Debug)]
+pub enum DownloadError<T, S> {
+    /// Executing a request failed.
+    Request(PipeError<T>),
+    /// Writing to, or reading from, the storage failed.
+    Storage(S),
+    /// Persisting the offset failed.
+    Offset,
+    /// The image doesn't fit into the storage.
+    TooLarge,
+    /// The range of the response doesn't match the request.
+    Range,
+    /// The image doesn't match the digest. The download will start over on the next attempt.
+    Digest,
+}
+
+impl<T, S> From<PipeError<T>> for DownloadError<T, S> {
+    fn from(err: PipeError<T>) -> Self {
+        DownloadError::Request(err)
+    }
+}
+
+/// The download of a firmware image, into a storage.
+pub struct Download<'p, S, O>
+where
+    S: Storage,
+    O: OffsetStore,
+{
+    path: &'p str,
+    storage: RefCell<S>,
+    offsets: O,
+    digest: [u8; 32],
+    chunk_size: u32,
+}
+
+impl<'p, S, O> Download<'p, S, O>
+where
+    S: Storage,
+    O: OffsetStore,
+{
+    /// Create a new download of the image at the path, with the expected SHA-256 digest.
+    pub fn new(path: &'p str, storage: S, offsets: O, digest: [u8; 32]) -> Self {
+        Download {
+            path,
+            storage: RefCell::new(storage),
+            offsets,
+            digest,
+            chunk_size: 4096,
+        }
+    }
+
+    /// Set the number of bytes requested at once, defaults to 4096.
+    ///
+    /// The offset is persisted after each chunk.
+    pub fn chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Give back the storage and offset store.
+    pub fn into_inner(self) -> (S, O) {
+        (self.storage.into_inner(), self.offsets)
+    }
+
+    /// Download the remaining part of the image, and verify it, returning its size.
+    ///
+    /// If the download is interrupted, it can be resumed by calling this function again, with
+    /// the same storage and offset store.
+    pub fn run<T, IN, I>(
+        &mut self,
+        client: &mut HttpClient<'_, T, IN, I>,
+    ) -> Result<u32, DownloadError<T::Error, S::Error>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+    {
+        let size = loop {
+            let offset = self.offsets.load();
+            let chunk = self.request(client, offset)?;
+
+            let total = chunk.total.ok_or(DownloadError::Range)?;
+            match chunk.code {
+                416 if offset == total => break total,
+                416 => return Err(DownloadError::Range),
+                // no progress
+                _ if chunk.offset == offset && offset < total => return Err(DownloadError::Range),
+                _ => {}
+            }
+
+            self.offsets
+                .store(chunk.offset)
+                .map_err(|_| DownloadError::Offset)?;
+            if chunk.offset >= total {
+                break total;
+            }
+        };
+
+        if self.verify(size)? {
+            Ok(size)
+        } else {
+            self.offsets.store(0).map_err(|_| DownloadError::Offset)?;
+            Err(DownloadError::Digest)
+        }
+    }
+
+    /// Request the next chunk, starting at the offset.
+    fn request<T, IN, I>(
+        &self,
+        client: &mut HttpClient<'_, T, IN, I>,
+        offset: u32,
+    ) -> Result<Chunk, DownloadError<T::Error, S::Error>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+    {
+        let mut range = String::<consts::U32>::new();
+        write!(
+            range,
+            "bytes={}-{}",
+            offset,
+            offset.saturating_add(self.chunk_size - 1)
+        )
+        .ok();
+
+        let storage = &self.storage;
+        let handler = client.request("GET", self.path, |req, sink| {
+            req.header(RANGE, &range)
+                .handler(ChunkHandler::new(storage, offset))
+                .execute(sink)
+        })?;
+
+        if let Some(err) = handler.error {
+            return Err(err.into());
+        }
+        match handler.code {
+            200 | 206 | 416 => Ok(Chunk {
+                code: handler.code,
+                offset: handler.offset,
+                total: handler.total,
+            }),
+            code => Err(PipeError::Protocol(Error::Status(code)).into()),
+        }
+    }
+
+    /// Check the digest of the image in the storage.
+    fn verify<T>(&mut self, size: u32) -> Result<bool, DownloadError<T, S::Error>> {
+        let mut storage = self.storage.borrow_mut();
+        let mut hasher = Sha256::new();
+        let mut buffer = [0u8; 64];
+        let mut pos = 0u32;
+        while pos < size {
+            let len = ((size - pos) as usize).min(buffer.len());
+            storage
+                .read(pos, &mut buffer[..len])
+                .map_err(DownloadError::Storage)?;
+            hasher.update(&buffer[..len]);
+            pos += len as u32;
+        }
+
+        let verified = hasher.finalize()[..] == self.digest[..];
+        if !verified {
+            log::warn!("Digest mismatch, for {} bytes", size);
+        }
+        Ok(verified)
+    }
+}
+
+/// The outcome of requesting a chunk.
+struct Chunk {
+    code: u16,
+    // the offset after the chunk
+    offset: u32,
+    total: Option<u32>,
+}
+
+/// Errors of writing a chunk.
+enum ChunkError<E> {
+    Storage(E),
+    TooLarge,
+    Range,
+}
+
+impl<T, S> From<ChunkError<S>> for DownloadError<T, S> {
+    fn from(err: ChunkError<S>) -> Self {
+        match err {
+            ChunkError::Storage(err) => DownloadError::Storage(err),
+            ChunkError::TooLarge => DownloadError::TooLarge,
+            ChunkError::Range => DownloadError::Range,
+        }
+    }
+}
+
+/// Writes the payload of a (partial) response to the storage.
+struct ChunkHandler<'s, S>
+where
+    S: Storage,
+{
+    storage: &'s RefCell<S>,
+    // the position to write the next byte to
+    offset: u32,
+    // bytes before the requested range, when the server sent the complete image
+    skip: u32,
+    code: u16,
+    total: Option<u32>,
+    error: Option<ChunkError<S::Error>>,
+}
+
+impl<'s, S> ChunkHandler<'s, S>
+where
+    S: Storage,
+{
+    fn new(storage: &'s RefCell<S>, offset: u32) -> Self {
+        ChunkHandler {
+            storage,
+            offset,
+            skip: 0,
+            code: 0,
+            total: None,
+            error: None,
+        }
+    }
+}
+
+impl<'s, S> ResponseHandler for ChunkHandler<'s, S>
+where
+    S: Storage,
+{
+    fn response(&mut self, response: Response) {
+        self.code = response.code;
+        match response.code {
+            206 | 416 => {
+                let range = response.headers.get(CONTENT_RANGE).and_then(content_range);
+                match range {
+                    Some((start, total)) if start.unwrap_or(self.offset) == self.offset => {
+                        self.total = total
+                    }
+                    _ => self.error = Some(ChunkError::Range),
+                }
+            }
+            200 => {
+                // the server ignored the range
+                self.skip = self.offset;
+                self.offset = 0;
+                self.total = response
+                    .headers
+                    .get(CONTENT_LENGTH)
+                    .and_then(|len| len.parse().ok());
+            }
+            _ => {}
+        }
+
+        if matches!(self.total, Some(total) if total as usize > self.storage.borrow().capacity()) {
+            self.error = Some(ChunkError::TooLarge);
+        }
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        let mut data = match payload {
+            Ok(Some(data)) if self.error.is_none() && matches!(self.code, 200 | 206) => data,
+            _ => return,
+        };
+
+        let skip = (self.skip as usize).min(data.len());
+        data = &data[skip..];
+        self.skip -= skip as u32;
+        self.offset += skip as u32;
+        if data.is_empty() {
+            return;
+        }
+
+        if matches!(self.total, Some(total) if self.offset as usize + data.len() > total as usize) {
+            self.error = Some(ChunkError::Range);
+            return;
+        }
+
+        match self.storage.borrow_mut().write(self.offset, data) {
+            Ok(_) => self.offset += data.len() as u32,
+            Err(err) => self.error = Some(ChunkError::Storage(err)),
+        }
+    }
+}
+
+/// Parse the value of a `Content-Range` header, like `bytes 0-1023/4096`, or `bytes */4096`,
+/// returning the start of the range, and the total size, if known.
+fn content_range(value: &str) -> Option<(Option<u32>, Option<u32>)> {
+    let value = value.trim().strip_prefix("bytes ")?;
+    let (range, total) = value.split_at(value.find('/')?);
+    let total = match &total[1..] {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    let start = match range {
+        "*" => None,
+        range => Some(range[..range.find('-')?].parse().ok()?),
+    };
+    Some((start, total))
+}
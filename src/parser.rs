@@ -0,0 +1,1340 @@
+//! Parsing of HTTP/1.1 responses.
+//!
+//! A [`ResponseParser`] processes the status line and headers of a response, and the framing of
+//! its body, by `Content-Length`, the "chunked" transfer encoding, or by closing the
+//! connection. The response is passed on to a [`ResponseHandler`]. This is what a
+//! [`Request`](crate::Request) uses, but it doesn't need a connection, so that the parser can
+//! be driven directly, e.g. for transports which don't fit the [`Source`](crate::Source) model.
+//!
+//! ~~~
+//! use heapless::consts::*;
+//!
+//! use drogue_http_client::parser::ResponseParser;
+//! use drogue_http_client::*;
+//!
+//! let mut parser = ResponseParser::<U1024, _>::new(BufferResponseHandler::<U64>::new());
+//!
+//! parser.push_data(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n");
+//! parser.push_data(b"5\r\nHello\r\n7\r\n, World\r\n0\r\n\r\n");
+//!
+//! assert!(parser.is_complete());
+//! assert_eq!(parser.handler().payload_str(), Ok("Hello, World"));
+//! ~~~
+
+use crate::headers::{ResponseHeaders, CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING};
+use crate::{
+    ConnectionEvent, Error, Inbound, IncompleteReason, Interceptor, Response, ResponseHandler,
+    RingBuffer,
+};
+use core::convert::TryFrom;
+use core::str::from_utf8;
+use heapless::ArrayLength;
+use httparse::Status;
+
+/// The maximum number of headers of a response.
+pub const MAX_HEADERS: usize = 16;
+
+// the version of the format of a suspended state
+const STATE_FORMAT: u8 = 1;
+
+#[derive(Copy, Clone, Debug)]
+enum State {
+    Header,
+    Failed(Error),
+    Payload(usize),
+    Chunked(Chunk),
+    Complete,
+    UnlimitedPayload,
+    Upgraded,
+}
+
+/// The position in a body using the "chunked" transfer encoding.
+#[derive(Copy, Clone, Debug)]
+enum Chunk {
+    /// The line with the size of the next chunk, and the size parsed so far.
+    Size {
+        size: usize,
+        digits: bool,
+        ext: bool,
+    },
+    /// The data of a chunk, with the remaining number of bytes.
+    Data(usize),
+    /// The line break after the data of a chunk.
+    DataEnd,
+    /// The trailer, after the last chunk.
+    Trailer { empty_line: bool },
+    /// The body is complete, but the handler did not consume all of it yet.
+    Done,
+}
+
+/// Parses a response, passing it on to a handler.
+///
+/// The parser buffers the response header, which must fit into `N` bytes, and payload which
+/// the handler did not consume yet. A header which is pushed completely at once, is parsed
+/// directly from the pushed data, without buffering it. Otherwise, once the status line was
+/// received, the header is only parsed again when it is complete, so that receiving it in many
+/// small fragments doesn't parse it over and over. Data received after the response is kept in
+/// the buffer, for the next response.
+pub struct ResponseParser<N, R>
+where
+    N: ArrayLength<u8>,
+    R: ResponseHandler,
+{
+    // the header, or pending payload
+    buffer: RingBuffer<N>,
+    handler: R,
+    state: State,
+    // processed bytes of the payload, for chunked payloads including the announced chunk
+    processed_bytes: usize,
+    // the pending payload, preceding the trailer of a chunked payload in the buffer
+    trailer: usize,
+    // the maximum size of the payload
+    max_body_size: Option<usize>,
+    // the maximum size of the header
+    max_header_size: Option<usize>,
+    // the maximum number of headers
+    max_headers: usize,
+    // pass on the reason phrase
+    capture_reason: bool,
+    // tolerate malformed responses
+    lenient: bool,
+    // the response is for a `CONNECT` request
+    tunnel: bool,
+    // the response is for a `HEAD` request
+    head: bool,
+    // the server closes the connection after the response
+    close: bool,
+    // the size of the free space of the buffer, handed out by `recv_grant`
+    grant: Option<usize>,
+    // the buffered bytes of the header, which were scanned for its end already
+    scanned: usize,
+    // leave data which can't be buffered to the caller, instead of dropping it
+    backpressure: bool,
+}
+
+impl<N, R> ResponseParser<N, R>
+where
+    N: ArrayLength<u8>,
+    R: ResponseHandler,
+{
+    /// Create a new instance, for parsing a single response.
+    pub fn new(handler: R) -> Self {
+        Self::with_buffer(handler, RingBuffer::new())
+    }
+
+    /// Create a new instance, with data already received.
+    pub(crate) fn with_buffer(handler: R, buffer: RingBuffer<N>) -> Self {
+        ResponseParser {
+            buffer,
+            handler,
+            state: State::Header,
+            processed_bytes: 0,
+            trailer: 0,
+            max_body_size: None,
+            max_header_size: None,
+            max_headers: MAX_HEADERS,
+            capture_reason: !cfg!(feature = "minimal"),
+            lenient: false,
+            tunnel: false,
+            head: false,
+            close: false,
+            grant: None,
+            scanned: 0,
+            backpressure: false,
+        }
+    }
+
+    /// Set if the reason phrase is passed on to the handler.
+    ///
+    /// By default, the reason phrase is passed on, unless the `minimal` feature is enabled. With
+    /// the `tiny` feature, it is never passed on.
+    pub fn capture_reason(mut self, capture_reason: bool) -> Self {
+        self.capture_reason = capture_reason;
+        self
+    }
+
+    /// Set if the response should be parsed in lenient mode, disabled by default.
+    ///
+    /// See [`HttpConnection::lenient`](crate::HttpConnection::lenient).
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Set if the response is for a `CONNECT` request, disabled by default.
+    ///
+    /// A `2xx` response then establishes a tunnel, the same way as switching protocols does, see
+    /// [`ResponseParser::is_upgraded`].
+    pub fn tunnel(mut self, tunnel: bool) -> Self {
+        self.tunnel = tunnel;
+        self
+    }
+
+    /// Set if the response is for a `HEAD` request, disabled by default.
+    ///
+    /// The response then has no payload, even when announcing one.
+    pub fn head(mut self, head: bool) -> Self {
+        self.head = head;
+        self
+    }
+
+    /// Fail with [`Error::BodyTooLarge`], when the payload of the response exceeds the size.
+    ///
+    /// A payload announced to be larger fails right after the header, before receiving any of
+    /// it. By default, the size is not limited.
+    pub fn max_body_size(mut self, max_body_size: Option<usize>) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Fail with [`Error::HeaderTooLarge`], when the header of the response exceeds the size.
+    ///
+    /// This is checked independently of the capacity of the buffer, covering the status line,
+    /// the headers and the empty line ending them. By default, the size is not limited.
+    pub fn max_header_size(mut self, max_header_size: Option<usize>) -> Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+
+    /// Fail with [`Error::Parse`], when the response has more headers than `max_headers`.
+    ///
+    /// The number of headers can't exceed [`MAX_HEADERS`], which is also the default.
+    pub fn max_headers(mut self, max_headers: usize) -> Self {
+        self.max_headers = max_headers.min(MAX_HEADERS);
+        self
+    }
+
+    /// Get the handler.
+    pub fn handler(&self) -> &R {
+        &self.handler
+    }
+
+    /// Get the handler, mutably.
+    pub fn handler_mut(&mut self) -> &mut R {
+        &mut self.handler
+    }
+
+    /// Give back the handler, and the buffered data.
+    ///
+    /// Once the response is complete, this is the data which was received after it.
+    pub fn into_parts(mut self) -> (R, RingBuffer<N>) {
+        self.release_grant();
+        (self.handler, self.buffer)
+    }
+
+    /// Check if the response is completely processed.
+    pub fn is_complete(&self) -> bool {
+        matches!(self.state, State::Complete | State::Upgraded)
+    }
+
+    /// Check if the server switched to a different protocol, or established a tunnel.
+    ///
+    /// All data received after the response header belongs to the new protocol.
+    pub fn is_upgraded(&self) -> bool {
+        matches!(self.state, State::Upgraded)
+    }
+
+    /// Check if the server closes the connection after the response.
+    ///
+    /// This is the case when the response has a `Connection: close` header, is an HTTP/1.0
+    /// response without `Connection: keep-alive`, or its payload is delimited by closing the
+    /// connection. No further request can be sent over the connection then.
+    pub fn is_close(&self) -> bool {
+        self.close
+    }
+
+    /// Get the reason why the response is not complete, or `None` if it is.
+    pub fn incomplete_reason(&self) -> Option<IncompleteReason> {
+        match self.state {
+            State::Header => Some(IncompleteReason::Header),
+            State::Payload(_) | State::Chunked(_) | State::UnlimitedPayload => {
+                Some(IncompleteReason::Body)
+            }
+            State::Failed(err) => Some(IncompleteReason::Failed(err)),
+            State::Complete | State::Upgraded => None,
+        }
+    }
+
+    /// Get the error, if processing the response failed.
+    ///
+    /// Once failed, no more data will be processed.
+    pub fn error(&self) -> Option<Error> {
+        match self.state {
+            State::Failed(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Push more data of the response.
+    ///
+    /// Payload which the handler doesn't consume, and which doesn't fit into the buffer, is
+    /// dropped. See [`ResponseParser::try_push_data`] for applying backpressure instead.
+    pub fn push_data(&mut self, data: &[u8]) {
+        self.push_data_observed(data, &mut ());
+    }
+
+    /// Push more data of the response, returning the number of bytes which were accepted.
+    ///
+    /// Instead of dropping payload which the handler doesn't consume, when the buffer is full,
+    /// processing stops there. The remaining data must be pushed again later, e.g. after the
+    /// handler consumed the pending payload using [`ResponseParser::redeliver`].
+    ///
+    /// Fails with the error, once processing the response failed, so that no more data is fed
+    /// into a broken response.
+    ///
+    /// ~~~
+    /// use heapless::consts::*;
+    ///
+    /// use drogue_http_client::parser::ResponseParser;
+    /// use drogue_http_client::*;
+    ///
+    /// let mut parser = ResponseParser::<U64, _>::new(BufferResponseHandler::<U64>::new());
+    ///
+    /// let data = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+    /// assert_eq!(parser.try_push_data(data), Ok(data.len()));
+    /// assert!(parser.is_complete());
+    ///
+    /// let mut parser = ResponseParser::<U64, _>::new(BufferResponseHandler::<U64>::new());
+    /// assert_eq!(parser.try_push_data(b"HTTP/1.1 abc OK\r\n\r\n"), Err(Error::Parse(httparse::Error::Status)));
+    /// ~~~
+    pub fn try_push_data(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.try_push_data_observed(data, &mut ())
+    }
+
+    /// Notify that the connection was closed.
+    pub fn push_close(&mut self) {
+        self.push_close_observed(&mut ())
+    }
+
+    /// Retry delivering payload, which the handler did not consume yet.
+    pub fn redeliver(&mut self) {
+        self.redeliver_observed(&mut ())
+    }
+
+    /// The number of payload bytes, which are buffered as the handler did not consume them yet.
+    pub fn pending_payload(&self) -> usize {
+        match self.state {
+            State::Chunked(Chunk::Trailer { .. }) => self.trailer,
+            State::Payload(_) | State::Chunked(_) | State::UnlimitedPayload => self.buffer.len(),
+            State::Header | State::Complete | State::Upgraded | State::Failed(_) => 0,
+        }
+    }
+
+    /// Save the state of processing the response, including the buffered data, returning the
+    /// number of bytes written to `out`.
+    ///
+    /// This allows to continue processing the response later, using [`ResponseParser::resume`],
+    /// e.g. after waking up from deep sleep, over a resumed connection. The state of the handler,
+    /// and the configuration of the parser, are not included. Fails with [`Error::Overflow`],
+    /// when `out` is too small, or with the error, when processing the response failed.
+    ///
+    /// ~~~
+    /// use heapless::consts::*;
+    ///
+    /// use drogue_http_client::parser::ResponseParser;
+    /// use drogue_http_client::*;
+    ///
+    /// let mut parser = ResponseParser::<U64, _>::new(BufferResponseHandler::<U64>::new());
+    /// parser.push_data(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHel");
+    ///
+    /// let mut state = [0u8; 128];
+    /// let len = parser.suspend(&mut state).unwrap();
+    ///
+    /// // later, the handler keeps its own state
+    /// let (handler, _) = parser.into_parts();
+    /// let mut parser = ResponseParser::<U64, _>::new(handler);
+    /// parser.resume(&state[..len]).unwrap();
+    /// parser.push_data(b"lo");
+    ///
+    /// assert!(parser.is_complete());
+    /// assert_eq!(parser.handler().payload_str(), Ok("Hello"));
+    /// ~~~
+    pub fn suspend(&self, out: &mut [u8]) -> Result<usize, Error> {
+        let (state, chunk, flags, value) = match self.state {
+            State::Header => (0, 0, 0, 0),
+            State::Failed(err) => return Err(err),
+            State::Payload(size) => (1, 0, 0, size),
+            State::Chunked(chunk) => match chunk {
+                Chunk::Size { size, digits, ext } => (2, 0, digits as u8 | (ext as u8) << 1, size),
+                Chunk::Data(remaining) => (2, 1, 0, remaining),
+                Chunk::DataEnd => (2, 2, 0, 0),
+                Chunk::Trailer { empty_line } => (2, 3, empty_line as u8, 0),
+                Chunk::Done => (2, 4, 0, 0),
+            },
+            State::Complete => (3, 0, 0, 0),
+            State::UnlimitedPayload => (4, 0, 0, 0),
+            State::Upgraded => (5, 0, 0, 0),
+        };
+
+        let flags = flags | (self.close as u8) << 7;
+
+        let mut writer = StateWriter { out, len: 0 };
+        writer.put(&[STATE_FORMAT, state, chunk, flags])?;
+        for value in &[
+            value,
+            self.processed_bytes,
+            self.trailer,
+            self.scanned,
+            self.buffer.len(),
+        ] {
+            writer.put(&(*value as u64).to_le_bytes())?;
+        }
+        let (first, second) = self.buffer.as_slices();
+        writer.put(first)?;
+        writer.put(second)?;
+
+        Ok(writer.len)
+    }
+
+    /// Restore the state of processing a response, which was saved by
+    /// [`ResponseParser::suspend`].
+    ///
+    /// Data which was buffered before is replaced. Fails with [`Error::InvalidState`], when the
+    /// state is malformed, or with [`Error::Overflow`], when its buffered data doesn't fit.
+    pub fn resume(&mut self, mut state: &[u8]) -> Result<(), Error> {
+        let header = take(&mut state, 4)?;
+        if header[0] != STATE_FORMAT {
+            return Err(Error::InvalidState);
+        }
+        let value = take_usize(&mut state)?;
+        let processed_bytes = take_usize(&mut state)?;
+        let trailer = take_usize(&mut state)?;
+        let scanned = take_usize(&mut state)?;
+        let len = take_usize(&mut state)?;
+        let data = take(&mut state, len)?;
+        if !state.is_empty() || trailer > len {
+            return Err(Error::InvalidState);
+        }
+
+        let flags = header[3];
+        self.state = match (header[1], header[2]) {
+            (0, _) => State::Header,
+            // more than the announced length can't have been received
+            (1, _) if processed_bytes > value => return Err(Error::InvalidState),
+            (1, _) => State::Payload(value),
+            (2, 0) => State::Chunked(Chunk::Size {
+                size: value,
+                digits: flags & 1 != 0,
+                ext: flags & 2 != 0,
+            }),
+            (2, 1) => State::Chunked(Chunk::Data(value)),
+            (2, 2) => State::Chunked(Chunk::DataEnd),
+            (2, 3) => State::Chunked(Chunk::Trailer {
+                empty_line: flags & 1 != 0,
+            }),
+            (2, 4) => State::Chunked(Chunk::Done),
+            (3, _) => State::Complete,
+            (4, _) => State::UnlimitedPayload,
+            (5, _) => State::Upgraded,
+            _ => return Err(Error::InvalidState),
+        };
+
+        self.release_grant();
+        self.buffer.clear();
+        self.buffer
+            .extend_from_slice(data)
+            .map_err(|_| Error::Overflow)?;
+        self.processed_bytes = processed_bytes;
+        self.close = flags & 0x80 != 0;
+        self.trailer = trailer;
+        self.scanned = scanned.min(len);
+
+        Ok(())
+    }
+
+    /// Get a writable part of the buffer, of up to `max` bytes, for receiving data directly
+    /// into it.
+    ///
+    /// See [`Request::recv_grant`](crate::Request::recv_grant).
+    pub fn recv_grant(&mut self, max: usize) -> &mut [u8] {
+        let max = *self.grant.get_or_insert(max);
+        self.buffer.write_grant(max)
+    }
+
+    /// Process `len` bytes, which were received into the part of the buffer returned by
+    /// [`ResponseParser::recv_grant`].
+    pub fn commit(&mut self, len: usize) {
+        self.commit_observed(len, &mut ())
+    }
+
+    pub(crate) fn push_data_observed<O>(&mut self, data: &[u8], observer: &mut O)
+    where
+        O: Interceptor,
+    {
+        self.release_grant();
+        observer.observe_inbound(data);
+        let accepted = self.push(Ok(Some(data)), observer);
+        if accepted < data.len() && self.error().is_none() {
+            log::warn!("Dropping {} bytes", data.len() - accepted);
+        }
+    }
+
+    pub(crate) fn try_push_data_observed<O>(
+        &mut self,
+        data: &[u8],
+        observer: &mut O,
+    ) -> Result<usize, Error>
+    where
+        O: Interceptor,
+    {
+        if let State::Failed(err) = self.state {
+            return Err(err);
+        }
+
+        self.release_grant();
+        self.backpressure = true;
+        let accepted = self.push(Ok(Some(data)), observer);
+        self.backpressure = false;
+        observer.observe_inbound(&data[..accepted]);
+
+        match self.state {
+            State::Failed(err) => Err(err),
+            _ => Ok(accepted),
+        }
+    }
+
+    /// Process a response which was received already, before the request was sent.
+    pub(crate) fn push_retained<O>(&mut self, observer: &mut O)
+    where
+        O: Interceptor,
+    {
+        if matches!(self.state, State::Header) && !self.buffer.is_empty() {
+            self.push(Ok(Some(&[])), observer);
+        }
+    }
+
+    pub(crate) fn push_close_observed<O>(&mut self, observer: &mut O)
+    where
+        O: Interceptor,
+    {
+        self.release_grant();
+        observer.observe_event(ConnectionEvent::Closed);
+        self.push(Ok(None), observer);
+    }
+
+    /// Keep data received after the complete response, for the next one.
+    pub(crate) fn retain<O>(&mut self, data: &[u8], observer: &mut O) -> Result<(), Error>
+    where
+        O: Interceptor,
+    {
+        self.release_grant();
+        observer.observe_inbound(data);
+        self.buffer
+            .extend_from_slice(data)
+            .map_err(|_| Error::Overflow)
+    }
+
+    pub(crate) fn redeliver_observed<O>(&mut self, observer: &mut O)
+    where
+        O: Interceptor,
+    {
+        if self.pending_payload() > 0 {
+            self.push(Ok(Some(&[])), observer);
+        }
+    }
+
+    pub(crate) fn commit_observed<O>(&mut self, len: usize, observer: &mut O)
+    where
+        O: Interceptor,
+    {
+        let len = match self.grant.take() {
+            Some(max) => len.min(max),
+            None => {
+                log::warn!("Committing {} bytes, without a grant", len);
+                return;
+            }
+        };
+        if len == 0 {
+            return;
+        }
+
+        if let State::Header = self.state {
+            // the header is parsed from the buffer, received data is already in place
+            observer.observe_inbound(&self.buffer.write_grant(len)[..len]);
+            self.buffer.commit_write(len);
+            self.push(Ok(Some(&[])), observer);
+            return;
+        }
+
+        let mut buffer = core::mem::take(&mut self.buffer);
+        // restore the pending data, received data is pushed from the old buffer
+        let (first, second) = buffer.as_slices();
+        self.buffer.extend_from_slice(first).ok();
+        self.buffer.extend_from_slice(second).ok();
+        let received = &buffer.write_grant(len)[..len];
+        self.push_data_observed(received, observer);
+    }
+
+    /// Drop an outstanding grant, the free space of the buffer is handed out again.
+    pub(crate) fn release_grant(&mut self) {
+        self.grant = None;
+    }
+
+    /// Fail a request, which wasn't sent at all.
+    ///
+    /// As the inbound stream is unaffected, the buffered data is kept, and the connection doesn't
+    /// get poisoned.
+    pub(crate) fn reject(&mut self, err: Error) {
+        log::info!("Request not sent: {:?}", err);
+        self.state = State::Failed(err);
+        self.handler.more_payload(Err(err));
+    }
+
+    pub(crate) fn fail<O>(&mut self, err: Error, observer: &mut O)
+    where
+        O: Interceptor,
+    {
+        log::info!("Request failed: {:?}", err);
+        self.state = State::Failed(err);
+        // skip everything until the connection is reset
+        self.buffer.clear();
+        observer.observe_event(ConnectionEvent::Failed(err));
+        self.handler.more_payload(Err(err));
+    }
+
+    fn complete_response<O>(&mut self, observer: &mut O)
+    where
+        O: Interceptor,
+    {
+        // mark as complete
+        self.state = State::Complete;
+        observer.observe_event(ConnectionEvent::ResponseComplete);
+        // notify about complete
+        self.handler.more_payload(Ok(None));
+    }
+
+    /// Process the data, returns the number of bytes which were accepted.
+    ///
+    /// Fails the request, when the handler failed processing the response.
+    fn push<O>(&mut self, data: Result<Option<&[u8]>, Error>, observer: &mut O) -> usize
+    where
+        O: Interceptor,
+    {
+        let accepted = self.dispatch(data, observer);
+        if let Some(err) = self.handler.failed() {
+            match self.state {
+                State::Failed(_) => {}
+                // the response was read completely, the connection is still in sync
+                State::Complete => self.reject(err),
+                _ => self.fail(err, observer),
+            }
+        }
+        accepted
+    }
+
+    /// Process the data according to the state, returns the number of bytes which were
+    /// accepted.
+    fn dispatch<O>(&mut self, data: Result<Option<&[u8]>, Error>, observer: &mut O) -> usize
+    where
+        O: Interceptor,
+    {
+        debug!("Pushing data: {:?}", data.map(|o| o.map(|b| from_utf8(b))),);
+        match self.state {
+            State::Header => self.push_header(data, observer),
+            State::Payload(size) => self.push_sized_payload(size, data, observer),
+            State::Chunked(chunk) => self.push_chunked_payload(chunk, data, observer),
+            State::UnlimitedPayload => self.push_payload(data, observer),
+            State::Complete => self.push_complete_payload(data),
+            State::Upgraded => self.push_upgraded(data),
+            State::Failed(_) => 0,
+        }
+    }
+
+    fn push_header<O>(&mut self, data: Result<Option<&[u8]>, Error>, observer: &mut O) -> usize
+    where
+        O: Interceptor,
+    {
+        debug!("Current data: {:?}", from_utf8(self.buffer.as_slices().0));
+
+        match data {
+            Ok(Some(mut data)) => {
+                let mut skipped = 0;
+                if self.lenient && self.buffer.is_empty() {
+                    // don't waste buffer space on garbage
+                    skipped = find_status_line(data);
+                    data = &data[skipped..];
+                }
+
+                // fast path: when the complete header was pushed at once, it is parsed
+                // directly from the pushed data, without copying it to the buffer
+                let direct = self.buffer.is_empty() && contains_header_end(data);
+
+                // only buffer what fits, any payload following the header will be taken
+                // directly from the pushed data
+                let mut buffered = 0;
+                if !direct {
+                    let free = self.buffer.capacity() - self.buffer.len();
+                    buffered = data.len().min(free);
+                    self.buffer.extend_from_slice(&data[..buffered]).ok();
+
+                    if self.lenient {
+                        let skip = find_status_line(self.buffer.make_contiguous());
+                        if skip > 0 {
+                            debug!("Skipping {} bytes before the status line", skip);
+                            self.buffer.consume(skip);
+                            self.scanned = self.scanned.saturating_sub(skip);
+                        }
+                    }
+
+                    // once the status line is validated, only parse again when the header is
+                    // complete, or can't grow any further, scanning just the new data for its end
+                    let header = &self.buffer.make_contiguous()[self.scanned.saturating_sub(2)..];
+                    if self.scanned > 0
+                        && !contains_header_end(header)
+                        && self.buffer.len() < self.buffer.capacity()
+                    {
+                        self.scanned = self.buffer.len();
+                        match self.max_header_size {
+                            Some(max) if self.scanned > max => {
+                                log::info!("Header exceeds {} bytes", max);
+                                self.fail(Error::HeaderTooLarge, observer);
+                            }
+                            _ => {}
+                        }
+                        return skipped + buffered;
+                    }
+                }
+                let source: &[u8] = if direct {
+                    data
+                } else {
+                    self.buffer.as_slices().0
+                };
+
+                let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+                let mut response = httparse::Response::new(&mut headers[..self.max_headers]);
+
+                let result = if self.lenient {
+                    httparse::ParserConfig::default()
+                        .allow_spaces_after_header_name_in_responses(true)
+                        .allow_multiple_spaces_in_response_status_delimiters(true)
+                        .allow_space_before_first_header_name(true)
+                        .parse_response(&mut response, source)
+                } else {
+                    response.parse(source)
+                };
+
+                let size = match result {
+                    Ok(Status::Complete(len)) => len,
+                    _ => source.len(),
+                };
+                match self.max_header_size {
+                    Some(max) if size > max => {
+                        log::info!("Header exceeds {} bytes", max);
+                        self.fail(Error::HeaderTooLarge, observer);
+                        return 0;
+                    }
+                    _ => {}
+                }
+
+                match result {
+                    Ok(Status::Complete(len)) => {
+                        debug!("Completed({})", len);
+
+                        if matches!(response.code, Some(100..=199)) && response.code != Some(101) {
+                            // an interim response (RFC 9110, section 15.2), drop it and
+                            // continue with the final one
+                            debug!("Skipping interim response: {:?}", response.code);
+                            let taken = if direct {
+                                len
+                            } else {
+                                self.buffer.consume(len);
+                                buffered
+                            };
+                            self.scanned = 0;
+                            return skipped + taken + self.push_buffered(&data[taken..], observer);
+                        }
+
+                        let content_size = response
+                            .headers
+                            .iter()
+                            .find(|e| CONTENT_LENGTH.matches(e.name));
+                        let transfer_encoding = response
+                            .headers
+                            .iter()
+                            .rev()
+                            .find(|e| TRANSFER_ENCODING.matches(e.name));
+                        let chunked = matches!(transfer_encoding, Some(e) if is_chunked(e.value));
+                        let mut connection = response
+                            .headers
+                            .iter()
+                            .filter(|e| CONNECTION.matches(e.name));
+                        self.close = if response.version == Some(0) {
+                            !connection.any(|e| has_option(e.value, "keep-alive"))
+                        } else {
+                            connection.any(|e| has_option(e.value, "close"))
+                        };
+
+                        // eval next state
+                        self.state = match content_size {
+                            _ if response.code == Some(101) => State::Upgraded,
+                            // a tunnel has no payload, even when announcing one
+                            _ if self.tunnel && matches!(response.code, Some(200..=299)) => {
+                                State::Upgraded
+                            }
+                            // these end with the header, whatever it announces (RFC 9112,
+                            // section 6.3), so the connection stays usable
+                            _ if self.head || matches!(response.code, Some(204 | 304)) => {
+                                State::Payload(0)
+                            }
+                            // the transfer encoding takes precedence over the length
+                            _ if chunked => State::Chunked(Chunk::Size {
+                                size: 0,
+                                digits: false,
+                                ext: false,
+                            }),
+                            Some(header) => match parse_content_length(header.value) {
+                                Some(size) => State::Payload(size),
+                                None => {
+                                    log::info!(
+                                        "Invalid Content-Length: {:?}",
+                                        from_utf8(header.value)
+                                    );
+                                    self.fail(httparse::Error::HeaderValue.into(), observer);
+                                    return 0;
+                                }
+                            },
+                            None => State::UnlimitedPayload,
+                        };
+
+                        if let State::UnlimitedPayload = self.state {
+                            // the payload ends by closing the connection
+                            self.close = true;
+                        }
+
+                        // log::debug!("Headers: {:?}", response.headers);
+                        debug!("Continue with: {:?}", self.state);
+
+                        // handle response
+                        let reason = if self.capture_reason && !cfg!(feature = "tiny") {
+                            response.reason.unwrap_or_default()
+                        } else {
+                            ""
+                        };
+                        let response = Response {
+                            version: response.version.unwrap_or_default(),
+                            code: response.code.unwrap_or_default(),
+                            reason,
+                            headers: ResponseHeaders::new(response.headers),
+                        };
+                        observer.on_response(&response);
+                        self.handler.response(response);
+
+                        // drop the header from the buffer, what remains is the start of the
+                        // payload, followed by the data which didn't fit into the buffer
+
+                        let taken = if direct {
+                            len
+                        } else {
+                            self.buffer.consume(len);
+                            buffered
+                        };
+                        let rem_data = &data[taken..];
+
+                        debug!(
+                            "Len = {}, dLen = {}, bLen = {}",
+                            len,
+                            buffered,
+                            self.buffer.len()
+                        );
+
+                        if let State::Upgraded = self.state {
+                            // there is no payload, everything else belongs to the new protocol
+                            observer.observe_event(ConnectionEvent::ResponseComplete);
+                            self.handler.more_payload(Ok(None));
+                        }
+
+                        if let State::Payload(size) = self.state {
+                            if !self.accept_body(size, observer) {
+                                return 0;
+                            }
+                            // count the received bytes from here on
+                            self.processed_bytes = 0;
+                        }
+
+                        // push on remaining data
+
+                        skipped + taken + self.push_buffered(rem_data, observer)
+                    }
+                    Ok(Status::Partial) if direct => {
+                        // not complete after all, continue by buffering what fits
+                        let buffered = data.len().min(self.buffer.capacity());
+                        self.buffer.extend_from_slice(&data[..buffered]).ok();
+                        skipped + buffered
+                    }
+                    Ok(Status::Partial) if self.buffer.len() == self.buffer.capacity() => {
+                        log::info!(
+                            "Header exceeds the buffer of {} bytes",
+                            self.buffer.capacity()
+                        );
+                        self.fail(Error::HeaderTooLarge, observer);
+                        0
+                    }
+                    Ok(Status::Partial) => {
+                        if response.reason.is_some() {
+                            // the status line is complete
+                            self.scanned = source.len();
+                        }
+                        skipped + buffered
+                    }
+                    Err(e) => {
+                        self.fail(e.into(), observer);
+                        0
+                    }
+                }
+            }
+            Ok(None) => {
+                log::info!("Connection closed before the end of the header");
+                self.fail(Error::IncompleteBody, observer);
+                0
+            }
+            Err(err) => {
+                self.fail(err, observer);
+                0
+            }
+        }
+    }
+
+    /// Push the buffered data to the payload processing, followed by more data.
+    ///
+    /// Returns the number of bytes of the data, which were accepted.
+    fn push_buffered<O>(&mut self, data: &[u8], observer: &mut O) -> usize
+    where
+        O: Interceptor,
+    {
+        if !self.buffer.is_empty() {
+            debug!(
+                "Push {} buffered bytes to payload processing",
+                self.buffer.len()
+            );
+            let buffer = core::mem::take(&mut self.buffer);
+            let (first, second) = buffer.as_slices();
+            self.push(Ok(Some(first)), observer);
+            if !second.is_empty() {
+                self.push(Ok(Some(second)), observer);
+            }
+        }
+        self.push(Ok(Some(data)), observer)
+    }
+
+    fn push_payload<O>(&mut self, data: Result<Option<&[u8]>, Error>, observer: &mut O) -> usize
+    where
+        O: Interceptor,
+    {
+        debug!("More data: {:?}", data);
+
+        match data {
+            Ok(Some(data)) => {
+                if !self.accept_body(data.len(), observer) {
+                    return 0;
+                }
+                let accepted = self.deliver(data);
+                // the rest will be pushed again
+                self.processed_bytes -= data.len() - accepted;
+                accepted
+            }
+            Ok(None) => {
+                if !self.flush_pending() {
+                    log::warn!(
+                        "Dropping {} bytes of undelivered payload",
+                        self.buffer.len()
+                    );
+                }
+                // the payload is delimited by closing the connection
+                self.complete_response(observer);
+                0
+            }
+            Err(err) => {
+                self.fail(err, observer);
+                0
+            }
+        }
+    }
+
+    /// Account for more payload, failing if it exceeds the maximum size.
+    fn accept_body<O>(&mut self, len: usize, observer: &mut O) -> bool
+    where
+        O: Interceptor,
+    {
+        self.processed_bytes = self.processed_bytes.saturating_add(len);
+        match self.max_body_size {
+            Some(max) if self.processed_bytes > max => {
+                log::info!("Payload exceeds {} bytes", max);
+                self.fail(Error::BodyTooLarge, observer);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Deliver payload to the handler, buffering what it doesn't consume.
+    ///
+    /// Returns the number of bytes which were accepted.
+    fn deliver(&mut self, data: &[u8]) -> usize {
+        // keep the order, deliver pending data first
+        let consumed = if self.flush_pending() && !data.is_empty() {
+            self.handler.consume_payload(data)
+        } else {
+            0
+        };
+
+        consumed + self.buffer_data(&data[consumed..])
+    }
+
+    /// Buffer the data, returns the number of bytes which were accepted.
+    ///
+    /// When the data doesn't fit, it is dropped. Unless applying backpressure, then only what
+    /// fits is accepted, leaving the rest to the caller.
+    fn buffer_data(&mut self, data: &[u8]) -> usize {
+        if self.buffer.extend_from_slice(data).is_ok() {
+            return data.len();
+        }
+
+        if self.backpressure {
+            let free = self.buffer.capacity() - self.buffer.len();
+            // fits, can't fail
+            self.buffer.extend_from_slice(&data[..free]).ok();
+            free
+        } else {
+            log::warn!("Buffer overflow, dropping {} bytes", data.len());
+            data.len()
+        }
+    }
+
+    /// Pass on the buffered trailer to the handler, and drop it from the buffer.
+    fn deliver_trailer(&mut self) {
+        let trailer = &self.buffer.make_contiguous()[self.trailer..];
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        match httparse::parse_headers(trailer, &mut headers[..self.max_headers]) {
+            Ok(Status::Complete((_, headers))) => {
+                if !headers.is_empty() {
+                    self.handler.trailers(ResponseHeaders::new(headers));
+                }
+            }
+            _ => log::info!("Skipping invalid trailer"),
+        }
+        self.buffer.truncate(self.trailer);
+    }
+
+    /// Try to deliver pending payload to the handler, returns `true` if nothing is pending.
+    fn flush_pending(&mut self) -> bool {
+        if self.buffer.is_empty() {
+            return true;
+        }
+
+        let (first, second) = self.buffer.as_slices();
+        let mut consumed = self.handler.consume_payload(first);
+        if consumed == first.len() && !second.is_empty() {
+            consumed += self.handler.consume_payload(second);
+        }
+        self.buffer.consume(consumed);
+
+        self.buffer.is_empty()
+    }
+
+    fn push_complete_payload(&mut self, data: Result<Option<&[u8]>, Error>) -> usize {
+        debug!("More data (overflow): {:?}", data);
+        match data {
+            Ok(Some(data)) => self.buffer_data(data),
+            _ => 0,
+        }
+    }
+
+    fn push_upgraded(&mut self, data: Result<Option<&[u8]>, Error>) -> usize {
+        match data {
+            Ok(Some(data)) => self.buffer_data(data),
+            _ => 0,
+        }
+    }
+
+    fn push_sized_payload<O>(
+        &mut self,
+        expected_bytes: usize,
+        data: Result<Option<&[u8]>, Error>,
+        observer: &mut O,
+    ) -> usize
+    where
+        O: Interceptor,
+    {
+        debug!("More data (sized): {:?}", data);
+
+        match data {
+            Ok(Some(data)) => {
+                let rem = expected_bytes - self.processed_bytes;
+                let len = data.len().min(rem);
+                let accepted = self.deliver(&data[0..len]);
+                self.processed_bytes += accepted;
+
+                if self.processed_bytes == expected_bytes && self.buffer.is_empty() {
+                    self.complete_response(observer);
+                    // data following the body
+                    accepted + self.push(Ok(Some(&data[len..])), observer)
+                } else {
+                    accepted
+                }
+            }
+            Ok(None) if self.processed_bytes < expected_bytes => {
+                log::info!(
+                    "Connection closed after {} of {} bytes",
+                    self.processed_bytes,
+                    expected_bytes
+                );
+                self.fail(Error::IncompleteBody, observer);
+                0
+            }
+            Ok(None) => {
+                // all bytes were received, the handler didn't consume them yet
+                if !self.flush_pending() {
+                    log::warn!(
+                        "Dropping {} bytes of undelivered payload",
+                        self.buffer.len()
+                    );
+                }
+                self.complete_response(observer);
+                0
+            }
+            Err(err) => {
+                self.fail(err, observer);
+                0
+            }
+        }
+    }
+
+    fn push_chunked_payload<O>(
+        &mut self,
+        mut chunk: Chunk,
+        data: Result<Option<&[u8]>, Error>,
+        observer: &mut O,
+    ) -> usize
+    where
+        O: Interceptor,
+    {
+        debug!("More data (chunked): {:?}", data);
+
+        let mut data = match data {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                if !matches!(chunk, Chunk::Done) {
+                    log::info!("Connection closed before the last chunk");
+                    self.fail(Error::IncompleteBody, observer);
+                    return 0;
+                }
+                if !self.flush_pending() {
+                    log::warn!(
+                        "Dropping {} bytes of undelivered payload",
+                        self.buffer.len()
+                    );
+                }
+                self.complete_response(observer);
+                return 0;
+            }
+            Err(err) => {
+                self.fail(err, observer);
+                return 0;
+            }
+        };
+        let total = data.len();
+
+        while !data.is_empty() && !matches!(chunk, Chunk::Done) {
+            let mut len = 1;
+            let mut stalled = false;
+            if let Chunk::Trailer { .. } = chunk {
+                // buffer the trailer after the pending payload, it is parsed once complete
+                self.buffer.extend_from_slice(&data[..1]).ok();
+            }
+            chunk = match (chunk, data[0]) {
+                (Chunk::Data(remaining), _) => {
+                    let wanted = data.len().min(remaining);
+                    len = self.deliver(&data[..wanted]);
+                    stalled = len < wanted;
+                    match remaining - len {
+                        0 => Chunk::DataEnd,
+                        remaining => Chunk::Data(remaining),
+                    }
+                }
+                (Chunk::Size { digits: false, .. }, b'\n') => {
+                    self.fail(Error::Chunk, observer);
+                    return 0;
+                }
+                (Chunk::Size { size: 0, .. }, b'\n') => {
+                    self.trailer = self.buffer.len();
+                    Chunk::Trailer { empty_line: true }
+                }
+                (Chunk::Size { size, .. }, b'\n') => {
+                    if !self.accept_body(size, observer) {
+                        return 0;
+                    }
+                    Chunk::Data(size)
+                }
+                (Chunk::Size { ext: true, .. }, _) | (Chunk::Size { .. }, b'\r') => chunk,
+                (Chunk::Size { size, digits, .. }, b';' | b' ' | b'\t') => Chunk::Size {
+                    size,
+                    digits,
+                    ext: true,
+                },
+                (Chunk::Size { size, .. }, b) => {
+                    let size = (b as char)
+                        .to_digit(16)
+                        .and_then(|digit| size.checked_mul(16)?.checked_add(digit as usize));
+                    match size {
+                        Some(size) => Chunk::Size {
+                            size,
+                            digits: true,
+                            ext: false,
+                        },
+                        None => {
+                            self.fail(Error::Chunk, observer);
+                            return 0;
+                        }
+                    }
+                }
+                (Chunk::DataEnd, b'\r') => Chunk::DataEnd,
+                (Chunk::DataEnd, b'\n') => Chunk::Size {
+                    size: 0,
+                    digits: false,
+                    ext: false,
+                },
+                (Chunk::DataEnd, _) => {
+                    self.fail(Error::Chunk, observer);
+                    return 0;
+                }
+                (Chunk::Trailer { empty_line: true }, b'\n') => {
+                    self.deliver_trailer();
+                    Chunk::Done
+                }
+                (Chunk::Trailer { .. }, b'\n') => Chunk::Trailer { empty_line: true },
+                (Chunk::Trailer { empty_line }, b'\r') => Chunk::Trailer { empty_line },
+                (Chunk::Trailer { .. }, _) => Chunk::Trailer { empty_line: false },
+                (Chunk::Done, _) => Chunk::Done,
+            };
+            data = &data[len..];
+            if stalled {
+                break;
+            }
+        }
+        self.state = State::Chunked(chunk);
+        let accepted = total - data.len();
+
+        if matches!(chunk, Chunk::Done) {
+            if !self.flush_pending() {
+                // wait for the handler to consume the pending payload
+                return accepted;
+            }
+            self.complete_response(observer);
+            // data following the body
+            return accepted + self.push(Ok(Some(data)), observer);
+        }
+        accepted
+    }
+}
+
+impl<N, R> Inbound for ResponseParser<N, R>
+where
+    N: ArrayLength<u8>,
+    R: ResponseHandler,
+{
+    fn push_data(&mut self, data: &[u8]) {
+        ResponseParser::push_data(self, data)
+    }
+
+    fn push_close(&mut self) {
+        ResponseParser::push_close(self)
+    }
+
+    fn redeliver(&mut self) {
+        ResponseParser::redeliver(self)
+    }
+
+    fn pending_payload(&self) -> usize {
+        ResponseParser::pending_payload(self)
+    }
+
+    fn is_complete(&self) -> bool {
+        ResponseParser::is_complete(self)
+    }
+
+    fn error(&self) -> Option<Error> {
+        ResponseParser::error(self)
+    }
+}
+
+/// Writes a suspended state.
+struct StateWriter<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl StateWriter<'_> {
+    fn put(&mut self, data: &[u8]) -> Result<(), Error> {
+        let end = self.len + data.len();
+        if end > self.out.len() {
+            return Err(Error::Overflow);
+        }
+        self.out[self.len..end].copy_from_slice(data);
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Take `len` bytes from the start of a suspended state.
+fn take<'a>(state: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if state.len() < len {
+        return Err(Error::InvalidState);
+    }
+    let (head, tail) = state.split_at(len);
+    *state = tail;
+    Ok(head)
+}
+
+/// Take a number from the start of a suspended state.
+fn take_usize(state: &mut &[u8]) -> Result<usize, Error> {
+    let mut value = [0u8; 8];
+    value.copy_from_slice(take(state, 8)?);
+    usize::try_from(u64::from_le_bytes(value)).map_err(|_| Error::InvalidState)
+}
+
+/// Check if the data contains the empty line, which ends the header.
+fn contains_header_end(data: &[u8]) -> bool {
+    data.windows(2)
+        .enumerate()
+        .any(|(i, w)| w == b"\n\n" || (w == b"\n\r" && data.get(i + 2) == Some(&b'\n')))
+}
+
+/// Parse the value of a `Content-Length` header, which must only consist of digits.
+fn parse_content_length(value: &[u8]) -> Option<usize> {
+    let value = from_utf8(value)
+        .ok()?
+        .trim_matches(|c| c == ' ' || c == '\t');
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Check if the value of a `Transfer-Encoding` header ends with the "chunked" encoding.
+fn is_chunked(value: &[u8]) -> bool {
+    let coding = from_utf8(value)
+        .ok()
+        .and_then(|value| value.rsplit(',').next());
+    matches!(coding, Some(coding) if coding.trim().eq_ignore_ascii_case("chunked"))
+}
+
+/// Check if the value of a `Connection` header contains the option.
+fn has_option(value: &[u8], option: &str) -> bool {
+    matches!(from_utf8(value), Ok(value) if value
+        .split(',')
+        .any(|token| token.trim().eq_ignore_ascii_case(option)))
+}
+
+/// Find the start of the status line, or a possible start at the end of the data.
+///
+/// Returns the length of the data if there is none.
+fn find_status_line(data: &[u8]) -> usize {
+    const PREFIX: &[u8] = b"HTTP/";
+
+    (0..data.len())
+        .find(|&i| {
+            let n = (data.len() - i).min(PREFIX.len());
+            data[i..i + n] == PREFIX[..n] && (i == 0 || !matches!(data[i - 1], b' ' | b'\t'))
+        })
+        .unwrap_or(data.len())
+}
@@ -0,0 +1,184 @@
+//! Long polling, for receiving events from the server.
+//!
+//! Devices which only speak HTTP can't be contacted by the server. Instead, they issue a `GET`
+//! request, which the server holds open until an event, like a command for the device, is
+//! available, or a timeout expires. Afterwards, the request gets issued again.
+//!
+//! ~~~no_run
+//! use heapless::consts::*;
+//!
+//! use drogue_network::addr::HostSocketAddr;
+//!
+//! use drogue_http_client::client::HttpClient;
+//! use drogue_http_client::poll::LongPoll;
+//! use drogue_http_client::*;
+//!
+//! # use drogue_http_client::mock;
+//! # let (network, _) = mock::mock_connection();
+//! let remote = HostSocketAddr::from("192.168.1.1", 8080).unwrap();
+//! let mut client = HttpClient::new(&network, remote, HttpConnection::<U1024>::new());
+//!
+//! LongPoll::new("/commands")
+//!     .timeout("ct", 30)
+//!     .run(&mut client, BufferResponseHandler::<U512>::new, |command| {
+//!         println!("Command: {:?}", command.payload());
+//!         // continue polling
+//!         true
+//!     })
+//!     .unwrap();
+//! ~~~
+
+use crate::client::HttpClient;
+use crate::headers::ResponseHeaders;
+use crate::{Error, Interceptor, PipeError, Response, ResponseHandler};
+use drogue_network::tcp::TcpStack;
+use heapless::ArrayLength;
+
+/// Repeatedly issues a `GET` request, which the server answers once an event is available.
+///
+/// Responses with the status `204 No Content`, `408 Request Timeout`, or `504 Gateway Timeout`
+/// end a poll without an event. As do failures of the transport, and read timeouts, which
+/// are tolerated up to a number of times in a row. All other successful responses are events,
+/// while any other status code fails with [`Error::Status`].
+pub struct LongPoll<'p> {
+    path: &'p str,
+    timeout: Option<(&'p str, u32)>,
+    max_failures: usize,
+    // consecutive transport failures
+    failures: usize,
+}
+
+impl<'p> LongPoll<'p> {
+    /// Create a new instance, polling the path.
+    pub fn new(path: &'p str) -> Self {
+        LongPoll {
+            path,
+            timeout: None,
+            max_failures: 3,
+            failures: 0,
+        }
+    }
+
+    /// Set a query parameter, which tells the server how many seconds to wait for an event.
+    pub fn timeout(mut self, name: &'p str, seconds: u32) -> Self {
+        self.timeout = Some((name, seconds));
+        self
+    }
+
+    /// Set how many transport failures in a row end a poll without an event, defaults to 3.
+    ///
+    /// The failure after that is returned as error.
+    pub fn max_failures(mut self, max_failures: usize) -> Self {
+        self.max_failures = max_failures;
+        self
+    }
+
+    /// Issue a single poll request.
+    ///
+    /// The function creates the handler for the response. The handler is returned if an event
+    /// was received, and `None` if the poll ended without one.
+    pub fn poll<T, IN, I, R, F>(
+        &mut self,
+        client: &mut HttpClient<'_, T, IN, I>,
+        mut handler: F,
+    ) -> Result<Option<R>, PipeError<T::Error>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+        R: ResponseHandler,
+        F: FnMut() -> R,
+    {
+        let timeout = self.timeout;
+        let result = client.request("GET", self.path, |req, sink| {
+            let req = match timeout {
+                Some((name, seconds)) => req.query_param(name, seconds),
+                None => req,
+            };
+            req.handler(StatusHandler {
+                handler: handler(),
+                code: 0,
+            })
+            .execute(sink)
+        });
+
+        let status = match result {
+            Ok(status) => status,
+            Err(err @ PipeError::Transport(_)) | Err(err @ PipeError::Timeout)
+                if self.failures < self.max_failures =>
+            {
+                log::info!("Poll failed, retrying: {:?}", err);
+                self.failures += 1;
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+        self.failures = 0;
+
+        match status.code {
+            204 | 408 | 504 => Ok(None),
+            200..=299 => Ok(Some(status.handler)),
+            code => Err(Error::Status(code).into()),
+        }
+    }
+
+    /// Poll repeatedly, passing the handler of each event to the callback, until it returns
+    /// `false`.
+    pub fn run<T, IN, I, R, F, E>(
+        &mut self,
+        client: &mut HttpClient<'_, T, IN, I>,
+        mut handler: F,
+        mut on_event: E,
+    ) -> Result<(), PipeError<T::Error>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+        R: ResponseHandler,
+        F: FnMut() -> R,
+        E: FnMut(R) -> bool,
+    {
+        loop {
+            if let Some(event) = self.poll(client, &mut handler)? {
+                if !on_event(event) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Records the status code, passing everything on to the handler.
+pub(crate) struct StatusHandler<R>
+where
+    R: ResponseHandler,
+{
+    pub(crate) handler: R,
+    pub(crate) code: u16,
+}
+
+impl<R> ResponseHandler for StatusHandler<R>
+where
+    R: ResponseHandler,
+{
+    fn response(&mut self, response: Response) {
+        self.code = response.code;
+        self.handler.response(response);
+    }
+
+    fn more_payload(&mut self, payload: Result<Option<&[u8]>, Error>) {
+        self.handler.more_payload(payload);
+    }
+
+    fn consume_payload(&mut self, payload: &[u8]) -> usize {
+        self.handler.consume_payload(payload)
+    }
+
+    fn trailers(&mut self, trailers: ResponseHeaders) {
+        self.handler.trailers(trailers);
+    }
+
+    fn failed(&self) -> Option<Error> {
+        self.handler.failed()
+    }
+}
@@ -0,0 +1,166 @@
+use heapless::{ArrayLength, Vec};
+
+/// A circular buffer of `N` bytes, for inbound data.
+///
+/// Data is appended at the end and consumed from the start, without moving the remaining
+/// data. This allows to process data incrementally, and to keep data which was received after
+/// a response for the next one.
+pub struct RingBuffer<N>
+where
+    N: ArrayLength<u8>,
+{
+    // the storage, only grown to its capacity once data gets written
+    storage: Vec<u8, N>,
+    // the position of the first byte
+    head: usize,
+    // the number of buffered bytes
+    len: usize,
+}
+
+impl<N> Default for RingBuffer<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N> RingBuffer<N>
+where
+    N: ArrayLength<u8>,
+{
+    /// Create a new, empty, instance.
+    pub fn new() -> Self {
+        RingBuffer {
+            storage: Vec::new(),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// The number of buffered bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if no data is buffered.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The maximum number of bytes the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Discard all buffered data.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Get the buffered data, which may wrap around the end of the storage.
+    ///
+    /// The second slice is empty, unless the data wraps around.
+    pub fn as_slices(&self) -> (&[u8], &[u8]) {
+        let end = self.head + self.len;
+        if end <= self.storage.len() {
+            (&self.storage[self.head..end], &[])
+        } else {
+            (
+                &self.storage[self.head..],
+                &self.storage[..end - self.storage.len()],
+            )
+        }
+    }
+
+    /// Rearrange the buffered data, so that it doesn't wrap around, and return it.
+    pub fn make_contiguous(&mut self) -> &mut [u8] {
+        if self.head + self.len > self.storage.len() {
+            self.storage.rotate_left(self.head);
+            self.head = 0;
+        }
+        &mut self.storage[self.head..self.head + self.len]
+    }
+
+    /// Discard up to `len` bytes from the start of the buffer.
+    pub fn consume(&mut self, len: usize) {
+        let len = len.min(self.len);
+        self.len -= len;
+        self.head = match self.len {
+            // start over, to make the most of the contiguous free space
+            0 => 0,
+            _ => (self.head + len) % self.storage.len(),
+        };
+    }
+
+    /// Keep the first `len` bytes, discarding the rest.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+        if self.len == 0 {
+            self.head = 0;
+        }
+    }
+
+    /// Append the data, failing if it doesn't fit completely.
+    pub fn extend_from_slice(&mut self, data: &[u8]) -> Result<(), ()> {
+        if data.len() > self.capacity() - self.len {
+            return Err(());
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        self.grow();
+        let tail = (self.head + self.len) % self.storage.len();
+        let first = data.len().min(self.storage.len() - tail);
+        self.storage[tail..tail + first].copy_from_slice(&data[..first]);
+        self.storage[..data.len() - first].copy_from_slice(&data[first..]);
+        self.len += data.len();
+
+        Ok(())
+    }
+
+    /// Get the free space following the buffered data, of up to `max` bytes, for writing data
+    /// directly into it.
+    ///
+    /// The slice is empty if the buffer is full. Written data is added to the buffer using
+    /// [`RingBuffer::commit_write`].
+    pub fn write_grant(&mut self, max: usize) -> &mut [u8] {
+        if self.len == 0 {
+            self.head = 0;
+        }
+        self.grow();
+
+        let capacity = self.storage.len();
+        let tail = self.head + self.len;
+        let (start, end) = if tail < capacity {
+            (tail, capacity)
+        } else {
+            (tail - capacity, self.head)
+        };
+        let end = start + max.min(end - start);
+        &mut self.storage[start..end]
+    }
+
+    /// Append `len` bytes, which were written into the slice returned by
+    /// [`RingBuffer::write_grant`].
+    pub fn commit_write(&mut self, len: usize) {
+        self.len += len.min(self.capacity() - self.len);
+    }
+
+    /// Convert into a vector, holding the buffered data.
+    pub fn into_vec(mut self) -> Vec<u8, N> {
+        // copy, as `truncate()` of heapless 0.5 trips the UB checks of recent Rust versions
+        Vec::from_slice(self.make_contiguous()).unwrap_or_default()
+    }
+
+    /// Grow the storage to its capacity, once it is needed.
+    fn grow(&mut self) {
+        if self.storage.len() < self.storage.capacity() {
+            // growing only, can't fail
+            self.storage.resize_default(self.storage.capacity()).ok();
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Periodic execution of a prepared request, e.g. for sending telemetry.
+//!
+//! A [`Schedule`] executes a [`PreparedRequest`] on the managed client in a fixed interval,
+//! tracked using a [`Clock`]. A random jitter spreads the requests of many devices, which were
+//! started at the same time. When a request fails, the next one is delayed, doubling the delay
+//! with each failure in a row, up to a maximum. So the application only has to provide the
+//! payload.
+//!
+//! ~~~no_run
+//! use core::fmt::Write;
+//! use heapless::consts::*;
+//!
+//! use drogue_network::addr::HostSocketAddr;
+//!
+//! use drogue_http_client::client::HttpClient;
+//! use drogue_http_client::schedule::Schedule;
+//! use drogue_http_client::*;
+//!
+//! # use drogue_http_client::mock;
+//! # let (network, _) = mock::mock_connection();
+//! # struct Ticks;
+//! # impl Clock for Ticks {
+//! #     fn now(&self) -> u64 { 0 }
+//! # }
+//! # fn read_temperature() -> f32 { 21.5 }
+//! # fn sleep(_: u64) {}
+//! let remote = HostSocketAddr::from("192.168.1.1", 8080).unwrap();
+//! let mut client = HttpClient::new(&network, remote, HttpConnection::<U1024>::new());
+//!
+//! let prepared = client
+//!     .prepare::<U128, _>("POST", "/telemetry", |req| req.json())
+//!     .unwrap();
+//! // every 10 seconds, with up to a second of jitter
+//! let mut schedule = Schedule::new(Ticks, prepared, 10_000).jitter(1_000);
+//!
+//! loop {
+//!     if let Some(result) = schedule.poll(&mut client, |w| {
+//!         write!(w, "{{\"temp\":{}}}", read_temperature())
+//!     }) {
+//!         println!("Sent telemetry: {:?}", result);
+//!     }
+//!     sleep(schedule.remaining());
+//! }
+//! ~~~
+
+use crate::client::HttpClient;
+use crate::poll::StatusHandler;
+use crate::{
+    Clock, Error, Interceptor, PipeError, PreparedRequest, ResponseHandler,
+    StatusOnlyResponseHandler,
+};
+use core::fmt::Write;
+use drogue_network::tcp::TcpStack;
+use heapless::{consts, ArrayLength};
+
+/// Executes a prepared request in an interval.
+///
+/// All times are in milliseconds of the clock. The first request is due right away. After a
+/// successful request, which is one with a `2xx` status code, the next one is due one interval
+/// after the previous one was. After a failed request, the next one is due after a backoff
+/// delay, starting at the interval, and doubling with each failure in a row, up to the
+/// maximum. A random jitter, up to the configured amount, is added to each due time.
+pub struct Schedule<C, NP = consts::U256>
+where
+    C: Clock,
+    NP: ArrayLength<u8>,
+{
+    clock: C,
+    prepared: PreparedRequest<NP>,
+    interval: u64,
+    jitter: u64,
+    max_backoff: u64,
+    // failed requests in a row
+    failures: u32,
+    // the time the next request is due, without the jitter
+    due: u64,
+    // the jitter of the next request
+    delay: u64,
+    // state of the pseudo random generator, never zero
+    random: u32,
+}
+
+impl<C, NP> Schedule<C, NP>
+where
+    C: Clock,
+    NP: ArrayLength<u8>,
+{
+    /// Create a new instance, executing the request in the interval.
+    pub fn new(clock: C, prepared: PreparedRequest<NP>, interval: u64) -> Self {
+        let due = clock.now();
+        Schedule {
+            clock,
+            prepared,
+            interval,
+            jitter: 0,
+            max_backoff: 5 * 60 * 1000,
+            failures: 0,
+            due,
+            delay: 0,
+            random: 0x9E37_79B9,
+        }
+    }
+
+    /// Add a random jitter, up to this amount, to the time requests are due, defaults to none.
+    pub fn jitter(mut self, jitter: u64) -> Self {
+        self.jitter = jitter;
+        self.delay = self.next_jitter();
+        self
+    }
+
+    /// Set the maximum delay after failed requests, defaults to 5 minutes.
+    ///
+    /// The delay is never shorter than the interval.
+    pub fn max_backoff(mut self, max_backoff: u64) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Seed the generator of the jitter, e.g. with the serial number of the device, so that
+    /// devices don't use the same sequence.
+    pub fn seed(mut self, seed: u32) -> Self {
+        // the generator gets stuck at zero
+        self.random = seed.max(1);
+        self.delay = self.next_jitter();
+        self
+    }
+
+    /// The prepared request.
+    pub fn prepared(&self) -> &PreparedRequest<NP> {
+        &self.prepared
+    }
+
+    /// Access the clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// The number of failed requests in a row.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// The time the next request is due, including the jitter.
+    pub fn next(&self) -> u64 {
+        self.due.saturating_add(self.delay)
+    }
+
+    /// The time until the next request is due, zero if it is due already.
+    pub fn remaining(&self) -> u64 {
+        self.next().saturating_sub(self.clock.now())
+    }
+
+    /// Execute the request, if it is due, formatting the payload using the function.
+    ///
+    /// Returns `None` if the request isn't due yet. Otherwise, the status code of a successful
+    /// response is returned, and responses with any other status code fail with
+    /// [`Error::Status`]. The function may be called multiple times for a single request, see
+    /// [`RequestBuilder::execute_with_fmt`](crate::RequestBuilder::execute_with_fmt).
+    pub fn poll<T, IN, I, F>(
+        &mut self,
+        client: &mut HttpClient<'_, T, IN, I>,
+        payload: F,
+    ) -> Option<Result<u16, PipeError<T::Error>>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+        F: Fn(&mut dyn Write) -> core::fmt::Result,
+    {
+        self.poll_with(client, StatusOnlyResponseHandler::new, payload)
+            .map(|result| result.map(|handler| handler.code()))
+    }
+
+    /// Execute the request, if it is due, using the handler created by the function.
+    ///
+    /// Like [`Schedule::poll`], but the handler is returned for successful responses.
+    pub fn poll_with<T, IN, I, R, H, F>(
+        &mut self,
+        client: &mut HttpClient<'_, T, IN, I>,
+        mut handler: H,
+        payload: F,
+    ) -> Option<Result<R, PipeError<T::Error>>>
+    where
+        T: TcpStack,
+        IN: ArrayLength<u8>,
+        I: Interceptor,
+        R: ResponseHandler,
+        H: FnMut() -> R,
+        F: Fn(&mut dyn Write) -> core::fmt::Result,
+    {
+        if self.remaining() > 0 {
+            return None;
+        }
+
+        let result = client.execute_prepared_fmt(
+            &self.prepared,
+            || StatusHandler {
+                handler: handler(),
+                code: 0,
+            },
+            payload,
+        );
+        let result = match result {
+            Ok(status) if (200..300).contains(&status.code) => Ok(status.handler),
+            Ok(status) => Err(Error::Status(status.code).into()),
+            Err(err) => Err(err),
+        };
+
+        let now = self.clock.now();
+        match result {
+            Ok(_) => {
+                self.failures = 0;
+                self.due = self.due.saturating_add(self.interval);
+                if self.due <= now {
+                    log::info!("Missed the schedule, continuing from now");
+                    self.due = now.saturating_add(self.interval);
+                }
+            }
+            Err(_) => {
+                self.failures = self.failures.saturating_add(1);
+                let backoff = self.backoff();
+                log::info!(
+                    "Scheduled request failed {} times in a row, retrying in {} ms",
+                    self.failures,
+                    backoff
+                );
+                self.due = now.saturating_add(backoff);
+            }
+        }
+        self.delay = self.next_jitter();
+
+        Some(result)
+    }
+
+    /// The delay after the current number of failures in a row.
+    fn backoff(&self) -> u64 {
+        let max = self.max_backoff.max(self.interval);
+        // the interval doubles with each failure, after the first one
+        let shift = self.failures.saturating_sub(1).min(63);
+        self.interval
+            .checked_mul(1 << shift)
+            .map_or(max, |backoff| backoff.min(max))
+    }
+
+    /// Get a random jitter, using a xorshift generator.
+    fn next_jitter(&mut self) -> u64 {
+        if self.jitter == 0 {
+            return 0;
+        }
+        let mut x = self.random;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random = x;
+        u64::from(x) % self.jitter.saturating_add(1)
+    }
+}
@@ -0,0 +1,328 @@
+//! A tiny HTTP/1.1 server, for exposing local endpoints of a device.
+//!
+//! A [`RequestParser`] receives a request, and can be fed by any [`Source`](crate::Source),
+//! like the transport of the client. A [`ResponseWriter`] then sends the response to a
+//! [`Sink`]. The request, including its payload, must fit into the buffer of the parser. The
+//! "chunked" transfer encoding isn't supported for request payloads.
+//!
+//! Accepting connections is up to the application, e.g. by letting a smoltcp socket listen.
+//!
+//! ~~~
+//! use heapless::{consts::*, Vec};
+//!
+//! use drogue_http_client::server::{RequestParser, ResponseWriter};
+//!
+//! let mut parser = RequestParser::<U512>::new();
+//! parser.push_data(b"GET /health HTTP/1.1\r\nHost: device\r\n\r\n");
+//!
+//! let mut sink = Vec::<u8, U256>::new();
+//! let request = parser.request().unwrap();
+//! match (request.method, request.path) {
+//!     ("GET", "/health") => ResponseWriter::new(&mut sink, 200, "OK")
+//!         .header("Content-Type", "application/json")
+//!         .send_fmt(|w| write!(w, "{{\"uptime\":{}}}", 42)),
+//!     _ => ResponseWriter::new(&mut sink, 404, "Not Found").send(b""),
+//! }
+//! .unwrap();
+//!
+//! assert_eq!(
+//!     sink,
+//!     b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 13\r\n\r\n{\"uptime\":42}"
+//!         .as_ref()
+//! );
+//! ~~~
+
+use crate::con::Counter;
+use crate::headers::{HeaderName, ResponseHeaders, CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING};
+use crate::parser::MAX_HEADERS;
+use crate::sink::SinkWrapper;
+use crate::{Error, Inbound, Sink};
+use core::fmt::{self, Write};
+use heapless::{ArrayLength, Vec};
+use httparse::Status;
+
+#[derive(Copy, Clone, Debug)]
+enum State {
+    Header,
+    /// Receiving the payload, with the length of the header, and the payload.
+    Payload(usize, usize),
+    Complete(usize, usize),
+    Failed(Error),
+}
+
+/// Receives a request, buffering it completely.
+///
+/// The request, header and payload, must fit into `N` bytes.
+pub struct RequestParser<N>
+where
+    N: ArrayLength<u8>,
+{
+    buffer: Vec<u8, N>,
+    state: State,
+}
+
+impl<N> Default for RequestParser<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N> RequestParser<N>
+where
+    N: ArrayLength<u8>,
+{
+    pub fn new() -> Self {
+        RequestParser {
+            buffer: Vec::new(),
+            state: State::Header,
+        }
+    }
+
+    /// Push more data of the request.
+    ///
+    /// Data following the request, like a pipelined request, is ignored.
+    pub fn push_data(&mut self, mut data: &[u8]) {
+        if let State::Header = self.state {
+            // only buffer what fits, the payload is checked against the buffer with the header
+            let buffered = data.len().min(self.buffer.capacity() - self.buffer.len());
+            self.buffer.extend_from_slice(&data[..buffered]).ok();
+            data = &data[buffered..];
+
+            self.parse_header();
+            if let State::Header = self.state {
+                if self.buffer.len() == self.buffer.capacity() {
+                    self.fail(Error::HeaderTooLarge);
+                }
+            }
+        }
+
+        if let State::Payload(header, len) = self.state {
+            let missing = header + len - self.buffer.len();
+            // fits, as checked after parsing the header
+            self.buffer
+                .extend_from_slice(&data[..data.len().min(missing)])
+                .ok();
+            if self.buffer.len() == header + len {
+                self.state = State::Complete(header, len);
+            }
+        }
+    }
+
+    /// Notify that the connection was closed.
+    pub fn push_close(&mut self) {
+        if let State::Header | State::Payload(..) = self.state {
+            self.fail(Error::IncompleteBody);
+        }
+    }
+
+    /// Check if the request was completely received.
+    pub fn is_complete(&self) -> bool {
+        matches!(self.state, State::Complete(..))
+    }
+
+    /// Get the error, if receiving the request failed.
+    pub fn error(&self) -> Option<Error> {
+        match self.state {
+            State::Failed(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Get the request, once it was completely received.
+    pub fn request(&self) -> Option<IncomingRequest<'_>> {
+        let (header, len) = match self.state {
+            State::Complete(header, len) => (header, len),
+            _ => return None,
+        };
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+        // parsed successfully before
+        request.parse(&self.buffer[..header]).ok()?;
+        let (method, path, version) = (request.method?, request.path?, request.version?);
+        let count = request.headers.len();
+
+        Some(IncomingRequest {
+            method,
+            path,
+            version,
+            headers,
+            count,
+            payload: &self.buffer[header..header + len],
+        })
+    }
+
+    /// Reset the parser, for receiving the next request.
+    pub fn reset(&mut self) {
+        self.buffer = Vec::new();
+        self.state = State::Header;
+    }
+
+    fn fail(&mut self, err: Error) {
+        log::info!("Receiving the request failed: {:?}", err);
+        self.state = State::Failed(err);
+        self.buffer = Vec::new();
+    }
+
+    fn parse_header(&mut self) {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+        let header = match request.parse(&self.buffer) {
+            Ok(Status::Complete(header)) => header,
+            Ok(Status::Partial) => return,
+            Err(err) => {
+                self.fail(err.into());
+                return;
+            }
+        };
+
+        let headers = ResponseHeaders::new(request.headers);
+        if headers.contains(TRANSFER_ENCODING) {
+            log::info!("Request payloads using a transfer encoding are not supported");
+            self.fail(Error::Chunk);
+            return;
+        }
+        let len = match headers.get(CONTENT_LENGTH).map(|len| len.trim().parse()) {
+            Some(Ok(len)) => len,
+            Some(Err(_)) => {
+                self.fail(Error::Parse(httparse::Error::HeaderValue));
+                return;
+            }
+            None => 0,
+        };
+
+        if header + len > self.buffer.capacity() {
+            self.fail(Error::BodyTooLarge);
+            return;
+        }
+
+        // drop anything following the request
+        self.buffer.truncate(self.buffer.len().min(header + len));
+        self.state = State::Payload(header, len);
+    }
+}
+
+impl<N> Inbound for RequestParser<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn push_data(&mut self, data: &[u8]) {
+        RequestParser::push_data(self, data)
+    }
+
+    fn push_close(&mut self) {
+        RequestParser::push_close(self)
+    }
+
+    fn is_complete(&self) -> bool {
+        RequestParser::is_complete(self)
+    }
+
+    fn error(&self) -> Option<Error> {
+        RequestParser::error(self)
+    }
+}
+
+/// A request, received by a [`RequestParser`].
+#[derive(Debug)]
+pub struct IncomingRequest<'a> {
+    pub method: &'a str,
+    /// The path and query.
+    pub path: &'a str,
+    /// The minor version of HTTP/1.
+    pub version: u8,
+    headers: [httparse::Header<'a>; MAX_HEADERS],
+    count: usize,
+    pub payload: &'a [u8],
+}
+
+impl<'a> IncomingRequest<'a> {
+    pub fn headers(&self) -> ResponseHeaders<'_> {
+        ResponseHeaders::new(&self.headers[..self.count])
+    }
+
+    /// Check if the connection should be closed after the response.
+    pub fn is_close(&self) -> bool {
+        match self.headers().get(CONNECTION) {
+            Some(value) => value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("close")),
+            // HTTP/1.0 closes the connection by default
+            None => self.version == 0,
+        }
+    }
+}
+
+/// Sends a response to a sink.
+///
+/// The status line and headers are written directly to the sink. Errors are reported once the
+/// response gets sent.
+pub struct ResponseWriter<'s> {
+    sink: &'s mut dyn Sink,
+    failed: bool,
+}
+
+impl<'s> ResponseWriter<'s> {
+    /// Start the response, with the status code and reason phrase.
+    pub fn new(sink: &'s mut dyn Sink, code: u16, reason: &str) -> Self {
+        let mut writer = ResponseWriter {
+            sink,
+            failed: false,
+        };
+        writer.write(|w| write!(w, "HTTP/1.1 {} {}\r\n", code, reason));
+        writer
+    }
+
+    /// Add a header.
+    pub fn header<'n, H>(mut self, name: H, value: &str) -> Self
+    where
+        H: Into<HeaderName<'n>>,
+    {
+        let name = name.into();
+        self.write(|w| write!(w, "{}: {}\r\n", name, value));
+        self
+    }
+
+    /// Send the response, with the payload.
+    pub fn send(mut self, payload: &[u8]) -> Result<(), ()> {
+        self.write(|w| write!(w, "{}: {}\r\n\r\n", CONTENT_LENGTH, payload.len()));
+        self.finish(|sink| sink.send_all(payload))
+    }
+
+    /// Send the response, formatting the payload directly to the sink.
+    ///
+    /// The function will be called twice, once for evaluating the length of the payload, and
+    /// a second time for sending it. So it must produce the same output both times.
+    pub fn send_fmt<F>(mut self, f: F) -> Result<(), ()>
+    where
+        F: Fn(&mut dyn Write) -> fmt::Result,
+    {
+        let mut counter = Counter(0);
+        f(&mut counter).map_err(|_| ())?;
+        self.write(|w| write!(w, "{}: {}\r\n\r\n", CONTENT_LENGTH, counter.0));
+        self.finish(|sink| f(&mut SinkWrapper(sink)).map_err(|_| ()))
+    }
+
+    fn write<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut dyn Write) -> fmt::Result,
+    {
+        if !self.failed && f(&mut SinkWrapper(self.sink)).is_err() {
+            self.failed = true;
+        }
+    }
+
+    fn finish<F>(self, f: F) -> Result<(), ()>
+    where
+        F: FnOnce(&mut dyn Sink) -> Result<(), ()>,
+    {
+        if self.failed {
+            return Err(());
+        }
+        f(self.sink)?;
+        self.sink.flush()
+    }
+}
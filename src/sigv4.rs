@@ -0,0 +1,336 @@
+//! Signing of requests, using the AWS Signature Version 4.
+//!
+//! The [`SigV4`] interceptor signs all requests of a connection, so that they can be sent to
+//! AWS services, like S3 or the API Gateway, directly. It adds the `X-Amz-Date`,
+//! `X-Amz-Content-Sha256` and `Authorization` headers, which don't fit into the default
+//! buffer of the request builder, so use [`RequestBuilder::buffer_capacity`] to increase it.
+//!
+//! The request must have a `Host` header. The path and query are signed as they are sent, so
+//! they must be percent-encoded already, e.g. by using [`RequestBuilder::query_param`]. The
+//! payload is signed when it is known before sending, otherwise it is marked as unsigned, which
+//! is only supported by S3.
+//!
+//! Signing requires a SHA-256 implementation, which is pluggable using the [`BodyDigest`]
+//! trait, and is provided by the `sha2` feature. The current time is provided by a
+//! [`UnixClock`](crate::UnixClock).
+//!
+//! This module requires the `sigv4` feature.
+//!
+//! ~~~
+//! # #[cfg(feature = "sha2")] {
+//! use heapless::{consts::*, Vec};
+//!
+//! use drogue_http_client::sigv4::*;
+//! use drogue_http_client::*;
+//!
+//! struct Rtc;
+//!
+//! impl UnixClock for Rtc {
+//!     fn unix_time(&self) -> u64 {
+//!         1_700_000_000
+//!     }
+//! }
+//!
+//! let signer = SigV4::<sha2::Sha256, _>::new(
+//!     Credentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY"),
+//!     "eu-west-1",
+//!     "s3",
+//!     Rtc,
+//! );
+//!
+//! let mut sink = Vec::<u8, U1024>::new();
+//! let req = HttpConnection::<U1024>::new()
+//!     .interceptor(signer)
+//!     .begin("PUT", "/my-bucket/state.json")
+//!     .header("Host", "s3.eu-west-1.amazonaws.com")
+//!     .buffer_capacity::<U512>()
+//!     .execute_with(&mut sink, Some(b"{}"));
+//! # }
+//! ~~~
+//!
+//! [`RequestBuilder::buffer_capacity`]: crate::RequestBuilder::buffer_capacity
+//! [`RequestBuilder::query_param`]: crate::RequestBuilder::query_param
+
+use crate::digest::{hmac, write_hex, BodyDigest, DigestWriter};
+use crate::headers::{HeaderName, AUTHORIZATION, HOST};
+use crate::{Interceptor, RequestParts, UnixClock};
+use core::fmt::{self, Write};
+use core::marker::PhantomData;
+use heapless::{consts, String};
+
+pub const X_AMZ_CONTENT_SHA256: HeaderName<'static> = HeaderName::new("X-Amz-Content-Sha256");
+pub const X_AMZ_DATE: HeaderName<'static> = HeaderName::new("X-Amz-Date");
+pub const X_AMZ_SECURITY_TOKEN: HeaderName<'static> = HeaderName::new("X-Amz-Security-Token");
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// The credentials of an AWS identity.
+#[derive(Copy, Clone, Debug)]
+pub struct Credentials<'a> {
+    access_key_id: &'a str,
+    secret_access_key: &'a str,
+    session_token: Option<&'a str>,
+}
+
+impl<'a> Credentials<'a> {
+    /// Create new credentials, from an access key.
+    pub fn new(access_key_id: &'a str, secret_access_key: &'a str) -> Self {
+        Credentials {
+            access_key_id,
+            secret_access_key,
+            session_token: None,
+        }
+    }
+
+    /// Set the token of temporary credentials.
+    pub fn session_token(mut self, session_token: &'a str) -> Self {
+        self.session_token = Some(session_token);
+        self
+    }
+}
+
+/// An interceptor, signing requests using the AWS Signature Version 4.
+///
+/// See the [module documentation](self).
+pub struct SigV4<'a, D, C>
+where
+    D: BodyDigest<Output = [u8; 32]> + Default,
+    C: UnixClock,
+{
+    credentials: Credentials<'a>,
+    region: &'a str,
+    service: &'a str,
+    clock: C,
+    _marker: PhantomData<D>,
+}
+
+impl<'a, D, C> SigV4<'a, D, C>
+where
+    D: BodyDigest<Output = [u8; 32]> + Default,
+    C: UnixClock,
+{
+    /// Create a new instance, signing requests to the service in the region, like `s3` in
+    /// `eu-west-1`.
+    pub fn new(credentials: Credentials<'a>, region: &'a str, service: &'a str, clock: C) -> Self {
+        SigV4 {
+            credentials,
+            region,
+            service,
+            clock,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Replace the credentials, e.g. after refreshing temporary credentials.
+    pub fn set_credentials(&mut self, credentials: Credentials<'a>) {
+        self.credentials = credentials;
+    }
+
+    /// Access the clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Compute the `Authorization` header.
+    fn authorization(
+        &self,
+        request: &RequestParts,
+        time: &Timestamp,
+        payload_hash: &str,
+        authorization: &mut String<consts::U512>,
+    ) -> fmt::Result {
+        let signed_headers = match self.credentials.session_token {
+            Some(_) => "host;x-amz-content-sha256;x-amz-date;x-amz-security-token",
+            None => "host;x-amz-content-sha256;x-amz-date",
+        };
+
+        // the canonical request
+
+        let (path, query) = match request.path().find('?') {
+            Some(i) => (&request.path()[..i], &request.path()[i + 1..]),
+            None => (request.path(), ""),
+        };
+        let host = request.get_header(HOST).unwrap_or_else(|| {
+            log::warn!("Signing a request without a Host header");
+            ""
+        });
+
+        let mut canonical = D::default();
+        let w = &mut DigestWriter(&mut canonical);
+        let path = if path.is_empty() { "/" } else { path };
+        write!(w, "{}\n{}\n", request.method(), path)?;
+        write_canonical_query(w, query)?;
+        write!(w, "\nhost:{}\n", host.trim())?;
+        writeln!(w, "x-amz-content-sha256:{}", payload_hash)?;
+        writeln!(w, "x-amz-date:{}", time.date_time())?;
+        if let Some(token) = self.credentials.session_token {
+            writeln!(w, "x-amz-security-token:{}", token.trim())?;
+        }
+        write!(w, "\n{}\n{}", signed_headers, payload_hash)?;
+        let canonical = canonical.finish();
+
+        // the string to sign
+
+        let mut scope = String::<consts::U128>::new();
+        write!(
+            scope,
+            "{}/{}/{}/aws4_request",
+            time.date(),
+            self.region,
+            self.service
+        )?;
+
+        let mut string_to_sign = String::<consts::U256>::new();
+        write!(
+            string_to_sign,
+            "{}\n{}\n{}\n",
+            ALGORITHM,
+            time.date_time(),
+            scope
+        )?;
+        write_hex(&mut string_to_sign, &canonical)?;
+
+        // the signature
+
+        let mut secret = String::<consts::U128>::new();
+        write!(secret, "AWS4{}", self.credentials.secret_access_key)?;
+        let key = hmac::<D>(secret.as_bytes(), &[time.date().as_bytes()]);
+        let key = hmac::<D>(&key, &[self.region.as_bytes()]);
+        let key = hmac::<D>(&key, &[self.service.as_bytes()]);
+        let key = hmac::<D>(&key, &[b"aws4_request"]);
+        let signature = hmac::<D>(&key, &[string_to_sign.as_bytes()]);
+
+        write!(
+            authorization,
+            "{} Credential={}/{}, SignedHeaders={}, Signature=",
+            ALGORITHM, self.credentials.access_key_id, scope, signed_headers
+        )?;
+        write_hex(authorization, &signature)
+    }
+}
+
+impl<'a, D, C> Interceptor for SigV4<'a, D, C>
+where
+    D: BodyDigest<Output = [u8; 32]> + Default,
+    C: UnixClock,
+{
+    fn before_send(&mut self, request: &mut RequestParts) {
+        let time = Timestamp::new(self.clock.unix_time());
+
+        let mut payload_hash = String::<consts::U64>::new();
+        match request.payload() {
+            Some(parts) => {
+                let mut digest = D::default();
+                for part in parts {
+                    digest.update(part);
+                }
+                // always fits
+                write_hex(&mut payload_hash, &digest.finish()).ok();
+            }
+            None => {
+                payload_hash.push_str(UNSIGNED_PAYLOAD).ok();
+            }
+        }
+
+        let mut authorization = String::new();
+        if self
+            .authorization(request, &time, &payload_hash, &mut authorization)
+            .is_err()
+        {
+            log::error!("Unable to sign the request, exceeds buffer capacity");
+            return;
+        }
+
+        let mut result = request
+            .header(X_AMZ_DATE, time.date_time())
+            .and_then(|_| request.header(X_AMZ_CONTENT_SHA256, &payload_hash));
+        if let Some(token) = self.credentials.session_token {
+            result = result.and_then(|_| request.header(X_AMZ_SECURITY_TOKEN, token));
+        }
+        result = result.and_then(|_| request.header(AUTHORIZATION, &authorization));
+        if result.is_err() {
+            log::warn!("Unable to add the signature, exceeds the buffer of the request");
+        }
+    }
+}
+
+/// Write the query, with the parameters sorted by name and value.
+fn write_canonical_query<W>(w: &mut W, query: &str) -> fmt::Result
+where
+    W: Write + ?Sized,
+{
+    let params = || {
+        query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| match param.find('=') {
+                Some(i) => (&param[..i], &param[i + 1..]),
+                None => (param, ""),
+            })
+    };
+
+    // sort without allocating, by repeatedly looking for the next parameter
+    let mut last = None;
+    let mut first = true;
+    while let Some(next) = params().filter(|param| Some(*param) > last).min() {
+        for (name, value) in params().filter(|param| *param == next) {
+            if !first {
+                w.write_char('&')?;
+            }
+            write!(w, "{}={}", name, value)?;
+            first = false;
+        }
+        last = Some(next);
+    }
+
+    Ok(())
+}
+
+/// The time of signing, in the formats required by the signature.
+struct Timestamp(String<consts::U16>);
+
+impl Timestamp {
+    fn new(unix_time: u64) -> Self {
+        let (year, month, day) = civil_from_days(unix_time / 86400);
+        let secs = unix_time % 86400;
+        let mut s = String::new();
+        // always fits
+        write!(
+            s,
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )
+        .ok();
+        Timestamp(s)
+    }
+
+    /// The date, like `20240131`.
+    fn date(&self) -> &str {
+        &self.0[..8]
+    }
+
+    /// The date and time, like `20240131T235959Z`.
+    fn date_time(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Convert the days since the Unix epoch into year, month, and day.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -3,7 +3,32 @@ use heapless::{ArrayLength, Vec};
 
 /// A sink to send HTTP requests to
 pub trait Sink {
+    /// Send data, returning the number of bytes which have been accepted.
+    ///
+    /// This may be less than the length of the provided data.
     fn send(&mut self, data: &[u8]) -> Result<usize, ()>;
+
+    /// Send all data, retrying partial writes.
+    ///
+    /// Fails if the sink doesn't accept any more data.
+    fn send_all(&mut self, data: &[u8]) -> Result<(), ()> {
+        let mut pos = 0usize;
+        while pos < data.len() {
+            match self.send(&data[pos..])? {
+                0 => return Err(()),
+                len => pos += len,
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush any buffered data.
+    ///
+    /// This gets called after a request was completely written to the sink. The default
+    /// implementation does nothing.
+    fn flush(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
 }
 
 /// A sink implementation for a buffer.
@@ -18,20 +43,170 @@ where
     }
 }
 
-struct SinkWrapper<'a>(&'a mut dyn Sink);
+pub(crate) struct SinkWrapper<'a>(pub(crate) &'a mut dyn Sink);
 
 impl<'a> Write for SinkWrapper<'a> {
     fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
-        let buffer = s.as_bytes();
-        let mut pos = 0usize;
+        self.0.send_all(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
 
-        while pos < buffer.len() {
-            match self.0.send(&buffer[pos..]) {
-                Ok(len) => pos += len,
-                Err(_) => return Err(core::fmt::Error),
-            }
+#[cfg(feature = "ufmt")]
+impl<'a> ufmt::uWrite for SinkWrapper<'a> {
+    type Error = core::fmt::Error;
+
+    fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
+        Write::write_str(self, s)
+    }
+}
+
+/// A writer, sending data using the "chunked" transfer encoding.
+///
+/// Every write will be sent as one chunk, empty writes are ignored.
+pub struct ChunkedWriter<'s> {
+    sink: &'s mut dyn Sink,
+}
+
+impl<'s> ChunkedWriter<'s> {
+    pub(crate) fn new(sink: &'s mut dyn Sink) -> Self {
+        ChunkedWriter { sink }
+    }
+
+    /// Send the terminating, empty chunk.
+    pub(crate) fn finish(self) -> Result<(), ()> {
+        self.sink.send_all(b"0\r\n\r\n")
+    }
+}
+
+impl<'s> Sink for ChunkedWriter<'s> {
+    fn send(&mut self, data: &[u8]) -> Result<usize, ()> {
+        if data.is_empty() {
+            // an empty chunk would terminate the payload
+            return Ok(0);
         }
 
+        write!(SinkWrapper(self.sink), "{:x}\r\n", data.len()).map_err(|_| ())?;
+        self.sink.send_all(data)?;
+        self.sink.send_all(b"\r\n")?;
+
+        Ok(data.len())
+    }
+}
+
+impl<'s> Write for ChunkedWriter<'s> {
+    fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
+        self.send(s.as_bytes()).map_err(|_| core::fmt::Error)?;
         Ok(())
     }
 }
+
+/// A sink, buffering data before sending it to the underlying sink.
+///
+/// This allows to coalesce the many small writes of the request line, headers and small
+/// payloads into a single write on the underlying sink. Data is sent when the buffer is full,
+/// or when the sink gets flushed.
+pub struct BufferedSink<'s, S, N>
+where
+    S: Sink,
+    N: ArrayLength<u8>,
+{
+    sink: &'s mut S,
+    buffer: Vec<u8, N>,
+}
+
+impl<'s, S, N> BufferedSink<'s, S, N>
+where
+    S: Sink,
+    N: ArrayLength<u8>,
+{
+    pub fn new(sink: &'s mut S) -> Self {
+        BufferedSink {
+            sink,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<'s, S, N> Sink for BufferedSink<'s, S, N>
+where
+    S: Sink,
+    N: ArrayLength<u8>,
+{
+    fn send(&mut self, data: &[u8]) -> Result<usize, ()> {
+        if self.buffer.extend_from_slice(data).is_ok() {
+            return Ok(data.len());
+        }
+
+        self.flush()?;
+
+        if self.buffer.extend_from_slice(data).is_ok() {
+            Ok(data.len())
+        } else {
+            // too big for the buffer anyway
+            self.sink.send(data)
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        let result = self.sink.send_all(&self.buffer);
+        self.buffer = Vec::new();
+        result?;
+        self.sink.flush()
+    }
+}
+
+/// The sending half of a request, holding the serialized request until it is sent.
+///
+/// This allows the transmission of the request to be driven independently of receiving the
+/// response, e.g. by different tasks of an RTIC application. See
+/// [`RequestBuilder::split`](crate::RequestBuilder::split).
+pub struct SendHalf<N>
+where
+    N: ArrayLength<u8>,
+{
+    data: Vec<u8, N>,
+    pos: usize,
+}
+
+impl<N> SendHalf<N>
+where
+    N: ArrayLength<u8>,
+{
+    pub(crate) fn new(data: Vec<u8, N>) -> Self {
+        SendHalf { data, pos: 0 }
+    }
+
+    /// The data which still needs to be sent.
+    pub fn pending(&self) -> &[u8] {
+        &self.data[self.pos..]
+    }
+
+    /// Mark data as sent.
+    pub fn advance(&mut self, len: usize) {
+        self.pos = (self.pos + len).min(self.data.len());
+    }
+
+    /// Check if all data was sent.
+    pub fn is_done(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    /// Send as much data as the sink accepts in a single call, without retrying.
+    ///
+    /// Returns the number of bytes sent.
+    pub fn send_to<S>(&mut self, sink: &mut S) -> Result<usize, ()>
+    where
+        S: Sink,
+    {
+        if self.is_done() {
+            return Ok(0);
+        }
+
+        let len = sink.send(self.pending())?;
+        self.advance(len);
+        if self.is_done() {
+            sink.flush()?;
+        }
+        Ok(len)
+    }
+}
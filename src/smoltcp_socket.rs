@@ -0,0 +1,155 @@
+//! [`Sink`] and [`Source`] implementation, using a TCP socket of [`smoltcp`](smoltcp)
+//! directly.
+//!
+//! The socket must be connected by the application. As the socket only makes progress when the
+//! interface gets polled, the adapter calls a function for polling it, while waiting to send or
+//! receive data.
+//!
+//! This module requires the `smoltcp` feature.
+//!
+//! ~~~no_run
+//! # use smoltcp::iface::{Config, Interface, SocketSet};
+//! # use smoltcp::phy::{Loopback, Medium};
+//! # use smoltcp::socket::tcp;
+//! # use smoltcp::time::Instant;
+//! # use smoltcp::wire::EthernetAddress;
+//! # fn now() -> Instant { Instant::from_millis(0) }
+//! use heapless::consts::*;
+//!
+//! use drogue_http_client::smoltcp_socket::SmoltcpSinkSource;
+//! use drogue_http_client::*;
+//!
+//! # let mut device = Loopback::new(Medium::Ethernet);
+//! # let config = Config::new(EthernetAddress([0x02, 0, 0, 0, 0, 1]).into());
+//! # let mut iface = Interface::new(config, &mut device, now());
+//! # let (mut rx, mut tx) = ([0u8; 1024], [0u8; 1024]);
+//! # let socket = tcp::Socket::new(tcp::SocketBuffer::new(&mut rx[..]), tcp::SocketBuffer::new(&mut tx[..]));
+//! # let mut storage: [_; 1] = Default::default();
+//! # let mut sockets = SocketSet::new(&mut storage[..]);
+//! # let handle = sockets.add(socket);
+//! // a connected socket, in the socket set
+//! let mut poll = |sockets: &mut SocketSet| {
+//!     iface.poll(now(), &mut device, sockets);
+//! };
+//! let mut tcp = SmoltcpSinkSource::new(&mut sockets, handle, &mut poll);
+//!
+//! let mut req = HttpConnection::<U1024>::new()
+//!     .begin("GET", "/")
+//!     .handler(BufferResponseHandler::<U1024>::new())
+//!     .execute(&mut tcp);
+//!
+//! tcp.pipe_data(&mut req).unwrap();
+//! ~~~
+
+use crate::{Budget, Inbound, PipeError, Progress, Sink, Source};
+use smoltcp::iface::{SocketHandle, SocketSet};
+use smoltcp::socket::tcp::{Socket, State};
+
+/// Errors of the smoltcp socket.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SocketError {
+    /// The connection was closed, before the response was complete.
+    Closed,
+    /// The socket isn't in a state which allows receiving data.
+    InvalidState,
+}
+
+/// A [`Sink`] and [`Source`], using a TCP socket of a smoltcp socket set.
+pub struct SmoltcpSinkSource<'a, 's> {
+    sockets: &'a mut SocketSet<'s>,
+    handle: SocketHandle,
+    poll: &'a mut dyn FnMut(&mut SocketSet<'s>),
+}
+
+impl<'a, 's> SmoltcpSinkSource<'a, 's> {
+    /// Create a new instance, for the socket of the handle.
+    ///
+    /// The function is called to poll the interface, before each attempt to send or receive
+    /// data.
+    pub fn new(
+        sockets: &'a mut SocketSet<'s>,
+        handle: SocketHandle,
+        poll: &'a mut dyn FnMut(&mut SocketSet<'s>),
+    ) -> Self {
+        SmoltcpSinkSource {
+            sockets,
+            handle,
+            poll,
+        }
+    }
+
+    fn socket(&mut self) -> &mut Socket<'s> {
+        (self.poll)(self.sockets);
+        self.sockets.get_mut::<Socket>(self.handle)
+    }
+}
+
+/// Check if the socket is still establishing the connection.
+fn is_connecting(socket: &Socket) -> bool {
+    matches!(socket.state(), State::SynSent | State::SynReceived)
+}
+
+impl<'a, 's> Source for SmoltcpSinkSource<'a, 's> {
+    type Error = SocketError;
+
+    fn pipe_data_limited<Q>(
+        &mut self,
+        request: &mut Q,
+        budget: Budget,
+    ) -> Result<Progress, PipeError<Self::Error>>
+    where
+        Q: Inbound,
+    {
+        let mut progress = Progress::default();
+        while !request.is_complete() && request.error().is_none() && !budget.is_exhausted(&progress)
+        {
+            let max = budget.remaining_bytes(&progress, usize::MAX);
+            progress.reads += 1;
+
+            let socket = self.socket();
+            if socket.can_recv() {
+                // hand over the data from the receive buffer, without copying it
+                let len = socket
+                    .recv(|data| {
+                        let len = data.len().min(max);
+                        request.push_data(&data[..len]);
+                        (len, len)
+                    })
+                    .map_err(|_| PipeError::Transport(SocketError::InvalidState))?;
+                progress.bytes += len;
+            } else if !socket.may_recv() && !is_connecting(socket) {
+                debug!("Socket closed: {}", socket.state());
+                request.push_close();
+                if !request.is_complete() && request.error().is_none() {
+                    request.transport_failed();
+                    return Err(PipeError::Transport(SocketError::Closed));
+                }
+            } else {
+                progress.would_block += 1;
+                request.redeliver();
+            }
+        }
+        if let Some(err) = request.error() {
+            return Err(err.into());
+        }
+
+        progress.complete = request.is_complete();
+        Ok(progress)
+    }
+}
+
+impl<'a, 's> Sink for SmoltcpSinkSource<'a, 's> {
+    fn send(&mut self, data: &[u8]) -> Result<usize, ()> {
+        let mut pos = 0usize;
+        while pos < data.len() {
+            let socket = self.socket();
+            if socket.can_send() {
+                pos += socket.send_slice(&data[pos..]).map_err(|_| ())?;
+            } else if !socket.may_send() && !is_connecting(socket) {
+                log::info!("Socket closed while sending: {}", socket.state());
+                return Err(());
+            }
+        }
+        Ok(pos)
+    }
+}
@@ -1,14 +1,110 @@
-use crate::{Request, ResponseHandler};
-use heapless::ArrayLength;
+use crate::{Error, PipeError};
+
+/// A limit of work for piping data.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// The maximum number of read attempts.
+    pub reads: Option<usize>,
+    /// The maximum number of bytes to read.
+    pub bytes: Option<usize>,
+}
+
+impl Budget {
+    /// A budget without any limits.
+    pub fn unlimited() -> Self {
+        Default::default()
+    }
+
+    /// Limit the number of read attempts.
+    pub fn max_reads(mut self, reads: usize) -> Self {
+        self.reads = Some(reads);
+        self
+    }
+
+    /// Limit the number of bytes read.
+    pub fn max_bytes(mut self, bytes: usize) -> Self {
+        self.bytes = Some(bytes);
+        self
+    }
+
+    /// Check if the budget is exhausted by the progress made.
+    pub fn is_exhausted(&self, progress: &Progress) -> bool {
+        matches!(self.reads, Some(reads) if progress.reads >= reads)
+            || matches!(self.bytes, Some(bytes) if progress.bytes >= bytes)
+    }
+
+    /// The number of bytes which may still be read, capped at `max`.
+    pub fn remaining_bytes(&self, progress: &Progress, max: usize) -> usize {
+        self.bytes
+            .map_or(max, |bytes| bytes.saturating_sub(progress.bytes).min(max))
+    }
+}
+
+/// The progress made while piping data.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of read attempts.
+    pub reads: usize,
+    /// The number of bytes read.
+    pub bytes: usize,
+    /// The number of read attempts, which would have blocked.
+    pub would_block: usize,
+    /// If the request was completed.
+    pub complete: bool,
+}
+
+/// The receiving end of a request, which a [`Source`] pushes the inbound data to.
+///
+/// This is implemented by the [`Request`](crate::Request) of an
+/// [`HttpConnection`](crate::HttpConnection), and allows the same transports to be used for
+/// other protocols.
+pub trait Inbound {
+    /// Push more inbound data.
+    fn push_data(&mut self, data: &[u8]);
+
+    /// Notify that the source has closed.
+    fn push_close(&mut self);
+
+    /// Retry delivering payload, which the handler did not consume yet.
+    fn redeliver(&mut self) {}
+
+    /// The number of payload bytes, which are buffered as the handler did not consume them yet.
+    fn pending_payload(&self) -> usize {
+        0
+    }
+
+    /// Check if the request is completely processed.
+    fn is_complete(&self) -> bool;
+
+    /// Get the error, if processing the response failed.
+    fn error(&self) -> Option<Error>;
+
+    /// Record a failure of the transport, when reading data.
+    fn transport_failed(&mut self) {}
+}
 
 /// A source of data for the HTTP response
 pub trait Source {
     type Error;
 
     /// This will block, and forward data from this source to the request, until the request
-    /// is completed or a read error occurred.
-    fn pipe_data<IN, R>(&mut self, request: &mut Request<IN, R>) -> Result<(), Self::Error>
+    /// is completed, a read error occurred, or processing the response failed.
+    ///
+    /// Returns the statistics of the reads, which e.g. allow judging the quality of the link.
+    fn pipe_data<Q>(&mut self, request: &mut Q) -> Result<Progress, PipeError<Self::Error>>
+    where
+        Q: Inbound,
+    {
+        self.pipe_data_limited(request, Budget::unlimited())
+    }
+
+    /// Forward data from this source to the request, until the request is completed, a read
+    /// error occurred, processing the response failed, or the budget is exhausted.
+    fn pipe_data_limited<Q>(
+        &mut self,
+        request: &mut Q,
+        budget: Budget,
+    ) -> Result<Progress, PipeError<Self::Error>>
     where
-        IN: ArrayLength<u8>,
-        R: ResponseHandler;
+        Q: Inbound;
 }
@@ -1,58 +1,318 @@
 //! TCP stack implementation of [`Sink`] and [`Source`].
 
-use crate::{Request, ResponseHandler, Sink, Source};
+use crate::{Budget, Clock, Inbound, PipeError, Progress, Sink, Source};
+use core::marker::PhantomData;
 use core::str::from_utf8;
-use drogue_network::tcp::TcpStack;
-use heapless::ArrayLength;
+use drogue_network::tcp::{Mode, TcpStack};
+#[cfg(feature = "delay")]
+use embedded_hal::blocking::delay::DelayUs;
+use heapless::{consts, ArrayLength, Vec};
 
-pub struct TcpSocketSinkSource<'tcp, T>
+/// A [`Sink`] and [`Source`], using a socket of a TCP stack.
+///
+/// Reading uses a buffer of `N` bytes, on the stack. Alternatively, a buffer can be provided
+/// using [`TcpSocketSinkSource::with_buffer`].
+///
+/// How reads without data are interpreted depends on the [`Mode`] the socket was opened with,
+/// which is set using [`TcpSocketSinkSource::mode`].
+pub struct TcpSocketSinkSource<'tcp, T, N = consts::U512>
 where
     T: TcpStack,
+    N: ArrayLength<u8>,
 {
-    stack: &'tcp mut T,
+    stack: &'tcp T,
     socket: &'tcp mut T::TcpSocket,
+    mode: Mode,
+    max_would_block: Option<usize>,
+    read_timeout: Option<(&'tcp dyn Clock, u64)>,
+    on_would_block: Option<&'tcp mut dyn FnMut()>,
+    #[cfg(feature = "delay")]
+    delay: Option<(&'tcp mut dyn DelayUs<u32>, u32)>,
+    buffer: Option<&'tcp mut [u8]>,
+    _buffer: PhantomData<N>,
 }
 
 impl<'tcp, T> TcpSocketSinkSource<'tcp, T>
 where
     T: TcpStack,
 {
-    pub fn from(stack: &'tcp mut T, socket: &'tcp mut T::TcpSocket) -> Self {
-        TcpSocketSinkSource { stack, socket }
+    /// Create a new instance, with the default buffer size of 512 bytes.
+    pub fn from(stack: &'tcp T, socket: &'tcp mut T::TcpSocket) -> Self {
+        Self::new(stack, socket)
+    }
+
+    /// Create a new instance, reading into the provided buffer.
+    ///
+    /// This avoids using a buffer on the stack, and allows placing the buffer in a specific
+    /// region of memory.
+    ///
+    /// # Panics
+    ///
+    /// If the buffer is empty, as no data could be read into it.
+    ///
+    /// ~~~
+    /// use drogue_http_client::tcp::TcpSocketSinkSource;
+    ///
+    /// # use drogue_http_client::mock;
+    /// let (network, mut socket) = mock::mock_connection();
+    /// let mut buffer = [0u8; 1024];
+    /// let tcp = TcpSocketSinkSource::with_buffer(&network, &mut socket, &mut buffer);
+    /// ~~~
+    pub fn with_buffer(
+        stack: &'tcp T,
+        socket: &'tcp mut T::TcpSocket,
+        buffer: &'tcp mut [u8],
+    ) -> TcpSocketSinkSource<'tcp, T, consts::U0> {
+        assert!(!buffer.is_empty(), "The read buffer must not be empty");
+        let mut tcp = TcpSocketSinkSource::new(stack, socket);
+        tcp.buffer = Some(buffer);
+        tcp
     }
 }
 
-impl<'tcp, T> Source for TcpSocketSinkSource<'tcp, T>
+impl<'tcp, T, N> TcpSocketSinkSource<'tcp, T, N>
 where
     T: TcpStack,
+    N: ArrayLength<u8>,
 {
-    type Error = T::Error;
+    /// Create a new instance, with a buffer size of `N`.
+    ///
+    /// ~~~
+    /// use heapless::consts::*;
+    /// use drogue_http_client::tcp::TcpSocketSinkSource;
+    ///
+    /// # use drogue_http_client::mock;
+    /// let (network, mut socket) = mock::mock_connection();
+    /// let tcp = TcpSocketSinkSource::<_, U128>::new(&network, &mut socket);
+    /// ~~~
+    pub fn new(stack: &'tcp T, socket: &'tcp mut T::TcpSocket) -> Self {
+        TcpSocketSinkSource {
+            stack,
+            socket,
+            mode: Mode::NonBlocking,
+            max_would_block: None,
+            read_timeout: None,
+            on_would_block: None,
+            #[cfg(feature = "delay")]
+            delay: None,
+            buffer: None,
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Set the mode the socket was opened with, defaults to [`Mode::NonBlocking`].
+    ///
+    /// A blocking socket only returns from reading without data once the connection was closed
+    /// by the server, which completes responses delimited by the end of the connection. With the
+    /// other modes, a read without data means that no data is available yet, it is handled like
+    /// a read which would block.
+    ///
+    /// ~~~
+    /// use drogue_network::tcp::Mode;
+    /// use drogue_http_client::tcp::TcpSocketSinkSource;
+    ///
+    /// # use drogue_http_client::mock;
+    /// // opened using `Mode::Timeout(100)`
+    /// let (network, mut socket) = mock::mock_connection();
+    /// let tcp = TcpSocketSinkSource::from(&network, &mut socket)
+    ///     .mode(Mode::Timeout(100))
+    ///     // give up after two seconds without data
+    ///     .max_would_block(20);
+    /// ~~~
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Fail with [`PipeError::Timeout`], when reading would block more often than this in a
+    /// row.
+    ///
+    /// With [`Mode::Timeout`], each of these reads waited for the timeout of the socket. Writes
+    /// are limited the same way, failing the send.
+    pub fn max_would_block(mut self, max_would_block: usize) -> Self {
+        self.max_would_block = Some(max_would_block);
+        self
+    }
 
-    fn pipe_data<IN, R>(&mut self, request: &mut Request<IN, R>) -> Result<(), Self::Error>
+    /// Fail with [`PipeError::Timeout`], when no data was received for the time, in
+    /// milliseconds of the clock.
+    ///
+    /// Sending fails as well, when no data could be written for the time.
+    pub fn read_timeout(mut self, clock: &'tcp dyn Clock, timeout: u64) -> Self {
+        self.read_timeout = Some((clock, timeout));
+        self
+    }
+
+    /// Call the function each time reading or writing would block.
+    ///
+    /// This allows doing something useful while waiting, like feeding a watchdog, or entering a
+    /// low power mode until the next interrupt, instead of spinning.
+    pub fn on_would_block(mut self, f: &'tcp mut dyn FnMut()) -> Self {
+        self.on_would_block = Some(f);
+        self
+    }
+
+    /// Sleep for the interval, in microseconds, each time reading or writing would block.
+    ///
+    /// This only applies to non-blocking sockets, as the other modes already wait for data. It
+    /// is done after calling the function set using [`TcpSocketSinkSource::on_would_block`].
+    ///
+    /// This requires the `delay` feature.
+    ///
+    /// ~~~
+    /// # use drogue_http_client::mock;
+    /// # struct Delay;
+    /// # impl embedded_hal::blocking::delay::DelayUs<u32> for Delay {
+    /// #     fn delay_us(&mut self, _: u32) {}
+    /// # }
+    /// # let mut delay = Delay;
+    /// use drogue_http_client::tcp::TcpSocketSinkSource;
+    ///
+    /// let (network, mut socket) = mock::mock_connection();
+    /// let tcp = TcpSocketSinkSource::from(&network, &mut socket)
+    ///     .delay(&mut delay, 10_000)
+    ///     // give up after a second without data
+    ///     .max_would_block(100);
+    /// ~~~
+    #[cfg(feature = "delay")]
+    pub fn delay(mut self, delay: &'tcp mut dyn DelayUs<u32>, interval: u32) -> Self {
+        self.delay = Some((delay, interval));
+        self
+    }
+
+    fn would_block(&mut self) {
+        if let Some(f) = &mut self.on_would_block {
+            f();
+        }
+        #[cfg(feature = "delay")]
+        if let (Some((delay, interval)), Mode::NonBlocking) = (&mut self.delay, &self.mode) {
+            delay.delay_us(*interval);
+        }
+    }
+
+    /// Check if waiting for the socket timed out, after the attempts which would block in a
+    /// row, and the time of the last progress.
+    fn is_timed_out(&self, idle: usize, last: Option<u64>) -> bool {
+        matches!(self.max_would_block, Some(max) if idle > max)
+            || matches!((self.read_timeout, last), (Some((clock, timeout)), Some(last))
+                if clock.now().saturating_sub(last) >= timeout)
+    }
+
+    fn pipe<Q>(
+        &mut self,
+        request: &mut Q,
+        budget: Budget,
+        buffer: &mut [u8],
+    ) -> Result<Progress, PipeError<T::Error>>
     where
-        IN: ArrayLength<u8>,
-        R: ResponseHandler,
+        Q: Inbound,
     {
-        let mut buffer = [0u8; 512];
-        while !request.is_complete() {
-            match self.stack.read(self.socket, &mut buffer) {
-                Ok(len) => {
+        // reads of zero bytes never make progress, e.g. with a buffer size of `U0`
+        debug_assert!(!buffer.is_empty(), "The read buffer must not be empty");
+        let mut progress = Progress::default();
+        // consecutive reads which would block, and the time of the last data
+        let mut idle = 0usize;
+        let mut last_data = self.read_timeout.map(|(clock, _)| clock.now());
+        while !request.is_complete() && request.error().is_none() && !budget.is_exhausted(&progress)
+        {
+            let len = budget.remaining_bytes(&progress, buffer.len());
+            progress.reads += 1;
+            match self.stack.read(self.socket, &mut buffer[0..len]) {
+                Ok(0) if matches!(self.mode, Mode::Blocking) => {
+                    log::info!("Connection closed by the server");
+                    request.push_close();
+                }
+                Ok(len) if len > 0 => {
+                    progress.bytes += len;
                     request.push_data(&buffer[0..len]);
+                    idle = 0;
+                    last_data = self.read_timeout.map(|(clock, _)| clock.now());
                 }
-                Err(nb::Error::WouldBlock) => {}
-                Err(nb::Error::Other(e)) => return Err(e),
+                Ok(_) | Err(nb::Error::WouldBlock) => {
+                    request.redeliver();
+                    self.would_block();
+                    progress.would_block += 1;
+                    idle += 1;
+                    if self.is_timed_out(idle, last_data) {
+                        log::info!("Read timed out, after {} attempts", idle);
+                        return Err(PipeError::Timeout);
+                    }
+                }
+                Err(nb::Error::Other(e)) => {
+                    request.transport_failed();
+                    return Err(PipeError::Transport(e));
+                }
+            }
+        }
+        if let Some(err) = request.error() {
+            return Err(err.into());
+        }
+
+        progress.complete = request.is_complete();
+        Ok(progress)
+    }
+}
+
+impl<'tcp, T, N> Source for TcpSocketSinkSource<'tcp, T, N>
+where
+    T: TcpStack,
+    N: ArrayLength<u8>,
+{
+    type Error = T::Error;
+
+    fn pipe_data_limited<Q>(
+        &mut self,
+        request: &mut Q,
+        budget: Budget,
+    ) -> Result<Progress, PipeError<Self::Error>>
+    where
+        Q: Inbound,
+    {
+        match self.buffer.take() {
+            Some(buffer) => {
+                let result = self.pipe(request, budget, buffer);
+                self.buffer = Some(buffer);
+                result
+            }
+            None => {
+                let mut buffer = Vec::<u8, N>::new();
+                buffer.resize_default(N::to_usize()).ok();
+                self.pipe(request, budget, &mut buffer)
             }
         }
-        Ok(())
     }
 }
 
-impl<'tcp, T> Sink for TcpSocketSinkSource<'tcp, T>
+impl<'tcp, T, N> Sink for TcpSocketSinkSource<'tcp, T, N>
 where
     T: TcpStack,
+    N: ArrayLength<u8>,
 {
     fn send(&mut self, data: &[u8]) -> Result<usize, ()> {
         log::info!("Sending: {:?}", from_utf8(data));
-        self.stack.write(self.socket, data).map_err(|_| ())
+        let mut pos = 0usize;
+        // consecutive writes which would block, and the time of the last progress
+        let mut idle = 0usize;
+        let mut last_write = self.read_timeout.map(|(clock, _)| clock.now());
+        while pos < data.len() {
+            match self.stack.write(self.socket, &data[pos..]) {
+                Ok(0) => break,
+                Ok(len) => {
+                    pos += len;
+                    idle = 0;
+                    last_write = self.read_timeout.map(|(clock, _)| clock.now());
+                }
+                Err(nb::Error::WouldBlock) => {
+                    self.would_block();
+                    idle += 1;
+                    if self.is_timed_out(idle, last_write) {
+                        log::info!("Write timed out, after {} attempts", idle);
+                        return Err(());
+                    }
+                }
+                Err(nb::Error::Other(_)) => return Err(()),
+            }
+        }
+        Ok(pos)
     }
 }
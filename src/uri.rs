@@ -0,0 +1,337 @@
+//! URI helpers.
+//!
+//! Parsing URLs, and resolving relative references, like the value of a `Location` header, against the target of
+//! the original request, following [RFC 3986, section 5.2](https://tools.ietf.org/html/rfc3986#section-5.2).
+//!
+//! ~~~
+//! use drogue_http_client::uri::resolve;
+//! use heapless::{consts::*, String};
+//!
+//! let target: String<U128> = resolve("/devices/1/events?since=1", "../2/state").unwrap();
+//! assert_eq!(target, "/devices/2/state");
+//! ~~~
+
+use core::fmt;
+use heapless::{ArrayLength, String};
+
+/// An `http` or `https` URL.
+///
+/// ~~~
+/// use drogue_http_client::uri::Url;
+///
+/// let url = Url::parse("https://example.com/devices?id=1").unwrap();
+/// assert!(url.is_secure());
+/// assert_eq!(url.host(), "example.com");
+/// assert_eq!(url.port(), 443);
+/// assert_eq!(url.path(), "/devices?id=1");
+/// ~~~
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Url<'a> {
+    secure: bool,
+    authority: &'a str,
+    host: &'a str,
+    port: Option<u16>,
+    // the path and query, without the `/` of an empty path
+    path: &'a str,
+}
+
+impl<'a> Url<'a> {
+    /// Parse an absolute URL, with the scheme `http` or `https`.
+    ///
+    /// A fragment is dropped, as it is never sent to the server.
+    pub fn parse(url: &'a str) -> Result<Self, ()> {
+        let url = split_off(url, '#').0;
+        let parts = Parts::parse(url);
+
+        let secure = match parts.scheme {
+            Some(scheme) if scheme.eq_ignore_ascii_case("http") => false,
+            Some(scheme) if scheme.eq_ignore_ascii_case("https") => true,
+            _ => return Err(()),
+        };
+
+        // drop the user info
+        let authority = parts.authority.ok_or(())?;
+        let authority = authority.rsplit('@').next().unwrap_or(authority);
+
+        let (host, port) = match authority.rfind(':') {
+            // an empty port is the default one
+            Some(i) if authority.len() == i + 1 => (&authority[..i], None),
+            Some(i) if !authority[i..].contains(']') => {
+                let port = authority[i + 1..].parse().map_err(|_| ())?;
+                (&authority[..i], Some(port))
+            }
+            _ => (authority, None),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(());
+        }
+
+        // the path and query, as sent in the request line
+        let start = url.len() - parts.query.map_or(0, |q| q.len() + 1) - parts.path.len();
+        let path = &url[start..];
+
+        Ok(Url {
+            secure,
+            authority,
+            host,
+            port,
+            path,
+        })
+    }
+
+    /// Check if the scheme is `https`.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// The host, without the brackets of IPv6 addresses.
+    pub fn host(&self) -> &'a str {
+        self.host
+    }
+
+    /// The port, or the default port of the scheme.
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(if self.secure { 443 } else { 80 })
+    }
+
+    /// The value for the `Host` header, including the port if one was given.
+    pub fn authority(&self) -> &'a str {
+        self.authority
+    }
+
+    /// The path and query, to be used as request target.
+    pub fn path(&self) -> Target<'a> {
+        Target(self.path)
+    }
+}
+
+/// The request target of a [`Url`], its path and query.
+///
+/// An empty path is sent as `/`, which needs formatting if the URL has a query, like
+/// `http://example.com?id=1`.
+///
+/// ~~~
+/// use drogue_http_client::uri::Url;
+///
+/// let target = Url::parse("http://example.com?id=1").unwrap().path();
+/// assert_eq!(target, "/?id=1");
+/// assert_eq!(target.as_str(), None);
+///
+/// let target = Url::parse("http://example.com/devices?id=1").unwrap().path();
+/// assert_eq!(target.as_str(), Some("/devices?id=1"));
+/// ~~~
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Target<'a>(&'a str);
+
+impl<'a> Target<'a> {
+    /// The target, `None` if a `/` must be prefixed to the query, using [`fmt::Display`].
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self.0 {
+            "" => Some("/"),
+            target if target.starts_with('/') => Some(target),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Target<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.0.starts_with('/') {
+            f.write_str("/")?;
+        }
+        f.write_str(self.0)
+    }
+}
+
+impl PartialEq<&str> for Target<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        match self.as_str() {
+            Some(target) => target == *other,
+            None => other.strip_prefix('/') == Some(self.0),
+        }
+    }
+}
+
+/// The components of a URI reference.
+#[derive(Copy, Clone, Debug)]
+struct Parts<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+impl<'a> Parts<'a> {
+    fn parse(uri: &'a str) -> Self {
+        let (rest, fragment) = split_off(uri, '#');
+        let (rest, query) = split_off(rest, '?');
+
+        let (scheme, rest) = match rest.find(':') {
+            Some(i) if is_scheme(&rest[..i]) => (Some(&rest[..i]), &rest[i + 1..]),
+            _ => (None, rest),
+        };
+
+        let (authority, path) = match rest.strip_prefix("//") {
+            Some(rest) => {
+                let i = rest.find('/').unwrap_or(rest.len());
+                (Some(&rest[..i]), &rest[i..])
+            }
+            None => (None, rest),
+        };
+
+        Parts {
+            scheme,
+            authority,
+            path,
+            query,
+            fragment,
+        }
+    }
+}
+
+fn split_off(s: &str, c: char) -> (&str, Option<&str>) {
+    match s.find(c) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    }
+}
+
+fn is_scheme(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+/// Resolve a URI reference against a base URI.
+///
+/// The base may be an absolute URI, or the path and query of the original request. Fails if
+/// the result doesn't fit into a string of capacity `N`.
+pub fn resolve<N>(base: &str, reference: &str) -> Result<String<N>, ()>
+where
+    N: ArrayLength<u8>,
+{
+    let base = Parts::parse(base);
+    let r = Parts::parse(reference);
+
+    let mut path = String::<N>::new();
+
+    let target = if r.scheme.is_some() {
+        remove_dot_segments(r.path, &mut path)?;
+        Parts { path: "", ..r }
+    } else if r.authority.is_some() {
+        remove_dot_segments(r.path, &mut path)?;
+        Parts {
+            scheme: base.scheme,
+            path: "",
+            ..r
+        }
+    } else if r.path.is_empty() {
+        path.push_str(base.path)?;
+        Parts {
+            scheme: base.scheme,
+            authority: base.authority,
+            path: "",
+            query: r.query.or(base.query),
+            fragment: r.fragment,
+        }
+    } else {
+        if r.path.starts_with('/') {
+            remove_dot_segments(r.path, &mut path)?;
+        } else {
+            let merged = merge::<N>(&base, r.path)?;
+            remove_dot_segments(&merged, &mut path)?;
+        }
+        Parts {
+            scheme: base.scheme,
+            authority: base.authority,
+            path: "",
+            query: r.query,
+            fragment: r.fragment,
+        }
+    };
+
+    let mut result = String::new();
+    if let Some(scheme) = target.scheme {
+        result.push_str(scheme)?;
+        result.push(':')?;
+    }
+    if let Some(authority) = target.authority {
+        result.push_str("//")?;
+        result.push_str(authority)?;
+    }
+    result.push_str(&path)?;
+    if let Some(query) = target.query {
+        result.push('?')?;
+        result.push_str(query)?;
+    }
+    if let Some(fragment) = target.fragment {
+        result.push('#')?;
+        result.push_str(fragment)?;
+    }
+
+    Ok(result)
+}
+
+/// Merge a relative path with the path of the base.
+fn merge<N>(base: &Parts, path: &str) -> Result<String<N>, ()>
+where
+    N: ArrayLength<u8>,
+{
+    let mut merged = String::new();
+    if base.authority.is_some() && base.path.is_empty() {
+        merged.push('/')?;
+    } else if let Some(i) = base.path.rfind('/') {
+        merged.push_str(&base.path[..=i])?;
+    }
+    merged.push_str(path)?;
+    Ok(merged)
+}
+
+/// Remove the `.` and `..` segments of the path, appending the result to the output.
+fn remove_dot_segments<N>(mut input: &str, output: &mut String<N>) -> Result<(), ()>
+where
+    N: ArrayLength<u8>,
+{
+    while !input.is_empty() {
+        if let Some(rest) = input
+            .strip_prefix("../")
+            .or_else(|| input.strip_prefix("./"))
+        {
+            input = rest;
+        } else if input.starts_with("/./") {
+            input = &input[2..];
+        } else if input == "/." {
+            input = "/";
+        } else if input.starts_with("/../") {
+            input = &input[3..];
+            remove_last_segment(output)?;
+        } else if input == "/.." {
+            input = "/";
+            remove_last_segment(output)?;
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            let start = if input.starts_with('/') { 1 } else { 0 };
+            let end = input[start..].find('/').map_or(input.len(), |i| i + start);
+            output.push_str(&input[..end])?;
+            input = &input[end..];
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_last_segment<N>(output: &mut String<N>) -> Result<(), ()>
+where
+    N: ArrayLength<u8>,
+{
+    // rebuild the string, as truncating a heapless 0.5 vector trips the UB checks of recent
+    // Rust versions
+    let end = output.rfind('/').unwrap_or(0);
+    let mut truncated = String::new();
+    truncated.push_str(&output[..end])?;
+    *output = truncated;
+    Ok(())
+}